@@ -0,0 +1,111 @@
+//! Throughput and latency of the queue implementations,
+//! with crossbeam's `ArrayQueue` and a mutex-protected `LockedQueue` as the baselines.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::{hint::black_box, thread, time::Instant};
+use synqueue::{AxelQueue, DoubleQueue, LockedQueue, MaskedQueue, NewQueue, SynQueue};
+
+const CAPACITY: usize = 1 << 10;
+const ELEMENTS: usize = 1 << 14;
+const THREAD_COUNTS: &[(usize, usize)] = &[(1, 1), (1, 4), (4, 1), (4, 4)];
+
+/// Wrapper implementing the queue traits for crossbeam.
+struct Crossbeam<T>(crossbeam_queue::ArrayQueue<T>);
+
+impl<T: Send> SynQueue<T> for Crossbeam<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.0.push(value)
+    }
+    fn pop(&self) -> Option<T> {
+        self.0.pop()
+    }
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    fn is_full(&self) -> bool {
+        self.0.is_full()
+    }
+    fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+impl<T: Send> NewQueue<T> for Crossbeam<T> {
+    fn new(capacity: usize) -> Self {
+        Self(crossbeam_queue::ArrayQueue::new(capacity))
+    }
+}
+
+/// Move `ELEMENTS` through the queue, split evenly across the threads.
+fn transfer<T: Send + Default, Q: SynQueue<T>>(queue: &Q, producers: usize, consumers: usize) {
+    thread::scope(|scope| {
+        for _ in 0..producers {
+            scope.spawn(|| {
+                for _ in 0..ELEMENTS / producers {
+                    let mut value = T::default();
+                    while let Err(v) = queue.push(value) {
+                        value = v;
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+        for _ in 0..consumers {
+            scope.spawn(|| {
+                for _ in 0..ELEMENTS / consumers {
+                    let value = loop {
+                        match queue.pop() {
+                            Some(value) => break value,
+                            None => thread::yield_now(),
+                        }
+                    };
+                    black_box(value);
+                }
+            });
+        }
+    });
+}
+
+fn bench_queue<T: Send + Default, Q: NewQueue<T>>(c: &mut Criterion, name: &str, element: &str) {
+    let mut group = c.benchmark_group(format!("throughput/{}", element));
+    group.throughput(Throughput::Elements(ELEMENTS as u64));
+    for &(producers, consumers) in THREAD_COUNTS {
+        let id = BenchmarkId::new(name, format!("{}p{}c", producers, consumers));
+        group.bench_function(id, |b| {
+            let queue = Q::new(CAPACITY);
+            b.iter(|| transfer(&queue, producers, consumers));
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("latency/{}", element));
+    group.bench_function(name, |b| {
+        let queue = Q::new(CAPACITY);
+        b.iter_custom(|iterations| {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                let _ = queue.push(T::default());
+                black_box(queue.pop());
+            }
+            start.elapsed()
+        });
+    });
+    group.finish();
+}
+
+fn bench_element<T: Send + Default + 'static>(c: &mut Criterion, element: &str) {
+    bench_queue::<T, AxelQueue<T>>(c, "axel", element);
+    bench_queue::<T, DoubleQueue<T>>(c, "double", element);
+    bench_queue::<T, MaskedQueue<T>>(c, "masked", element);
+    bench_queue::<T, Crossbeam<T>>(c, "crossbeam", element);
+    bench_queue::<T, LockedQueue<T>>(c, "locked", element);
+}
+
+fn queues(c: &mut Criterion) {
+    bench_element::<u64>(c, "u64");
+    bench_element::<[u64; 8]>(c, "64B");
+    bench_element::<[u64; 32]>(c, "256B");
+}
+
+criterion_group!(benches, queues);
+criterion_main!(benches);
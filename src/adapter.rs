@@ -0,0 +1,123 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+
+/// Consumer-side adapter transforming the values on pop.
+///
+/// It can't implement `SynQueue` over the new type, since the values can't be
+/// mapped back on push, so the producers are expected to push via `inner`.
+pub struct Mapped<Q, F> {
+    queue: Q,
+    map: F,
+}
+
+impl<Q, F> Mapped<Q, F> {
+    pub fn new(queue: Q, map: F) -> Self {
+        Self { queue, map }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    pub fn pop<T, U>(&self) -> Option<U>
+    where
+        Q: super::SynQueue<T>,
+        F: Fn(T) -> U,
+    {
+        self.queue.pop().map(&self.map)
+    }
+
+    pub fn is_empty<T>(&self) -> bool
+    where
+        Q: super::SynQueue<T>,
+    {
+        self.queue.is_empty()
+    }
+}
+
+/// Queue adapter discarding the values that don't pass the predicate on pop.
+///
+/// Filtering on pop, as opposed to push, allows the predicate to depend on
+/// the consumer state at the time of processing, e.g. a cancellation flag.
+pub struct Filtered<Q, F> {
+    queue: Q,
+    predicate: F,
+    discarded: AtomicUsize,
+}
+
+impl<Q, F> Filtered<Q, F> {
+    pub fn new(queue: Q, predicate: F) -> Self {
+        Self {
+            queue,
+            predicate,
+            discarded: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    /// Number of values that didn't pass the predicate.
+    pub fn discarded(&self) -> usize {
+        self.discarded.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, Q, F> super::SynQueue<T> for Filtered<Q, F>
+where
+    Q: super::SynQueue<T>,
+    F: Fn(&T) -> bool + Send + Sync,
+{
+    fn push(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        loop {
+            let value = self.queue.pop()?;
+            if (self.predicate)(&value) {
+                return Some(value);
+            }
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn mapped() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let mq = Mapped::new(super::DoubleQueue::new(4), |v: i32| v.to_string());
+        mq.inner().push(5).unwrap();
+        assert!(!mq.is_empty());
+        assert_eq!(mq.pop(), Some("5".to_string()));
+        assert_eq!(mq.pop(), None);
+    })
+}
+
+#[test]
+fn filtered() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let fq = Filtered::new(super::DoubleQueue::new(4), |v: &i32| v % 2 == 0);
+        for i in 0..4 {
+            fq.push(i).unwrap();
+        }
+        assert_eq!(fq.pop(), Some(0));
+        assert_eq!(fq.pop(), Some(2));
+        assert_eq!(fq.pop(), None);
+        assert_eq!(fq.discarded(), 2);
+    })
+}
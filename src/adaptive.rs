@@ -0,0 +1,226 @@
+use super::qstd::sync::{atomic::AtomicUsize, Mutex};
+use super::{DoubleQueue, ShardedQueue, SynQueue};
+
+/// The sharded lane is active.
+const SHARDED: usize = 1;
+/// A migration is holding back the new operations.
+const MIGRATING: usize = 2;
+/// Unit of the count of operations in flight.
+const IN_FLIGHT: usize = 1 << 2;
+const IN_FLIGHT_MASK: usize = 0xFFFF << 2;
+/// Unit of the count of finished operations, which wraps around.
+const FINISHED: usize = 1 << 18;
+/// Number of finished operations between the evaluations of the contention.
+const WINDOW: usize = 1 << 10;
+
+/// Experimental queue that migrates between a single lane and a sharded one,
+/// depending on the contention.
+///
+/// ## Principle
+/// Every `WINDOW` operations, the failed CAS attempts of the active lane,
+/// as counted with the `stats` feature, are compared against the thresholds.
+/// At `shard_above` percent of the operations or more, the elements are moved
+/// into a `ShardedQueue`, and below `unshard_below` percent, back into a `DoubleQueue`.
+///
+/// The elements are only moved at a quiescent point: the migration holds back
+/// the new operations, and waits for the ones in flight to finish. Every operation
+/// enters and leaves the same atomic state for this, which is the price of the experiment.
+///
+/// ## Ordering
+/// While the sharded lane is active, the order is relaxed like in `ShardedQueue`.
+/// Both lanes are allocated upfront, with the same capacity.
+pub struct AdaptiveQueue<T> {
+    state: AtomicUsize,
+    single: DoubleQueue<T>,
+    sharded: ShardedQueue<T>,
+    /// Failed CAS attempts of the active lane at the last evaluation.
+    last_failed_cas: Mutex<u64>,
+    shard_above: u64,
+    unshard_below: u64,
+    wait: super::WaitStrategy,
+}
+
+impl<T: Send> AdaptiveQueue<T> {
+    /// Set the percentages of failed CAS attempts per operation,
+    /// at which the queue migrates to the sharded lane and back.
+    pub fn with_thresholds(mut self, shard_above: u64, unshard_below: u64) -> Self {
+        self.shard_above = shard_above;
+        self.unshard_below = unshard_below;
+        self
+    }
+
+    /// Check if the elements are in the sharded lane.
+    pub fn is_sharded(&self) -> bool {
+        self.state.load(super::LOAD_ORDER) & SHARDED != 0
+    }
+
+    fn lane(&self, state: usize) -> &dyn SynQueue<T> {
+        if state & SHARDED != 0 {
+            &self.sharded
+        } else {
+            &self.single
+        }
+    }
+
+    fn failed_cas(&self, state: usize) -> u64 {
+        if state & SHARDED != 0 {
+            let shards = self.sharded.shards().iter();
+            shards.map(|shard| shard.stats().failed_cas).sum()
+        } else {
+            self.single.stats().failed_cas
+        }
+    }
+
+    /// Run the operation on the active lane, waiting for a migration to finish.
+    fn operate<R>(&self, op: impl FnOnce(&dyn SynQueue<T>) -> R) -> R {
+        let mut state = self.state.load(super::LOAD_ORDER);
+        let mut step = 0;
+        loop {
+            if state & MIGRATING != 0 {
+                self.wait.wait(step);
+                step = step.saturating_add(1);
+                state = self.state.load(super::LOAD_ORDER);
+                continue;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + IN_FLIGHT,
+                super::CAS_ORDER,
+                super::LOAD_ORDER,
+            ) {
+                Ok(_) => break,
+                Err(other) => state = other,
+            }
+        }
+
+        let result = op(self.lane(state));
+
+        // leave and count the finished operation in the same RMW
+        let prev = self
+            .state
+            .fetch_add(FINISHED.wrapping_sub(IN_FLIGHT), super::CAS_ORDER);
+        if (prev / FINISHED + 1).is_multiple_of(WINDOW) {
+            self.evaluate();
+        }
+        result
+    }
+
+    /// Compare the contention in the last window against the thresholds.
+    fn evaluate(&self) {
+        // another thread is evaluating or migrating
+        let Ok(mut last_failed_cas) = self.last_failed_cas.try_lock() else {
+            return;
+        };
+        let state = self.state.load(super::LOAD_ORDER);
+        let failed_cas = self.failed_cas(state);
+        let percent = failed_cas.saturating_sub(*last_failed_cas) * 100 / WINDOW as u64;
+        let migrate = if state & SHARDED != 0 {
+            percent < self.unshard_below
+        } else {
+            percent >= self.shard_above
+        };
+        *last_failed_cas = if migrate {
+            self.migrate();
+            self.failed_cas(state ^ SHARDED)
+        } else {
+            failed_cas
+        };
+    }
+
+    /// Move the elements into the other lane at a quiescent point.
+    fn migrate(&self) {
+        let state = self.state.fetch_or(MIGRATING, super::CAS_ORDER);
+        let mut step = 0;
+        while self.state.load(super::LOAD_ORDER) & IN_FLIGHT_MASK != 0 {
+            self.wait.wait(step);
+            step = step.saturating_add(1);
+        }
+
+        trace!("Migrate from state {:x}", state);
+        let (from, to) = (self.lane(state), self.lane(state ^ SHARDED));
+        while let Some(value) = from.pop() {
+            if to.push(value).is_err() {
+                unreachable!("the lanes have the same capacity");
+            }
+        }
+        self.state.fetch_xor(MIGRATING | SHARDED, super::CAS_ORDER);
+    }
+}
+
+impl<T: Send> super::NewQueue<T> for AdaptiveQueue<T> {
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Start with the single lane, sharding at 50% of failed CAS attempts,
+    /// and going back below 5%.
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        Ok(Self {
+            state: AtomicUsize::new(0),
+            single: DoubleQueue::try_new(capacity)?,
+            sharded: ShardedQueue::try_new(capacity)?,
+            last_failed_cas: Mutex::new(0),
+            shard_above: 50,
+            unshard_below: 5,
+            wait: super::WaitStrategy::default(),
+        })
+    }
+}
+
+impl<T: Send> SynQueue<T> for AdaptiveQueue<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.operate(|lane| lane.push(value))
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.operate(|lane| lane.pop())
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        self.operate(|lane| lane.try_pop())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.operate(|lane| lane.is_empty())
+    }
+
+    fn is_full(&self) -> bool {
+        self.operate(|lane| lane.is_full())
+    }
+
+    fn capacity(&self) -> usize {
+        self.single.capacity()
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<AdaptiveQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<AdaptiveQueue<i32>>();
+}
+
+#[test]
+fn migration() {
+    use super::NewQueue as _;
+    super::loom::model(|| {
+        // migrate on every evaluation
+        let aq = AdaptiveQueue::new(8).with_thresholds(0, 1);
+        for i in 0..3 {
+            aq.push(i).unwrap();
+        }
+        for _ in 3..WINDOW {
+            assert!(!aq.is_sharded());
+            assert!(!aq.is_full());
+        }
+        assert!(aq.is_sharded());
+        for _ in 0..WINDOW {
+            assert!(!aq.is_empty());
+        }
+        assert!(!aq.is_sharded());
+        assert_eq!(aq.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+    })
+}
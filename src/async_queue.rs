@@ -0,0 +1,476 @@
+use super::qstd::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Mutex,
+};
+use std::{
+    cell::Cell,
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+std::thread_local! {
+    /// Ready polls of the pop futures on this thread since the last `Pending` one.
+    static READY_POPS: Cell<u32> = const { Cell::new(0) };
+}
+
+struct WakerList {
+    wakers: Mutex<Vec<Waker>>,
+    count: AtomicUsize,
+}
+
+impl WakerList {
+    fn new() -> Self {
+        Self {
+            wakers: Mutex::new(Vec::new()),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+            self.count.store(wakers.len(), Ordering::SeqCst);
+        }
+        drop(wakers);
+        // pairs with `count_after_fence` in `wake_all`
+        #[cfg(not(feature = "tsan"))]
+        super::qstd::sync::atomic::fence(Ordering::SeqCst);
+        #[cfg(feature = "tsan")]
+        self.count.fetch_add(0, Ordering::SeqCst);
+    }
+
+    fn wake_all(&self) {
+        if self.count_after_fence() == 0 {
+            return;
+        }
+        let wakers = {
+            let mut wakers = self.wakers.lock().unwrap();
+            self.count.store(0, Ordering::SeqCst);
+            mem::take(&mut *wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    #[cfg(not(feature = "tsan"))]
+    fn count_after_fence(&self) -> usize {
+        super::qstd::sync::atomic::fence(Ordering::SeqCst);
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// ThreadSanitizer doesn't model standalone fences, so synchronize
+    /// on the count itself: the RMW reads the latest value in its order.
+    #[cfg(feature = "tsan")]
+    fn count_after_fence(&self) -> usize {
+        self.count.fetch_add(0, Ordering::SeqCst)
+    }
+}
+
+/// Queue wrapper with asynchronous operations.
+///
+/// ## Principle
+/// A future first tries the non-blocking operation. If it fails, the task's waker
+/// is registered, and the operation is retried, so that a concurrent operation
+/// on the other side either sees the waker or makes the retry succeed.
+/// Every successful operation wakes up all the tasks waiting on the other side,
+/// since a registered waker may belong to a future that has already completed.
+///
+/// All the operations need to go through the wrapper, since the ones done
+/// on the inner queue directly don't wake up the tasks.
+///
+/// ## Budget
+/// A task popping in a loop from a queue that never runs empty would never give
+/// the executor a chance to run the other tasks on the same thread. With
+/// `with_pop_budget`, after that many ready pops in a row on a thread, the next
+/// pop future yields once, by waking itself up and returning `Pending`.
+///
+/// ## Closing
+/// If the inner queue can be closed, closing the wrapper wakes up all the tasks.
+/// The pending pushes then resolve to `PushError::Closed`, and the pending pops
+/// take the remaining elements before resolving to `TryPopError::Closed`.
+pub struct AsyncQueue<Q> {
+    queue: Q,
+    pushers: WakerList,
+    poppers: WakerList,
+    closed: AtomicBool,
+    pop_budget: Option<u32>,
+}
+
+impl<Q> AsyncQueue<Q> {
+    pub fn new(queue: Q) -> Self {
+        Self {
+            queue,
+            pushers: WakerList::new(),
+            poppers: WakerList::new(),
+            closed: AtomicBool::new(false),
+            pop_budget: None,
+        }
+    }
+
+    /// Yield to the executor after `budget` ready pops in a row.
+    pub fn with_pop_budget(mut self, budget: u32) -> Self {
+        self.pop_budget = Some(budget);
+        self
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    /// Push a value, waiting while the queue is full.
+    /// Resolves to `PushError::Closed` if the queue is closed.
+    pub fn push_async<T>(&self, value: T) -> PushFuture<'_, Q, T>
+    where
+        Q: super::SynQueue<T>,
+    {
+        PushFuture {
+            owner: self,
+            value: Some(value),
+        }
+    }
+
+    /// Pop a value, waiting while the queue is empty.
+    /// Resolves to `TryPopError::Closed` once the queue is closed and drained.
+    pub fn pop_async<T>(&self) -> PopFuture<'_, Q, T>
+    where
+        Q: super::SynQueue<T>,
+    {
+        PopFuture {
+            owner: self,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "sink")]
+impl<Q> AsyncQueue<Q> {
+    /// Create a `Sink` pushing into this queue.
+    pub fn sink<T>(&self) -> QueueSink<'_, Q, T>
+    where
+        Q: super::SynQueue<T>,
+    {
+        QueueSink {
+            owner: self,
+            pending: None,
+        }
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> super::SynQueue<T> for AsyncQueue<Q> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)?;
+        self.poppers.wake_all();
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        let value = self.queue.pop()?;
+        self.pushers.wake_all();
+        Some(value)
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let value = self.queue.try_pop()?;
+        self.pushers.wake_all();
+        Ok(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<T, Q: super::CloseQueue<T>> super::CloseQueue<T> for AsyncQueue<Q> {
+    fn close(&self) {
+        self.queue.close();
+        self.closed.store(true, Ordering::SeqCst);
+        self.pushers.wake_all();
+        self.poppers.wake_all();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+}
+
+/// Future returned by `AsyncQueue::push_async`.
+pub struct PushFuture<'a, Q, T> {
+    owner: &'a AsyncQueue<Q>,
+    value: Option<T>,
+}
+
+// The value is never pinned.
+impl<Q, T> Unpin for PushFuture<'_, Q, T> {}
+
+impl<Q: super::SynQueue<T>, T> Future for PushFuture<'_, Q, T> {
+    type Output = Result<(), super::PushError<T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let owner = self.owner;
+        let mut value = self.value.take().expect("polled after completion");
+        for attempt in 0..2 {
+            value = match super::SynQueue::push(owner, value) {
+                Ok(()) => return Poll::Ready(Ok(())),
+                Err(value) => value,
+            };
+            if attempt == 0 {
+                owner.pushers.register(cx.waker());
+            }
+        }
+        // pairs with the store in `close`, which wakes up the registered tasks after it
+        if owner.closed.load(Ordering::SeqCst) {
+            return Poll::Ready(Err(super::PushError::Closed(value)));
+        }
+        self.value = Some(value);
+        Poll::Pending
+    }
+}
+
+/// Future returned by `AsyncQueue::pop_async`.
+pub struct PopFuture<'a, Q, T> {
+    owner: &'a AsyncQueue<Q>,
+    _marker: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<Q: super::SynQueue<T>, T> Future for PopFuture<'_, Q, T> {
+    type Output = Result<T, super::TryPopError>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let owner = self.owner;
+        let ready = READY_POPS.get();
+        if owner.pop_budget.is_some_and(|budget| ready >= budget) {
+            READY_POPS.set(0);
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        for attempt in 0..2 {
+            match super::SynQueue::try_pop(owner) {
+                Ok(value) => {
+                    READY_POPS.set(ready.saturating_add(1));
+                    return Poll::Ready(Ok(value));
+                }
+                Err(super::TryPopError::Closed) => {
+                    return Poll::Ready(Err(super::TryPopError::Closed));
+                }
+                Err(_) => {}
+            }
+            if attempt == 0 {
+                owner.poppers.register(cx.waker());
+            }
+        }
+        READY_POPS.set(0);
+        Poll::Pending
+    }
+}
+
+/// Sink adapter returned by `AsyncQueue::sink`.
+///
+/// It holds at most one value that didn't fit into the queue,
+/// and is not ready to accept more until that value is pushed.
+#[cfg(feature = "sink")]
+pub struct QueueSink<'a, Q, T> {
+    owner: &'a AsyncQueue<Q>,
+    pending: Option<T>,
+}
+
+#[cfg(feature = "sink")]
+impl<Q, T> Unpin for QueueSink<'_, Q, T> {}
+
+#[cfg(feature = "sink")]
+impl<Q: super::SynQueue<T>, T> QueueSink<'_, Q, T> {
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), super::PushError<T>>> {
+        match self.pending.take() {
+            Some(value) => {
+                let mut push = self.owner.push_async(value);
+                let poll = Pin::new(&mut push).poll(cx);
+                self.pending = push.value.take();
+                poll
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+#[cfg(feature = "sink")]
+impl<Q: super::SynQueue<T>, T> futures_sink::Sink<T> for QueueSink<'_, Q, T> {
+    type Error = super::PushError<T>;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), super::PushError<T>>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), super::PushError<T>> {
+        let this = self.get_mut();
+        assert!(
+            this.pending.is_none(),
+            "start_send called without poll_ready"
+        );
+        this.pending = Some(item);
+        Ok(())
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), super::PushError<T>>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), super::PushError<T>>> {
+        self.get_mut().poll_pending(cx)
+    }
+}
+
+#[test]
+fn wake_up() {
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        task::Wake,
+    };
+    struct Flag(AtomicBool);
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Release);
+        }
+    }
+
+    super::loom::model(|| {
+        use super::NewQueue as _;
+        let aq = AsyncQueue::new(super::DoubleQueue::new(1));
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(Arc::clone(&flag));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut pop = aq.pop_async();
+        assert_eq!(Pin::new(&mut pop).poll(&mut cx), Poll::Pending);
+        let mut push = aq.push_async(1);
+        assert_eq!(Pin::new(&mut push).poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(flag.0.swap(false, Ordering::Acquire));
+
+        let mut push = aq.push_async(2);
+        assert_eq!(Pin::new(&mut push).poll(&mut cx), Poll::Pending);
+        assert_eq!(Pin::new(&mut pop).poll(&mut cx), Poll::Ready(Ok(1)));
+        assert!(flag.0.swap(false, Ordering::Acquire));
+        assert_eq!(Pin::new(&mut push).poll(&mut cx), Poll::Ready(Ok(())));
+
+        let mut push = aq.push_async(3);
+        assert_eq!(Pin::new(&mut push).poll(&mut cx), Poll::Pending);
+        assert_eq!(super::SynQueue::try_pop(&aq), Ok(2));
+        assert!(flag.0.swap(false, Ordering::Acquire));
+    })
+}
+
+#[cfg(feature = "sink")]
+#[test]
+fn sink() {
+    use futures_sink::Sink;
+    super::loom::model(|| {
+        use super::{NewQueue as _, SynQueue as _};
+        let aq = AsyncQueue::new(super::DoubleQueue::new(1));
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+        let mut sink = aq.sink();
+        let mut sink = Pin::new(&mut sink);
+
+        assert_eq!(sink.as_mut().poll_ready(&mut cx), Poll::Ready(Ok(())));
+        sink.as_mut().start_send(1).unwrap();
+        assert_eq!(sink.as_mut().poll_ready(&mut cx), Poll::Ready(Ok(())));
+        sink.as_mut().start_send(2).unwrap();
+        assert_eq!(sink.as_mut().poll_flush(&mut cx), Poll::Pending);
+        assert_eq!(sink.as_mut().poll_ready(&mut cx), Poll::Pending);
+        assert_eq!(aq.pop(), Some(1));
+        assert_eq!(sink.as_mut().poll_flush(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(aq.pop(), Some(2));
+    })
+}
+
+#[test]
+fn close() {
+    use super::{CloseQueue as _, NewQueue as _, PushError, TryPopError};
+    super::loom::model(|| {
+        let aq = AsyncQueue::new(super::Closable::new(super::DoubleQueue::new(1)));
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+
+        let mut push = aq.push_async(1);
+        assert_eq!(Pin::new(&mut push).poll(&mut cx), Poll::Ready(Ok(())));
+        let mut push = aq.push_async(2);
+        assert_eq!(Pin::new(&mut push).poll(&mut cx), Poll::Pending);
+        aq.close();
+        assert_eq!(
+            Pin::new(&mut push).poll(&mut cx),
+            Poll::Ready(Err(PushError::Closed(2)))
+        );
+        let mut pop = aq.pop_async();
+        assert_eq!(Pin::new(&mut pop).poll(&mut cx), Poll::Ready(Ok(1)));
+        let mut pop = aq.pop_async();
+        assert_eq!(
+            Pin::new(&mut pop).poll(&mut cx),
+            Poll::Ready(Err(TryPopError::Closed))
+        );
+    })
+}
+
+#[test]
+fn pop_budget() {
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        task::Wake,
+    };
+    struct Flag(AtomicBool);
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Release);
+        }
+    }
+
+    super::loom::model(|| {
+        use super::{NewQueue as _, SynQueue as _};
+        READY_POPS.set(0);
+        let aq = AsyncQueue::new(super::DoubleQueue::new(4)).with_pop_budget(2);
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(Arc::clone(&flag));
+        let mut cx = Context::from_waker(&waker);
+        for i in 0..3 {
+            aq.push(i).unwrap();
+        }
+
+        for i in 0..2 {
+            let mut pop = aq.pop_async();
+            assert_eq!(Pin::new(&mut pop).poll(&mut cx), Poll::Ready(Ok(i)));
+        }
+        let mut pop = aq.pop_async();
+        // out of budget, yield once
+        assert_eq!(Pin::new(&mut pop).poll(&mut cx), Poll::Pending);
+        assert!(flag.0.swap(false, Ordering::Acquire));
+        assert_eq!(Pin::new(&mut pop).poll(&mut cx), Poll::Ready(Ok(2)));
+    })
+}
+
+#[test]
+fn try_pop_closed() {
+    use super::{CloseQueue as _, NewQueue as _, SynQueue as _, TryPopError};
+    super::loom::model(|| {
+        let cq = super::Closable::new(super::DoubleQueue::<i32>::new(1));
+        cq.close();
+        // the error of the inner queue is passed through
+        let aq = AsyncQueue::new(cq);
+        assert_eq!(aq.try_pop(), Err(TryPopError::Closed));
+    })
+}
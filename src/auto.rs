@@ -0,0 +1,144 @@
+use super::{DoubleQueue, MaskedQueue};
+
+/// Queue picking the implementation based on the requested capacity
+/// and the word size of the target.
+///
+/// This is a good default for users who don't care about the trade-offs
+/// between the variants. On 64-bit targets, `MaskedQueue` is used whenever
+/// it can address the capacity, i.e. below 2^20 slots, and `DoubleQueue` otherwise.
+/// On 32-bit targets, `MaskedQueue` only has 12 bits left for the operations
+/// in flight, so `DoubleQueue` is used for as long as its 16-bit positions
+/// can address the capacity.
+// The parking primitives of shuttle make `DoubleQueue` much larger.
+#[cfg_attr(feature = "shuttle", allow(clippy::large_enum_variant))]
+pub enum AutoQueue<T> {
+    Masked(MaskedQueue<T>),
+    Double(DoubleQueue<T>),
+}
+
+impl<T> AutoQueue<T> {
+    /// Override the process-wide default wait strategy.
+    pub fn with_wait_strategy(self, strategy: super::WaitStrategy) -> Self {
+        match self {
+            Self::Masked(q) => Self::Masked(q.with_wait_strategy(strategy)),
+            Self::Double(q) => Self::Double(q.with_wait_strategy(strategy)),
+        }
+    }
+
+    /// Set the order of dropping the remaining elements.
+    pub fn with_drop_order(self, order: super::DropOrder) -> Self {
+        match self {
+            Self::Masked(q) => Self::Masked(q.with_drop_order(order)),
+            Self::Double(q) => Self::Double(q.with_drop_order(order)),
+        }
+    }
+}
+
+/// Whether `MaskedQueue` is the better fit for the capacity on this target.
+fn prefers_masked(capacity: usize) -> bool {
+    let addressable = capacity != 0 && capacity < super::masked::INDEX_MASK;
+    if cfg!(target_pointer_width = "32") {
+        addressable && capacity > DoubleQueue::<()>::MAX_CAPACITY
+    } else {
+        addressable
+    }
+}
+
+impl<T: Send> super::NewQueue<T> for AutoQueue<T> {
+    fn new(capacity: usize) -> Self {
+        if prefers_masked(capacity) {
+            Self::Masked(MaskedQueue::new(capacity))
+        } else {
+            Self::Double(DoubleQueue::new(capacity))
+        }
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        if prefers_masked(capacity) {
+            MaskedQueue::try_new(capacity).map(Self::Masked)
+        } else {
+            DoubleQueue::try_new(capacity).map(Self::Double)
+        }
+    }
+}
+
+impl<T: Send> super::SynQueue<T> for AutoQueue<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        match *self {
+            Self::Masked(ref q) => q.push(value),
+            Self::Double(ref q) => q.push(value),
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        match *self {
+            Self::Masked(ref q) => q.pop(),
+            Self::Double(ref q) => q.pop(),
+        }
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        match *self {
+            Self::Masked(ref q) => q.try_pop(),
+            Self::Double(ref q) => q.try_pop(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match *self {
+            Self::Masked(ref q) => q.is_empty(),
+            Self::Double(ref q) => q.is_empty(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        match *self {
+            Self::Masked(ref q) => q.is_full(),
+            Self::Double(ref q) => q.is_full(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match *self {
+            Self::Masked(ref q) => q.capacity(),
+            Self::Double(ref q) => q.capacity(),
+        }
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<AutoQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<AutoQueue<i32>>();
+}
+
+#[test]
+fn close() {
+    super::test_close::<super::Closable<AutoQueue<i32>>>();
+}
+
+#[test]
+fn selection() {
+    use super::NewQueue as _;
+    super::loom::model(|| {
+        if cfg!(target_pointer_width = "32") {
+            assert!(matches!(AutoQueue::<i32>::new(16), AutoQueue::Double(_)));
+            assert!(matches!(
+                AutoQueue::<i32>::new(1 << 16),
+                AutoQueue::Masked(_)
+            ));
+            assert!(AutoQueue::<i32>::try_new(1 << 20).is_err());
+        } else {
+            assert!(matches!(AutoQueue::<i32>::new(16), AutoQueue::Masked(_)));
+            assert!(matches!(AutoQueue::<i32>::new(12), AutoQueue::Masked(_)));
+            assert!(matches!(
+                AutoQueue::<i32>::new(1 << 20),
+                AutoQueue::Double(_)
+            ));
+        }
+    })
+}
@@ -1,75 +1,224 @@
-use super::qstd::{cell::UnsafeCell, hint, sync::atomic::AtomicUsize};
+use super::qstd::{hint, sync::atomic::AtomicUsize};
+use super::storage::{Slot, Slots};
 use std::mem;
 
+/// Half of the machine word, wide enough for any position of the state word.
+#[cfg(target_pointer_width = "64")]
 type Pointer = u32;
+#[cfg(target_pointer_width = "32")]
+type Pointer = u16;
 const _BITS_CHECK: usize = (mem::size_of::<usize>() == 2 * mem::size_of::<Pointer>()) as usize - 1;
 const MASK_BITS: usize = mem::size_of::<usize>() * 8;
 
+/// Bits of a position in the state word. The rest of the word is taken
+/// by the generation, which `pop_newest` bumps on every retreat of the head,
+/// so that a stale CAS can't mistake the retreated word for the old one.
+const POSITION_BITS: usize = mem::size_of::<usize>() * 3;
+const POSITION_MASK: usize = (1 << POSITION_BITS) - 1;
+
 #[derive(Clone, Copy, Debug)]
 struct State {
     head: Pointer,
     tail: Pointer,
+    generation: Pointer,
 }
 impl State {
-    const HEAD_BITS: usize = mem::size_of::<Pointer>() * 8;
     #[inline(always)]
     fn unpack(raw: usize) -> Self {
         Self {
-            head: raw as Pointer,
-            tail: (raw >> Self::HEAD_BITS) as Pointer,
+            head: (raw & POSITION_MASK) as Pointer,
+            tail: ((raw >> POSITION_BITS) & POSITION_MASK) as Pointer,
+            generation: (raw >> (2 * POSITION_BITS)) as Pointer,
         }
     }
     #[inline(always)]
     fn pack(self) -> usize {
-        (self.head as usize) | ((self.tail as usize) << Self::HEAD_BITS)
+        (self.head as usize)
+            | ((self.tail as usize) << POSITION_BITS)
+            | ((self.generation as usize) << (2 * POSITION_BITS))
     }
 }
 
-pub struct AxelQueue<T> {
+pub struct AxelQueue<T, const N: usize = 0> {
     state: AtomicUsize,
     occupation: Box<[AtomicUsize]>,
-    data: Box<[mem::MaybeUninit<UnsafeCell<T>>]>,
+    data: Slots<Slot<T>, Box<[Slot<T>]>, N>,
+    wait: super::WaitStrategy,
+    drop_order: super::DropOrder,
 }
 
-unsafe impl<T> Sync for AxelQueue<T> {}
+unsafe impl<T, const N: usize> Sync for AxelQueue<T, N> {}
+
+impl<T, const N: usize> AxelQueue<T, N> {
+    /// Largest capacity the positions of the state word can address.
+    ///
+    /// A quarter of the word is left to the generation tag, so this is
+    /// smaller than the limit of `DoubleQueue`.
+    pub const MAX_CAPACITY: usize = if N == 0 {
+        POSITION_MASK
+    } else {
+        POSITION_MASK / 2
+    };
+    /// Fails the build for a fixed capacity the positions can't address.
+    const FIXED_CAPACITY_CHECK: () = assert!(
+        N <= Self::MAX_CAPACITY,
+        "the fixed capacity doesn't fit the positions"
+    );
 
-impl<T> AxelQueue<T> {
     fn advance(&self, index: Pointer) -> Pointer {
-        if index as usize + 1 == self.data.len() {
+        if index as usize + 1 == self.data.wrap() {
             0
         } else {
             index + 1
         }
     }
+
+    /// Data slot at the position, only bounds-checked with the `checked` feature.
+    #[inline(always)]
+    fn slot(&self, index: usize) -> &Slot<T> {
+        if cfg!(feature = "checked") {
+            assert!(index < self.data.wrap(), "slot {} is out of bounds", index);
+        }
+        unsafe { self.data.get_unchecked(index) }
+    }
+
+    /// Occupation word with the bit of the slot at the position,
+    /// only bounds-checked with the `checked` feature.
+    #[inline(always)]
+    fn occupation_word(&self, index: usize) -> &AtomicUsize {
+        let slot = self.data.index(index);
+        if cfg!(feature = "checked") {
+            assert!(index < self.data.wrap(), "slot {} is out of bounds", index);
+            &self.occupation[slot / MASK_BITS]
+        } else {
+            unsafe { self.occupation.get_unchecked(slot / MASK_BITS) }
+        }
+    }
+
+    /// Bit of the slot at the position within its occupation word.
+    #[inline(always)]
+    fn occupation_bit(&self, index: usize) -> usize {
+        1 << (self.data.index(index) % MASK_BITS)
+    }
+
+    fn retreat(&self, index: Pointer) -> Pointer {
+        if index == 0 {
+            self.data.wrap() as Pointer - 1
+        } else {
+            index - 1
+        }
+    }
+
+    /// Override the process-wide default wait strategy.
+    pub fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+
+    /// Set the order of dropping the remaining elements.
+    pub fn with_drop_order(mut self, order: super::DropOrder) -> Self {
+        self.drop_order = order;
+        self
+    }
 }
 
-impl<T: Send> super::SynQueue<T> for AxelQueue<T> {
+impl<T> AxelQueue<T> {
+    /// Decompose the queue into the data pointer, capacity, head, and tail indices.
+    ///
+    /// The data has `capacity + 1` slots, and the ones in `tail..head` range
+    /// (wrapping around) are initialized.
+    #[cfg(not(feature = "loom"))]
+    pub fn into_raw_parts(self) -> (*mut T, usize, usize, usize) {
+        let this = mem::ManuallyDrop::new(self);
+        let s = State::unpack(this.state.load(super::LOAD_ORDER));
+        let data = unsafe { std::ptr::read(&this.data) }.into_heap();
+        let capacity = data.len() - 1;
+        drop(unsafe { std::ptr::read(&this.occupation) });
+        (
+            Box::into_raw(data) as *mut T,
+            capacity,
+            s.head as usize,
+            s.tail as usize,
+        )
+    }
+
+    /// Reconstruct the queue from its raw parts.
+    ///
+    /// # Safety
+    /// The parts have to come from `AxelQueue::into_raw_parts`.
+    #[cfg(not(feature = "loom"))]
+    pub unsafe fn from_raw_parts(data: *mut T, capacity: usize, head: usize, tail: usize) -> Self {
+        debug_assert!(head <= capacity && tail <= capacity);
+        let data = Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            data as *mut Slot<T>,
+            capacity + 1,
+        ));
+        let mut occupation = (0..1 + capacity / MASK_BITS)
+            .map(|_| AtomicUsize::new(0))
+            .collect::<Box<[_]>>();
+        let mut index = tail;
+        while index != head {
+            *occupation[index / MASK_BITS].get_mut() |= 1 << (index % MASK_BITS);
+            index = if index == capacity { 0 } else { index + 1 };
+        }
+        let state = State {
+            head: head as Pointer,
+            tail: tail as Pointer,
+            generation: 0,
+        };
+        Self {
+            state: AtomicUsize::new(state.pack()),
+            occupation,
+            data: Slots::new(data),
+            wait: super::WaitStrategy::default(),
+            drop_order: super::DropOrder::default(),
+        }
+    }
+}
+
+impl<T: Send, const N: usize> super::NewQueue<T> for AxelQueue<T, N> {
     fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        let () = Self::FIXED_CAPACITY_CHECK;
+        if (N != 0 && capacity != N) || capacity > Self::MAX_CAPACITY {
+            return Err(super::NewError::InvalidCapacity(capacity));
+        }
         let num_words = 1 + capacity / MASK_BITS;
-        Self {
+        Ok(Self {
             state: AtomicUsize::new(0),
-            occupation: (0..num_words).map(|_| AtomicUsize::new(0)).collect(),
-            data: (0..=capacity).map(|_| mem::MaybeUninit::uninit()).collect(),
-        }
+            occupation: super::try_alloc(num_words, || AtomicUsize::new(0))?,
+            data: Slots::new(if N == 0 {
+                super::try_alloc(capacity + 1, mem::MaybeUninit::uninit)?
+            } else {
+                Box::default()
+            }),
+            wait: super::WaitStrategy::default(),
+            drop_order: super::DropOrder::default(),
+        })
     }
+}
 
-    #[profiling::function]
+impl<T: Send, const N: usize> super::SynQueue<T> for AxelQueue<T, N> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
     fn push(&self, value: T) -> Result<(), T> {
         let mut state = self.state.load(super::LOAD_ORDER);
         let mut index;
         let mut bit;
+        let mut step = 0;
         let next = loop {
-            log::trace!("Push CAS: {:x}", state);
+            trace!("Push CAS: {:x}", state);
             let s = State::unpack(state);
-            let next = self.advance(s.head);
-            if next == s.tail {
+            if self.data.distance(s.tail as usize, s.head as usize) == self.data.capacity() {
                 return Err(value);
             }
+            let next = self.advance(s.head);
 
             index = s.head as usize;
-            bit = 1 << (index % MASK_BITS);
-            let mask =
-                unsafe { self.occupation.get_unchecked(index / MASK_BITS) }.load(super::LOAD_ORDER);
+            bit = self.occupation_bit(index);
+            let mask = self.occupation_word(index).load(super::LOAD_ORDER);
             if mask & bit == 0 {
                 match self.state.compare_exchange_weak(
                     state,
@@ -82,39 +231,65 @@ impl<T: Send> super::SynQueue<T> for AxelQueue<T> {
                 }
             } else {
                 // some `pop` is not finished reading the value?
+                self.wait.wait(step);
+                step = step.saturating_add(1);
                 state = self.state.load(super::LOAD_ORDER);
+                continue;
             }
             hint::spin_loop();
         };
 
-        log::trace!("Push success, next head = {:x}", next);
+        trace!("Push success, next head = {:x}", next);
         // write the data
-        unsafe { super::UnsafeCellHelper::write(self.data.get_unchecked(index).as_ptr(), value) };
+        unsafe { super::UnsafeCellHelper::write(self.slot(index).as_ptr(), value) };
 
-        let old = unsafe { self.occupation.get_unchecked(index / MASK_BITS) }
-            .fetch_or(bit, super::CAS_ORDER);
-        debug_assert_eq!(old & bit, 0);
+        let old = self.occupation_word(index).fetch_or(bit, super::CAS_ORDER);
+        checked_assert!(old & bit == 0, "slot {} is already occupied", index);
 
         // done
         Ok(())
     }
 
-    #[profiling::function]
     fn pop(&self) -> Option<T> {
+        self.pop_oldest(true).ok()
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        self.pop_oldest(false)
+    }
+
+    fn is_empty(&self) -> bool {
+        let state = self.state.load(super::LOAD_ORDER);
+        let s = State::unpack(state);
+        s.head == s.tail
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() == self.data.capacity()
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+}
+
+impl<T: Send, const N: usize> AxelQueue<T, N> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn pop_oldest(&self, wait: bool) -> Result<T, super::TryPopError> {
         let mut state = self.state.load(super::LOAD_ORDER);
         let mut index;
         let mut bit;
+        let mut step = 0;
         let next = loop {
-            log::trace!("Pop CAS: {:x}", state);
+            trace!("Pop CAS: {:x}", state);
             let s = State::unpack(state);
             if s.head == s.tail {
-                return None;
+                return Err(super::TryPopError::Empty);
             }
 
             index = s.tail as usize;
-            bit = 1 << (index % MASK_BITS);
-            let mask =
-                unsafe { self.occupation.get_unchecked(index / MASK_BITS) }.load(super::LOAD_ORDER);
+            bit = self.occupation_bit(index);
+            let mask = self.occupation_word(index).load(super::LOAD_ORDER);
             if mask & bit != 0 {
                 let next = self.advance(s.tail);
                 match self.state.compare_exchange_weak(
@@ -126,47 +301,200 @@ impl<T: Send> super::SynQueue<T> for AxelQueue<T> {
                     Ok(_) => break next,
                     Err(other) => state = other,
                 }
+            } else if !wait {
+                return Err(super::TryPopError::Busy);
             } else {
                 // some `push` is not finished writing the value?
+                self.wait.wait(step);
+                step = step.saturating_add(1);
                 state = self.state.load(super::LOAD_ORDER);
+                continue;
             }
             hint::spin_loop();
         };
 
-        log::trace!("Pop success, next tail = {:x}", next);
+        trace!("Pop success, next tail = {:x}", next);
         // read the data
-        let value = unsafe {
-            self.data
-                .get_unchecked(index)
-                .assume_init_read()
-                .into_inner()
-        };
+        let value = unsafe { self.slot(index).assume_init_read().into_inner() };
 
-        let old = unsafe { self.occupation.get_unchecked(index / MASK_BITS) }
+        let old = self
+            .occupation_word(index)
             .fetch_and(!bit, super::CAS_ORDER);
-        debug_assert_ne!(old & bit, 0);
+        checked_assert!(old & bit != 0, "slot {} is not occupied", index);
 
         // done
+        Ok(value)
+    }
+
+    /// Pop the most recently pushed element, instead of the oldest one.
+    ///
+    /// This is the same as `pop`, but it takes the slot right before the head,
+    /// moving the head back. Elements that are still being written by
+    /// the producers are waited on, just like in `pop`.
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn pop_newest(&self) -> Option<T> {
+        let mut state = self.state.load(super::LOAD_ORDER);
+        let mut index;
+        let mut bit;
+        let mut step = 0;
+        let prev = loop {
+            trace!("Pop newest CAS: {:x}", state);
+            let s = State::unpack(state);
+            if s.head == s.tail {
+                return None;
+            }
+
+            let prev = self.retreat(s.head);
+            index = prev as usize;
+            bit = self.occupation_bit(index);
+            let mask = self.occupation_word(index).load(super::LOAD_ORDER);
+            if mask & bit != 0 {
+                match self.state.compare_exchange_weak(
+                    state,
+                    State {
+                        head: prev,
+                        tail: s.tail,
+                        generation: s.generation.wrapping_add(1),
+                    }
+                    .pack(),
+                    super::CAS_ORDER,
+                    super::LOAD_ORDER,
+                ) {
+                    Ok(_) => break prev,
+                    Err(other) => state = other,
+                }
+            } else {
+                // some `push` is not finished writing the value?
+                self.wait.wait(step);
+                step = step.saturating_add(1);
+                state = self.state.load(super::LOAD_ORDER);
+                continue;
+            }
+            hint::spin_loop();
+        };
+
+        trace!("Pop newest success, next head = {:x}", prev);
+        let value = unsafe { self.slot(index).assume_init_read().into_inner() };
+
+        let old = self
+            .occupation_word(index)
+            .fetch_and(!bit, super::CAS_ORDER);
+        checked_assert!(old & bit != 0, "slot {} is not occupied", index);
+
         Some(value)
     }
 
-    fn is_empty(&self) -> bool {
-        let state = self.state.load(super::LOAD_ORDER);
-        let s = State::unpack(state);
-        s.head == s.tail
+    fn len(&self) -> usize {
+        let s = State::unpack(self.state.load(super::LOAD_ORDER));
+        self.data.distance(s.tail as usize, s.head as usize)
+    }
+
+    fn is_occupied(&self, index: usize) -> bool {
+        let mask = self.occupation_word(index).load(super::LOAD_ORDER);
+        mask & self.occupation_bit(index) != 0
+    }
+
+    /// Move roughly half of the elements of another queue into this one,
+    /// returning the number of elements moved.
+    ///
+    /// The elements are claimed with a single CAS on the other queue,
+    /// so it's an efficient way to rebalance between per-worker queues.
+    /// If this queue is concurrently filled up, the rest of the elements
+    /// are returned back to the other queue.
+    ///
+    /// The stolen elements keep their relative order, but they go behind
+    /// the elements of this queue and may interleave with concurrent pushes.
+    /// The returned ones go to the back of the other queue, behind its newer elements.
+    ///
+    /// Each element is retried until it lands in one of the two queues. If both
+    /// are kept full, for example by two workers stealing from each other while
+    /// their producers are busy, the elements bounce between them without progress.
+    /// Rebalancing should thus only be done from a queue with room to spare.
+    pub fn steal_half(&self, from: &Self) -> usize {
+        assert!(!std::ptr::eq(self, from));
+        let mut state = from.state.load(super::LOAD_ORDER);
+        let mut step = 0;
+        let (start, count) = loop {
+            trace!("Steal CAS: {:x}", state);
+            let s = State::unpack(state);
+            let len = from.data.distance(s.tail as usize, s.head as usize);
+            let count = len.div_ceil(2).min(self.data.capacity() - self.len());
+            if count == 0 {
+                return 0;
+            }
+
+            // only claim the elements that are completely written
+            let mut ready = 0;
+            let mut end = s.tail;
+            while ready < count && from.is_occupied(end as usize) {
+                ready += 1;
+                end = from.advance(end);
+            }
+            if ready != 0 {
+                match from.state.compare_exchange_weak(
+                    state,
+                    State { tail: end, ..s }.pack(),
+                    super::CAS_ORDER,
+                    super::LOAD_ORDER,
+                ) {
+                    Ok(_) => break (s.tail, ready),
+                    Err(other) => state = other,
+                }
+            } else {
+                // some `push` is not finished writing the value?
+                self.wait.wait(step);
+                step = step.saturating_add(1);
+                state = from.state.load(super::LOAD_ORDER);
+                continue;
+            }
+            hint::spin_loop();
+        };
+
+        let mut values = Vec::with_capacity(count);
+        let mut index = start;
+        for _ in 0..count {
+            let slot = index as usize;
+            values.push(unsafe { from.slot(slot).assume_init_read().into_inner() });
+            let bit = from.occupation_bit(slot);
+            let old = from.occupation_word(slot).fetch_and(!bit, super::CAS_ORDER);
+            checked_assert!(old & bit != 0, "slot {} is not occupied", index);
+            index = from.advance(index);
+        }
+
+        let mut stolen = 0;
+        for mut value in values {
+            let mut step = 0;
+            loop {
+                value = match super::SynQueue::push(self, value) {
+                    Ok(()) => {
+                        stolen += 1;
+                        break;
+                    }
+                    Err(value) => value,
+                };
+                value = match super::SynQueue::push(from, value) {
+                    Ok(()) => break,
+                    Err(value) => value,
+                };
+                self.wait.wait(step);
+                step = step.saturating_add(1);
+            }
+        }
+        stolen
     }
 }
 
-impl<T> Drop for AxelQueue<T> {
+impl<T: Send, const N: usize> super::ExtendQueue<T> for AxelQueue<T, N> {}
+
+impl<T, const N: usize> Drop for AxelQueue<T, N> {
     fn drop(&mut self) {
         let state = self.state.load(super::LOAD_ORDER);
-        log::trace!("Drop state: {:x}", state);
+        trace!("Drop state: {:x}", state);
         let s = State::unpack(state);
-        let mut cursor = s.tail;
-        while cursor != s.head {
-            unsafe { self.data[cursor as usize].assume_init_drop() };
-            cursor = self.advance(cursor);
-        }
+        unsafe {
+            self.data
+                .drop_range(s.tail as usize, s.head as usize, self.drop_order)
+        };
     }
 }
 
@@ -180,7 +508,117 @@ fn smoke() {
     super::test_smoke::<AxelQueue<i32>>();
 }
 
+#[test]
+fn close() {
+    super::test_close::<super::Closable<AxelQueue<i32>>>();
+}
+
+#[test]
+fn fixed() {
+    super::test_overflow::<AxelQueue<i32, 2>>();
+    super::test_smoke::<AxelQueue<i32, 16>>();
+    super::test_differential::<AxelQueue<u32, 4>>();
+}
+
 #[test]
 fn barrage() {
     super::test_barrage::<AxelQueue<usize>>();
 }
+
+#[test]
+fn try_pop_busy() {
+    use super::{NewQueue as _, SynQueue as _, TryPopError};
+    super::loom::model(|| {
+        let aq = super::AxelQueue::new(2);
+        aq.push(1).unwrap();
+        // pretend the push is still writing the value
+        aq.occupation[0].fetch_and(!1, super::CAS_ORDER);
+        assert_eq!(aq.try_pop(), Err(TryPopError::Busy));
+        aq.occupation[0].fetch_or(1, super::CAS_ORDER);
+        assert_eq!(aq.try_pop(), Ok(1));
+    })
+}
+
+#[test]
+fn pop_newest() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let aq = super::AxelQueue::new(3);
+        for i in 0..3 {
+            aq.push(i).unwrap();
+        }
+        assert_eq!(aq.pop_newest(), Some(2));
+        assert_eq!(aq.pop(), Some(0));
+        aq.push(3).unwrap();
+        aq.push(4).unwrap();
+        assert_eq!(aq.pop_newest(), Some(4));
+        assert_eq!(aq.pop_newest(), Some(3));
+        assert_eq!(aq.pop_newest(), Some(1));
+        assert_eq!(aq.pop_newest(), None);
+    })
+}
+
+#[test]
+fn pop_newest_generation() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let aq = super::AxelQueue::new(3);
+        let before = aq.state.load(super::LOAD_ORDER);
+        aq.push(0).unwrap();
+        assert_eq!(aq.pop_newest(), Some(0));
+        // a push that loaded `before` must not be able to succeed now
+        assert_ne!(aq.state.load(super::LOAD_ORDER), before);
+    })
+}
+
+#[test]
+fn steal_half() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let victim = super::AxelQueue::new(8);
+        let thief = super::AxelQueue::new(2);
+        for i in 0..5 {
+            victim.push(i).unwrap();
+        }
+        assert_eq!(thief.steal_half(&victim), 2);
+        assert_eq!(thief.steal_half(&victim), 0);
+        assert_eq!(thief.try_iter().collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(thief.steal_half(&victim), 2);
+        assert_eq!(thief.steal_half(&victim), 0);
+        assert_eq!(victim.try_iter().collect::<Vec<_>>(), [4]);
+        assert_eq!(thief.try_iter().collect::<Vec<_>>(), [2, 3]);
+    })
+}
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn raw_parts() {
+    super::test_raw_parts(
+        super::AxelQueue::into_raw_parts,
+        |data, capacity, head, tail| unsafe {
+            super::AxelQueue::from_raw_parts(data, capacity, head, tail)
+        },
+    );
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    super::lincheck::check_mpmc::<AxelQueue<u32>>();
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<AxelQueue<u32>>();
+}
+
+#[cfg(feature = "checked")]
+#[test]
+#[should_panic(expected = "slot 3 is out of bounds")]
+fn checked_slot() {
+    use super::NewQueue as _;
+    super::loom::model(|| {
+        let aq = AxelQueue::<i32>::new(2);
+        let _ = aq.slot(3);
+    })
+}
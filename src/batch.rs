@@ -0,0 +1,115 @@
+/// Producer handle accumulating the elements locally and pushing them in batches.
+///
+/// Each producer thread is meant to own its handle, so the buffer is thread-local
+/// and filling it doesn't touch any shared state. The batch is published with
+/// `ExtendQueue::try_extend` once it reaches the threshold, or on `flush`,
+/// which for `DoubleQueue` costs one CAS per batch instead of one per element.
+///
+/// The elements only become visible to the consumers when they are flushed.
+/// Dropping the handle flushes the buffer, and the elements that don't fit are lost,
+/// so the handle should be consumed with `finish` if they need to be kept.
+pub struct BatchProducer<'a, T, Q: super::ExtendQueue<T>> {
+    queue: &'a Q,
+    buffer: Vec<T>,
+    threshold: usize,
+}
+
+impl<'a, T, Q: super::ExtendQueue<T>> BatchProducer<'a, T, Q> {
+    pub fn new(queue: &'a Q, threshold: usize) -> Self {
+        assert_ne!(threshold, 0);
+        Self {
+            queue,
+            buffer: Vec::with_capacity(threshold),
+            threshold,
+        }
+    }
+
+    pub fn queue(&self) -> &'a Q {
+        self.queue
+    }
+
+    /// Number of elements waiting in the buffer.
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Add an element to the batch, flushing it if it reaches the threshold.
+    ///
+    /// Fails only if the buffer is at the threshold and the queue has no space for it.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.buffer.len() >= self.threshold && self.flush() == 0 {
+            return Err(value);
+        }
+        self.buffer.push(value);
+        if self.buffer.len() >= self.threshold {
+            self.flush();
+        }
+        Ok(())
+    }
+
+    /// Push the buffered elements to the queue, returning the number of them moved.
+    ///
+    /// The ones that don't fit stay in the buffer.
+    pub fn flush(&mut self) -> usize {
+        if self.buffer.is_empty() {
+            return 0;
+        }
+        self.queue.try_extend(&mut self.buffer)
+    }
+
+    /// Take the elements that haven't been flushed, without pushing them.
+    pub fn into_pending(mut self) -> Vec<T> {
+        std::mem::take(&mut self.buffer)
+    }
+
+    /// Flush the buffer, and return the elements that don't fit into the queue.
+    pub fn finish(mut self) -> Vec<T> {
+        self.flush();
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+impl<T, Q: super::ExtendQueue<T>> Drop for BatchProducer<'_, T, Q> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[test]
+fn batches() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let dq = super::DoubleQueue::new(4);
+        let mut producer = BatchProducer::new(&dq, 3);
+        producer.push(1).unwrap();
+        producer.push(2).unwrap();
+        assert!(dq.is_empty());
+        producer.push(3).unwrap();
+        assert_eq!(producer.pending(), 0);
+        producer.push(4).unwrap();
+        producer.push(5).unwrap();
+        producer.push(6).unwrap();
+        // only one of them fits
+        assert_eq!(producer.pending(), 2);
+        assert_eq!(producer.push(7), Ok(()));
+        assert_eq!(producer.push(8), Err(8));
+        assert_eq!(dq.pop(), Some(1));
+        assert_eq!(producer.flush(), 1);
+        assert_eq!(producer.into_pending(), [6, 7]);
+        assert_eq!(dq.try_iter().collect::<Vec<_>>(), [2, 3, 4, 5]);
+    })
+}
+
+#[test]
+fn fallback() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let mq = super::MaskedQueue::new(2);
+        let mut producer = BatchProducer::new(&mq, 4);
+        for i in 0..3 {
+            producer.push(i).unwrap();
+        }
+        assert_eq!(producer.finish(), [2]);
+        assert_eq!(mq.try_iter().collect::<Vec<_>>(), [0, 1]);
+    })
+}
@@ -0,0 +1,209 @@
+//! Stress test pushing elements through a queue from multiple threads,
+//! printing the throughput and checking that no element is lost or duplicated.
+
+use std::{
+    process,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+use synqueue::{
+    AxelQueue, DoubleQueue, FaaQueue, MaskedQueue, MsQueue, NewQueue, ShardedQueue, SynQueue,
+    VyukovQueue,
+};
+
+const USAGE: &str = "\
+Usage: synqueue-stress [OPTIONS]
+
+Options:
+  --queue <NAME>       axel, double, masked, faa, vyukov, ms, sharded [default: double]
+  --capacity <N>       capacity of the queue [default: 1024]
+  --producers <N>      number of producer threads [default: 4]
+  --consumers <N>      number of consumer threads [default: 4]
+  --duration <SECS>    how long the producers run [default: 5]
+  --payload <BYTES>    element size: 8, 16, 32, 64, 128, 256, 512, or 1024 [default: 8]";
+
+struct Config {
+    queue: String,
+    capacity: usize,
+    producers: usize,
+    consumers: usize,
+    duration: Duration,
+    payload: usize,
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn parse_args() -> Config {
+    let mut config = Config {
+        queue: "double".to_string(),
+        capacity: 1024,
+        producers: 4,
+        consumers: 4,
+        duration: Duration::from_secs(5),
+        payload: 8,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        let value = args
+            .next()
+            .unwrap_or_else(|| fail(&format!("missing value for {}", arg)));
+        let number = || {
+            value
+                .parse::<usize>()
+                .unwrap_or_else(|_| fail(&format!("invalid value for {}: {}", arg, value)))
+        };
+        match arg.as_str() {
+            "--queue" => config.queue = value.clone(),
+            "--capacity" => config.capacity = number(),
+            "--producers" => config.producers = number(),
+            "--consumers" => config.consumers = number(),
+            "--duration" => {
+                let secs = value
+                    .parse::<f64>()
+                    .unwrap_or_else(|_| fail(&format!("invalid duration: {}", value)));
+                config.duration = Duration::from_secs_f64(secs);
+            }
+            "--payload" => config.payload = number(),
+            _ => fail(&format!("unknown option: {}", arg)),
+        }
+    }
+    if config.producers == 0 || config.consumers == 0 {
+        fail("there has to be at least one producer and one consumer");
+    }
+    config
+}
+
+/// Element of the given size, carrying its value in the first word.
+#[derive(Clone, Copy)]
+struct Payload<const WORDS: usize>([u64; WORDS]);
+
+#[derive(Default)]
+struct Totals {
+    count: u64,
+    sum: u64,
+}
+
+fn produce<const W: usize>(queue: &impl SynQueue<Payload<W>>, stop: &AtomicBool) -> Totals {
+    let mut totals = Totals::default();
+    let mut next = 0u64;
+    while !stop.load(Ordering::Relaxed) {
+        let mut payload = Payload([next; W]);
+        while let Err(p) = queue.push(payload) {
+            if stop.load(Ordering::Relaxed) {
+                return totals;
+            }
+            payload = p;
+            thread::yield_now();
+        }
+        totals.count += 1;
+        totals.sum = totals.sum.wrapping_add(next);
+        next += 1;
+    }
+    totals
+}
+
+fn consume<const W: usize>(queue: &impl SynQueue<Payload<W>>, active: &AtomicUsize) -> Totals {
+    let mut totals = Totals::default();
+    loop {
+        match queue.pop() {
+            Some(Payload(words)) => {
+                totals.count += 1;
+                totals.sum = totals.sum.wrapping_add(words[0]);
+            }
+            // only stop once all the producers are done and the queue is drained
+            None if active.load(Ordering::Acquire) == 0 && queue.is_empty() => break,
+            None => thread::yield_now(),
+        }
+    }
+    totals
+}
+
+fn run<const W: usize, Q: NewQueue<Payload<W>>>(config: &Config) -> bool {
+    let queue = Q::try_new(config.capacity).unwrap_or_else(|e| fail(&e.to_string()));
+    let stop = AtomicBool::new(false);
+    let active = AtomicUsize::new(config.producers);
+    let start = Instant::now();
+    let (pushed, popped) = thread::scope(|scope| {
+        let producers = (0..config.producers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let totals = produce(&queue, &stop);
+                    active.fetch_sub(1, Ordering::Release);
+                    totals
+                })
+            })
+            .collect::<Vec<_>>();
+        let consumers = (0..config.consumers)
+            .map(|_| scope.spawn(|| consume(&queue, &active)))
+            .collect::<Vec<_>>();
+        thread::sleep(config.duration);
+        stop.store(true, Ordering::Relaxed);
+        let total = |handles: Vec<thread::ScopedJoinHandle<Totals>>| {
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .fold(Totals::default(), |a, b| Totals {
+                    count: a.count + b.count,
+                    sum: a.sum.wrapping_add(b.sum),
+                })
+        };
+        (total(producers), total(consumers))
+    });
+    let elapsed = start.elapsed();
+
+    println!(
+        "{}: {} elements of {} bytes in {:.2?}, {:.2} M/s",
+        config.queue,
+        popped.count,
+        std::mem::size_of::<Payload<W>>(),
+        elapsed,
+        popped.count as f64 / elapsed.as_secs_f64() / 1e6,
+    );
+    let conserved = pushed.count == popped.count && pushed.sum == popped.sum;
+    if !conserved {
+        eprintln!(
+            "conservation violated: pushed {} (sum {}), popped {} (sum {})",
+            pushed.count, pushed.sum, popped.count, popped.sum
+        );
+    }
+    conserved
+}
+
+fn run_queue<const W: usize>(config: &Config) -> bool {
+    match config.queue.as_str() {
+        "axel" => run::<W, AxelQueue<Payload<W>>>(config),
+        "double" => run::<W, DoubleQueue<Payload<W>>>(config),
+        "masked" => run::<W, MaskedQueue<Payload<W>>>(config),
+        "faa" => run::<W, FaaQueue<Payload<W>>>(config),
+        "vyukov" => run::<W, VyukovQueue<Payload<W>>>(config),
+        "ms" => run::<W, MsQueue<Payload<W>>>(config),
+        "sharded" => run::<W, ShardedQueue<Payload<W>>>(config),
+        other => fail(&format!("unknown queue: {}", other)),
+    }
+}
+
+fn main() {
+    let config = parse_args();
+    let conserved = match config.payload {
+        8 => run_queue::<1>(&config),
+        16 => run_queue::<2>(&config),
+        32 => run_queue::<4>(&config),
+        64 => run_queue::<8>(&config),
+        128 => run_queue::<16>(&config),
+        256 => run_queue::<32>(&config),
+        512 => run_queue::<64>(&config),
+        1024 => run_queue::<128>(&config),
+        other => fail(&format!("unsupported payload size: {}", other)),
+    };
+    if !conserved {
+        process::exit(1);
+    }
+}
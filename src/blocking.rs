@@ -0,0 +1,321 @@
+use super::qstd::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Condvar, Mutex,
+};
+use std::time::{Duration, Instant};
+
+/// Queue wrapper that can park the threads on a full or empty queue.
+///
+/// ## Principle
+/// The fast path is the plain non-blocking operation of the inner queue.
+/// Only when it fails, the thread registers itself as a waiter under the lock,
+/// retries the operation, and parks on a condition variable.
+/// A successful operation only touches the lock if there are waiters on the
+/// other side, so an uncontended queue stays lock-free.
+///
+/// All the operations need to go through the wrapper, since the ones done
+/// on the inner queue directly don't wake up the waiters.
+///
+/// ## Closing
+/// If the inner queue can be closed, closing the wrapper wakes up all the waiters.
+/// The parked pushes then fail with `PushError::Closed`, and the parked pops
+/// take the remaining elements before failing with `TryPopError::Closed`.
+pub struct BlockingQueue<Q> {
+    queue: Q,
+    lock: Mutex<()>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    waiting_pushers: AtomicUsize,
+    waiting_poppers: AtomicUsize,
+    closed: AtomicBool,
+}
+
+impl<Q> BlockingQueue<Q> {
+    pub fn new(queue: Q) -> Self {
+        Self {
+            queue,
+            lock: Mutex::new(()),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            waiting_pushers: AtomicUsize::new(0),
+            waiting_poppers: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    fn notify(&self, waiters: &AtomicUsize, condvar: &Condvar) {
+        if Self::waiters_after_fence(waiters) != 0 {
+            let _guard = self.lock.lock().unwrap();
+            // the last pushes in flight make the closed queue drained for all the waiters
+            if self.closed.load(Ordering::SeqCst) {
+                condvar.notify_all();
+            } else {
+                condvar.notify_one();
+            }
+        }
+    }
+
+    /// Pairs with the fence in `wait`, making sure that either we see
+    /// the waiter, or the waiter sees the result of our operation.
+    #[cfg(not(feature = "tsan"))]
+    fn waiters_after_fence(waiters: &AtomicUsize) -> usize {
+        super::qstd::sync::atomic::fence(Ordering::SeqCst);
+        waiters.load(Ordering::SeqCst)
+    }
+
+    /// ThreadSanitizer doesn't model standalone fences, so synchronize
+    /// on the counter itself: the RMW reads the latest value in its order.
+    #[cfg(feature = "tsan")]
+    fn waiters_after_fence(waiters: &AtomicUsize) -> usize {
+        waiters.fetch_add(0, Ordering::SeqCst)
+    }
+
+    /// Park until `attempt` succeeds, or the deadline passes.
+    fn wait<R>(
+        &self,
+        waiters: &AtomicUsize,
+        condvar: &Condvar,
+        deadline: Option<Instant>,
+        mut attempt: impl FnMut() -> Option<R>,
+    ) -> Option<R> {
+        let mut guard = self.lock.lock().unwrap();
+        loop {
+            waiters.fetch_add(1, Ordering::SeqCst);
+            // pairs with `waiters_after_fence` in `notify`
+            #[cfg(not(feature = "tsan"))]
+            super::qstd::sync::atomic::fence(Ordering::SeqCst);
+            let result = attempt();
+            if result.is_none() {
+                match deadline {
+                    None => guard = condvar.wait(guard).unwrap(),
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            waiters.fetch_sub(1, Ordering::SeqCst);
+                            return None;
+                        }
+                        guard = condvar.wait_timeout(guard, deadline - now).unwrap().0;
+                    }
+                }
+            }
+            waiters.fetch_sub(1, Ordering::SeqCst);
+            if result.is_some() {
+                return result;
+            }
+        }
+    }
+
+    fn push_until<T>(&self, value: T, deadline: Option<Instant>) -> Result<(), super::PushError<T>>
+    where
+        Q: super::SynQueue<T>,
+    {
+        let mut value = match self.queue.push(value) {
+            Ok(()) => {
+                self.notify(&self.waiting_poppers, &self.not_empty);
+                return Ok(());
+            }
+            Err(value) => Some(value),
+        };
+        let pushed = self.wait(
+            &self.waiting_pushers,
+            &self.not_full,
+            deadline,
+            || match self.queue.push(value.take().unwrap()) {
+                Ok(()) => Some(Ok(())),
+                Err(rejected) if self.closed.load(Ordering::SeqCst) => Some(Err(rejected)),
+                Err(rejected) => {
+                    value = Some(rejected);
+                    None
+                }
+            },
+        );
+        match pushed {
+            Some(Ok(())) => {
+                self.notify(&self.waiting_poppers, &self.not_empty);
+                Ok(())
+            }
+            Some(Err(value)) => {
+                // the failed push could have kept a closed queue from looking drained
+                self.notify(&self.waiting_poppers, &self.not_empty);
+                Err(super::PushError::Closed(value))
+            }
+            None => Err(super::PushError::Full(value.unwrap())),
+        }
+    }
+
+    fn pop_until<T>(&self, deadline: Option<Instant>) -> Result<T, super::TryPopError>
+    where
+        Q: super::SynQueue<T>,
+    {
+        let value = match self.queue.try_pop() {
+            Ok(value) => value,
+            Err(super::TryPopError::Closed) => return Err(super::TryPopError::Closed),
+            Err(_) => self
+                .wait(
+                    &self.waiting_poppers,
+                    &self.not_empty,
+                    deadline,
+                    || match self.queue.try_pop() {
+                        Err(super::TryPopError::Empty | super::TryPopError::Busy) => None,
+                        result => Some(result),
+                    },
+                )
+                .unwrap_or(Err(super::TryPopError::Empty))?,
+        };
+        self.notify(&self.waiting_pushers, &self.not_full);
+        Ok(value)
+    }
+
+    /// Push a value, parking the thread while the queue is full.
+    /// Returns the value back if the queue is closed.
+    pub fn push_blocking<T>(&self, value: T) -> Result<(), T>
+    where
+        Q: super::SynQueue<T>,
+    {
+        self.push_until(value, None)
+            .map_err(super::PushError::into_inner)
+    }
+
+    /// Pop a value, parking the thread while the queue is empty.
+    /// Fails with `TryPopError::Closed` once the queue is closed and drained.
+    pub fn pop_blocking<T>(&self) -> Result<T, super::TryPopError>
+    where
+        Q: super::SynQueue<T>,
+    {
+        self.pop_until(None)
+    }
+
+    /// Push a value, parking the thread while the queue is full, up to the timeout.
+    /// Returns the value back if the queue is still full after the timeout, or closed.
+    pub fn push_timeout<T>(&self, value: T, timeout: Duration) -> Result<(), super::PushError<T>>
+    where
+        Q: super::SynQueue<T>,
+    {
+        self.push_until(value, Some(Instant::now() + timeout))
+    }
+
+    /// Pop a value, parking the thread while the queue is empty, up to the timeout.
+    /// Fails with `TryPopError::Empty` after the timeout, or `TryPopError::Closed`
+    /// once the queue is closed and drained.
+    pub fn pop_timeout<T>(&self, timeout: Duration) -> Result<T, super::TryPopError>
+    where
+        Q: super::SynQueue<T>,
+    {
+        self.pop_until(Some(Instant::now() + timeout))
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> super::SynQueue<T> for BlockingQueue<Q> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)?;
+        self.notify(&self.waiting_poppers, &self.not_empty);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        let value = self.queue.pop()?;
+        self.notify(&self.waiting_pushers, &self.not_full);
+        Some(value)
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let value = self.queue.try_pop()?;
+        self.notify(&self.waiting_pushers, &self.not_full);
+        Ok(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<T, Q: super::CloseQueue<T>> super::CloseQueue<T> for BlockingQueue<Q> {
+    fn close(&self) {
+        self.queue.close();
+        self.closed.store(true, Ordering::SeqCst);
+        let _guard = self.lock.lock().unwrap();
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+}
+
+#[test]
+fn ping_pong() {
+    use super::qstd::{sync::Arc, thread};
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let bq = Arc::new(BlockingQueue::new(super::DoubleQueue::new(1)));
+        let bq2 = Arc::clone(&bq);
+        let consumer = thread::spawn(move || (bq2.pop_blocking(), bq2.pop_blocking()));
+        bq.push_blocking(1).unwrap();
+        bq.push_blocking(2).unwrap();
+        assert_eq!(consumer.join().unwrap(), (Ok(1), Ok(2)));
+        assert!(bq.is_empty());
+    })
+}
+
+#[test]
+fn try_pop_closed() {
+    use super::{CloseQueue as _, NewQueue as _, SynQueue as _, TryPopError};
+    super::loom::model(|| {
+        let cq = super::Closable::new(super::DoubleQueue::<i32>::new(1));
+        cq.close();
+        // the error of the inner queue is passed through
+        let bq = BlockingQueue::new(cq);
+        assert_eq!(bq.try_pop(), Err(TryPopError::Closed));
+    })
+}
+
+// Shuttle doesn't model the passage of time, so the timed waits never wake up.
+#[cfg(not(feature = "shuttle"))]
+#[test]
+fn timeouts() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let bq = BlockingQueue::new(super::DoubleQueue::new(1));
+        assert_eq!(
+            bq.pop_timeout(Duration::from_millis(1)),
+            Err::<i32, _>(super::TryPopError::Empty)
+        );
+        bq.push_timeout(1, Duration::from_millis(1)).unwrap();
+        assert_eq!(
+            bq.push_timeout(2, Duration::from_millis(1)),
+            Err(super::PushError::Full(2))
+        );
+        assert!(bq.is_full());
+        assert_eq!(bq.pop_timeout(Duration::ZERO), Ok(1));
+    })
+}
+
+#[test]
+fn close_wakes_up() {
+    use super::qstd::{sync::Arc, thread};
+    use super::{CloseQueue as _, NewQueue as _, TryPopError};
+    super::loom::model(|| {
+        let bq = Arc::new(BlockingQueue::new(super::Closable::new(
+            super::DoubleQueue::new(1),
+        )));
+        let bq2 = Arc::clone(&bq);
+        let consumer = thread::spawn(move || (bq2.pop_blocking(), bq2.pop_blocking()));
+        bq.push_blocking(1).unwrap();
+        bq.close();
+        assert_eq!(bq.push_blocking(2), Err(2));
+        assert_eq!(consumer.join().unwrap(), (Ok(1), Err(TryPopError::Closed)));
+    })
+}
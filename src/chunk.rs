@@ -0,0 +1,201 @@
+use super::{DoubleQueue, NewQueue as _, SynQueue as _};
+use std::{alloc, mem, ops, ptr::NonNull, slice};
+
+/// Alignment of every chunk, suitable for GPU buffer copies.
+pub const CHUNK_ALIGNMENT: usize = 256;
+
+/// Queue of fixed-size, aligned byte chunks.
+///
+/// ## Principle
+/// The chunk memory is allocated once, and the queue only moves chunk indices
+/// around: a `DoubleQueue` of free chunks, and another one of filled chunks.
+/// Producers reserve a free chunk, write into it in place, and commit it.
+/// Consumers pop a filled chunk, read it in place, and drop it to release it.
+/// Since each index is owned by exactly one party at a time, no copies or locks are needed.
+pub struct ChunkQueue {
+    memory: NonNull<u8>,
+    layout: alloc::Layout,
+    chunk_size: usize,
+    free: DoubleQueue<usize>,
+    filled: DoubleQueue<usize>,
+}
+
+unsafe impl Send for ChunkQueue {}
+unsafe impl Sync for ChunkQueue {}
+
+impl ChunkQueue {
+    /// Create a queue of `count` chunks, each at least `chunk_size` bytes long.
+    ///
+    /// The size is rounded up to a multiple of `CHUNK_ALIGNMENT`.
+    pub fn new(chunk_size: usize, count: usize) -> Self {
+        Self::try_new(chunk_size, count).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Create a queue, reporting invalid sizes and allocation failures instead of panicking.
+    ///
+    /// The chunks start zeroed.
+    pub fn try_new(chunk_size: usize, count: usize) -> Result<Self, super::NewError> {
+        if chunk_size == 0 || count == 0 {
+            return Err(super::NewError::InvalidCapacity(count));
+        }
+        let chunk_size = chunk_size
+            .checked_next_multiple_of(CHUNK_ALIGNMENT)
+            .ok_or(super::NewError::AllocationFailed)?;
+        let layout = chunk_size
+            .checked_mul(count)
+            .and_then(|size| alloc::Layout::from_size_align(size, CHUNK_ALIGNMENT).ok())
+            .ok_or(super::NewError::AllocationFailed)?;
+        let free = DoubleQueue::try_new(count)?;
+        let filled = DoubleQueue::try_new(count)?;
+        let memory = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+            .ok_or(super::NewError::AllocationFailed)?;
+        for index in 0..count {
+            free.push(index).unwrap();
+        }
+        Ok(Self {
+            memory,
+            layout,
+            chunk_size,
+            free,
+            filled,
+        })
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filled.is_empty()
+    }
+
+    /// Reserve a free chunk for writing, if there is one.
+    ///
+    /// The chunk is queued on `WriteChunk::commit`, or released if dropped.
+    pub fn reserve(&self) -> Option<WriteChunk<'_>> {
+        let index = self.free.pop()?;
+        Some(WriteChunk { queue: self, index })
+    }
+
+    /// Pop the oldest committed chunk for reading, if there is one.
+    ///
+    /// The chunk is released when dropped.
+    pub fn pop(&self) -> Option<ReadChunk<'_>> {
+        let index = self.filled.pop()?;
+        Some(ReadChunk { queue: self, index })
+    }
+
+    fn chunk_ptr(&self, index: usize) -> *mut u8 {
+        unsafe { self.memory.as_ptr().add(index * self.chunk_size) }
+    }
+
+    fn release(&self, index: usize) {
+        // can't fail, since there is a slot for every chunk
+        self.free.push(index).unwrap();
+    }
+}
+
+impl Drop for ChunkQueue {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.memory.as_ptr(), self.layout) };
+    }
+}
+
+/// Chunk reserved for writing.
+pub struct WriteChunk<'a> {
+    queue: &'a ChunkQueue,
+    index: usize,
+}
+
+impl WriteChunk<'_> {
+    /// Queue the chunk for the consumers.
+    pub fn commit(self) {
+        let index = self.index;
+        let queue = self.queue;
+        mem::forget(self);
+        queue.filled.push(index).unwrap();
+    }
+}
+
+impl ops::Deref for WriteChunk<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        let ptr = self.queue.chunk_ptr(self.index);
+        unsafe { slice::from_raw_parts(ptr, self.queue.chunk_size) }
+    }
+}
+
+impl ops::DerefMut for WriteChunk<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        let ptr = self.queue.chunk_ptr(self.index);
+        unsafe { slice::from_raw_parts_mut(ptr, self.queue.chunk_size) }
+    }
+}
+
+impl Drop for WriteChunk<'_> {
+    fn drop(&mut self) {
+        self.queue.release(self.index);
+    }
+}
+
+/// Chunk popped for reading.
+pub struct ReadChunk<'a> {
+    queue: &'a ChunkQueue,
+    index: usize,
+}
+
+impl ops::Deref for ReadChunk<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        let ptr = self.queue.chunk_ptr(self.index);
+        unsafe { slice::from_raw_parts(ptr, self.queue.chunk_size) }
+    }
+}
+
+impl Drop for ReadChunk<'_> {
+    fn drop(&mut self) {
+        self.queue.release(self.index);
+    }
+}
+
+#[test]
+fn staging() {
+    super::loom::model(|| {
+        let cq = ChunkQueue::new(100, 2);
+        assert_eq!(cq.chunk_size(), CHUNK_ALIGNMENT);
+        let mut first = cq.reserve().unwrap();
+        assert_eq!(first.as_ptr() as usize % CHUNK_ALIGNMENT, 0);
+        assert!(first.iter().all(|&b| b == 0));
+        first.fill(1);
+        let mut second = cq.reserve().unwrap();
+        second.fill(2);
+        assert!(cq.reserve().is_none());
+        drop(second);
+        first.commit();
+        let mut third = cq.reserve().unwrap();
+        third.fill(3);
+        third.commit();
+        assert!(cq.pop().unwrap().iter().all(|&b| b == 1));
+        assert!(cq.pop().unwrap().iter().all(|&b| b == 3));
+        assert!(cq.pop().is_none());
+        assert!(cq.is_empty());
+    })
+}
+
+#[test]
+fn oversized() {
+    use super::NewError;
+    let size = usize::MAX - CHUNK_ALIGNMENT;
+    assert_eq!(
+        ChunkQueue::try_new(0, 2).err(),
+        Some(NewError::InvalidCapacity(2))
+    );
+    assert_eq!(
+        ChunkQueue::try_new(size, 1).err(),
+        Some(NewError::AllocationFailed)
+    );
+    assert_eq!(
+        ChunkQueue::try_new(usize::MAX / 4, 8).err(),
+        Some(NewError::AllocationFailed)
+    );
+}
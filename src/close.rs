@@ -0,0 +1,171 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+
+const CLOSED: usize = 1 << (usize::BITS - 1);
+
+/// Closed flag of a queue, combined with the number of pushes in flight.
+///
+/// Keeping them in the same atomic allows the consumers to tell if more
+/// elements can still arrive: once the queue is closed and there are no pushes
+/// in flight, an empty queue is going to stay empty.
+pub(crate) struct Gate(AtomicUsize);
+
+/// Push in flight, which is finished when dropped.
+pub(crate) struct Pass<'a>(&'a AtomicUsize);
+
+impl Gate {
+    pub(crate) fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    /// Start a push, unless the gate is closed.
+    ///
+    /// A closed gate is never entered, not even for a moment, so that a consumer
+    /// can't see it as not drained after the last pass is dropped.
+    pub(crate) fn enter(&self) -> Option<Pass<'_>> {
+        let mut count = self.0.load(Ordering::SeqCst);
+        loop {
+            if count & CLOSED != 0 {
+                return None;
+            }
+            match self
+                .0
+                .compare_exchange_weak(count, count + 1, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return Some(Pass(&self.0)),
+                Err(other) => count = other,
+            }
+        }
+    }
+
+    pub(crate) fn close(&self) {
+        self.0.fetch_or(CLOSED, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.load(Ordering::SeqCst) & CLOSED != 0
+    }
+
+    /// Check if the gate is closed, and there are no pushes in flight.
+    pub(crate) fn is_drained(&self) -> bool {
+        self.0.load(Ordering::SeqCst) == CLOSED
+    }
+}
+
+impl Drop for Pass<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Queue wrapper that can be closed, telling the consumers that no more elements are coming.
+///
+/// Closing is opt-in, since every push through the wrapper also enters and leaves
+/// the gate, which is two more contended atomic operations.
+/// All the pushes need to go through the wrapper, since the ones done
+/// on the inner queue directly are not stopped by closing it.
+pub struct Closable<Q> {
+    queue: Q,
+    gate: Gate,
+}
+
+impl<Q> Closable<Q> {
+    pub fn new(queue: Q) -> Self {
+        Self {
+            queue,
+            gate: Gate::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+}
+
+impl<T: Send, const N: usize> Closable<super::double::DoubleQueue<T, N>> {
+    /// Push an element, evicting the oldest one if the queue is full.
+    ///
+    /// On a closed queue, the value is returned as `PushError::Closed`,
+    /// so it can't be mistaken for an evicted element.
+    pub fn force_push(&self, value: T) -> Result<Option<T>, super::PushError<T>> {
+        let Some(_pass) = self.gate.enter() else {
+            return Err(super::PushError::Closed(value));
+        };
+        Ok(self.queue.force_push(value))
+    }
+}
+
+impl<T, Q: super::NewQueue<T>> super::NewQueue<T> for Closable<Q> {
+    fn new(capacity: usize) -> Self {
+        Self::new(Q::new(capacity))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        Q::try_new(capacity).map(Self::new)
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> super::SynQueue<T> for Closable<Q> {
+    fn push(&self, value: T) -> Result<(), T> {
+        let Some(_pass) = self.gate.enter() else {
+            return Err(value);
+        };
+        self.queue.push(value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        match self.queue.try_pop() {
+            Err(super::TryPopError::Empty) if self.gate.is_drained() && self.queue.is_empty() => {
+                Err(super::TryPopError::Closed)
+            }
+            other => other,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<T, Q: super::ExtendQueue<T>> super::ExtendQueue<T> for Closable<Q> {
+    fn try_extend(&self, values: &mut Vec<T>) -> usize {
+        match self.gate.enter() {
+            Some(_pass) => self.queue.try_extend(values),
+            None => 0,
+        }
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> super::CloseQueue<T> for Closable<Q> {
+    fn close(&self) {
+        self.gate.close();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.gate.is_closed()
+    }
+}
+
+#[test]
+fn force_push() {
+    use super::{CloseQueue as _, NewQueue as _, PushError, SynQueue as _};
+    super::loom::model(|| {
+        let cq = Closable::new(super::DoubleQueue::new(1));
+        assert_eq!(cq.force_push(1), Ok(None));
+        assert_eq!(cq.force_push(2), Ok(Some(1)));
+        cq.close();
+        assert_eq!(cq.force_push(3), Err(PushError::Closed(3)));
+        assert_eq!(cq.pop(), Some(2));
+    })
+}
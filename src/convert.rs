@@ -0,0 +1,112 @@
+use super::{AxelQueue, DoubleQueue, MaskedQueue, NewError, NewQueue, SynQueue};
+use std::fmt;
+
+/// Failed conversion into a queue that doesn't support the capacity.
+///
+/// The source queue is given back with all its elements.
+pub struct ConvertError<S> {
+    pub error: NewError,
+    pub source: S,
+}
+
+impl<S> fmt::Debug for ConvertError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConvertError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> fmt::Display for ConvertError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to convert the queue: {}", self.error)
+    }
+}
+
+impl<S> std::error::Error for ConvertError<S> {}
+
+/// Move the elements into a fresh queue of another type.
+fn convert<T, S: SynQueue<T>, D: NewQueue<T>>(
+    source: S,
+    capacity: usize,
+) -> Result<D, ConvertError<S>> {
+    let target = match D::try_new(capacity) {
+        Ok(target) => target,
+        Err(error) => return Err(ConvertError { error, source }),
+    };
+    while let Some(value) = source.pop() {
+        if target.push(value).is_err() {
+            unreachable!("Target capacity is too small");
+        }
+    }
+    Ok(target)
+}
+
+// The wait strategy and the drop order are not carried over,
+// the target queue is created with the defaults. The capacity is preserved.
+macro_rules! impl_from {
+    ($source:ident => $target:ident) => {
+        impl<T: Send> From<$source<T>> for $target<T> {
+            fn from(source: $source<T>) -> Self {
+                let capacity = source.capacity();
+                convert(source, capacity).unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+}
+
+// `MaskedQueue` and `AxelQueue` support smaller capacities than `DoubleQueue`.
+macro_rules! impl_try_from {
+    ($source:ident => $target:ident) => {
+        impl<T: Send> TryFrom<$source<T>> for $target<T> {
+            type Error = ConvertError<$source<T>>;
+            fn try_from(source: $source<T>) -> Result<Self, Self::Error> {
+                let capacity = source.capacity();
+                convert(source, capacity)
+            }
+        }
+    };
+}
+
+impl_from!(AxelQueue => DoubleQueue);
+impl_try_from!(AxelQueue => MaskedQueue);
+impl_try_from!(DoubleQueue => AxelQueue);
+impl_try_from!(DoubleQueue => MaskedQueue);
+impl_try_from!(MaskedQueue => AxelQueue);
+impl_from!(MaskedQueue => DoubleQueue);
+
+#[test]
+fn conversions() {
+    super::loom::model(|| {
+        let dq = DoubleQueue::new(3);
+        dq.push(1).unwrap();
+        dq.push(2).unwrap();
+        let mq = MaskedQueue::try_from(dq).unwrap();
+        assert_eq!(mq.capacity(), 3);
+        let aq = AxelQueue::try_from(mq).unwrap();
+        assert_eq!(aq.capacity(), 3);
+        let dq = DoubleQueue::from(aq);
+        assert_eq!(dq.try_iter().collect::<Vec<_>>(), [1, 2]);
+    })
+}
+
+#[test]
+fn unsupported_capacity() {
+    super::loom::model(|| {
+        let capacity = super::masked::INDEX_MASK;
+        let dq = DoubleQueue::<u8>::new(capacity);
+        dq.push(1).unwrap();
+        let Err(error) = MaskedQueue::try_from(dq) else {
+            panic!("the capacity is not supported by MaskedQueue");
+        };
+        assert_eq!(error.error, NewError::InvalidCapacity(capacity));
+        assert_eq!(error.source.pop(), Some(1));
+
+        let capacity = AxelQueue::<u8>::MAX_CAPACITY + 1;
+        let dq = DoubleQueue::<u8>::new(capacity);
+        let Err(error) = AxelQueue::try_from(dq) else {
+            panic!("the capacity is not supported by AxelQueue");
+        };
+        assert_eq!(error.error, NewError::InvalidCapacity(capacity));
+    })
+}
@@ -0,0 +1,82 @@
+use super::qstd::sync::atomic::{AtomicU64, Ordering};
+
+/// Queue wrapper counting the successful operations.
+///
+/// The counters are 64-bit and monotonic, independent of the wrapping ring indices,
+/// so they can be used for throughput accounting. Once the producers and consumers
+/// are stopped, `total_pushed() - total_popped()` is the number of elements left.
+pub struct Counted<Q> {
+    queue: Q,
+    pushed: AtomicU64,
+    popped: AtomicU64,
+}
+
+impl<Q> Counted<Q> {
+    pub fn new(queue: Q) -> Self {
+        Self {
+            queue,
+            pushed: AtomicU64::new(0),
+            popped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    pub fn total_pushed(&self) -> u64 {
+        self.pushed.load(Ordering::Relaxed)
+    }
+
+    pub fn total_popped(&self) -> u64 {
+        self.popped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> super::SynQueue<T> for Counted<Q> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)?;
+        self.pushed.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        let value = self.queue.pop()?;
+        self.popped.fetch_add(1, Ordering::Relaxed);
+        Some(value)
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let value = self.queue.try_pop()?;
+        self.popped.fetch_add(1, Ordering::Relaxed);
+        Ok(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn totals() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let cq = Counted::new(super::AxelQueue::new(2));
+        for i in 0..3 {
+            let _ = cq.push(i);
+        }
+        assert_eq!(cq.pop(), Some(0));
+        assert_eq!(cq.try_pop(), Ok(1));
+        assert_eq!(cq.try_pop(), Err(super::TryPopError::Empty));
+        assert_eq!(cq.total_pushed(), 2);
+        assert_eq!(cq.total_popped(), 2);
+    })
+}
@@ -0,0 +1,126 @@
+use super::qstd::sync::Mutex;
+use std::{cmp, collections::BinaryHeap, time::Instant};
+
+struct Entry<T, D> {
+    deadline: D,
+    // breaks ties in the FIFO order
+    serial: u64,
+    value: T,
+}
+
+impl<T, D: Ord> PartialEq for Entry<T, D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+impl<T, D: Ord> Eq for Entry<T, D> {}
+impl<T, D: Ord> PartialOrd for Entry<T, D> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T, D: Ord> Ord for Entry<T, D> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        // `BinaryHeap` is a max-heap, and we want the earliest deadline on top
+        (&other.deadline, other.serial).cmp(&(&self.deadline, self.serial))
+    }
+}
+
+struct Heap<T, D> {
+    entries: BinaryHeap<Entry<T, D>>,
+    next_serial: u64,
+}
+
+/// Bounded earliest-deadline-first queue.
+///
+/// Every element carries a deadline, and `pop` returns the one
+/// with the nearest deadline, in FIFO order for equal deadlines.
+/// Unlike the FIFO queues, this one is backed by a lock around a binary heap.
+pub struct DeadlineQueue<T, D = Instant> {
+    heap: Mutex<Heap<T, D>>,
+    capacity: usize,
+}
+
+impl<T, D: Ord> DeadlineQueue<T, D> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            heap: Mutex::new(Heap {
+                entries: BinaryHeap::with_capacity(capacity),
+                next_serial: 0,
+            }),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, deadline: D, value: T) -> Result<(), T> {
+        let mut heap = self.heap.lock().unwrap();
+        if heap.entries.len() == self.capacity {
+            return Err(value);
+        }
+        let serial = heap.next_serial;
+        heap.next_serial += 1;
+        heap.entries.push(Entry {
+            deadline,
+            serial,
+            value,
+        });
+        Ok(())
+    }
+
+    /// Pop the element with the nearest deadline.
+    pub fn pop(&self) -> Option<T> {
+        let mut heap = self.heap.lock().unwrap();
+        heap.entries.pop().map(|entry| entry.value)
+    }
+
+    /// Pop the element with the nearest deadline, but only if it's already expired by `now`.
+    pub fn pop_expired(&self, now: D) -> Option<T> {
+        let mut heap = self.heap.lock().unwrap();
+        if heap.entries.peek()?.deadline > now {
+            return None;
+        }
+        heap.entries.pop().map(|entry| entry.value)
+    }
+
+    /// Return the nearest deadline, if any.
+    pub fn next_deadline(&self) -> Option<D>
+    where
+        D: Clone,
+    {
+        let heap = self.heap.lock().unwrap();
+        heap.entries.peek().map(|entry| entry.deadline.clone())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.lock().unwrap().entries.is_empty()
+    }
+}
+
+#[test]
+fn smoke() {
+    super::loom::model(|| {
+        let dq = DeadlineQueue::<_, u32>::new(3);
+        dq.push(20, 'a').unwrap();
+        dq.push(10, 'b').unwrap();
+        dq.push(20, 'c').unwrap();
+        assert_eq!(dq.push(5, 'd'), Err('d'));
+        assert_eq!(dq.next_deadline(), Some(10));
+        assert_eq!(dq.pop(), Some('b'));
+        assert_eq!(dq.pop(), Some('a'));
+        assert_eq!(dq.pop(), Some('c'));
+        assert_eq!(dq.pop(), None);
+    })
+}
+
+#[test]
+fn expired() {
+    super::loom::model(|| {
+        let dq = DeadlineQueue::<_, u32>::new(4);
+        dq.push(10, 'a').unwrap();
+        dq.push(30, 'b').unwrap();
+        assert_eq!(dq.pop_expired(5), None);
+        assert_eq!(dq.pop_expired(20), Some('a'));
+        assert_eq!(dq.pop_expired(20), None);
+        assert!(!dq.is_empty());
+    })
+}
@@ -0,0 +1,108 @@
+use super::qstd::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+/// Queue wrapper suppressing consecutive duplicates.
+///
+/// A push whose key equals the key of the most recently pushed value is dropped,
+/// which is useful for noisy sensors and progress update streams.
+/// Pushes are serialized by a lock around the last key.
+pub struct DedupQueue<Q, K, F> {
+    queue: Q,
+    key: F,
+    last: Mutex<Option<K>>,
+    suppressed: AtomicUsize,
+}
+
+impl<Q, K: Clone> DedupQueue<Q, K, fn(&K) -> K> {
+    /// Create a wrapper comparing the values themselves.
+    pub fn new(queue: Q) -> Self {
+        Self::with_key(queue, K::clone)
+    }
+}
+
+impl<Q, K, F> DedupQueue<Q, K, F> {
+    /// Create a wrapper comparing the keys extracted from values.
+    pub fn with_key(queue: Q, key: F) -> Self {
+        Self {
+            queue,
+            key,
+            last: Mutex::new(None),
+            suppressed: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    /// Number of values dropped as duplicates.
+    pub fn suppressed(&self) -> usize {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, Q, K, F> super::SynQueue<T> for DedupQueue<Q, K, F>
+where
+    Q: super::SynQueue<T>,
+    K: PartialEq + Send,
+    F: Fn(&T) -> K + Send + Sync,
+{
+    fn push(&self, value: T) -> Result<(), T> {
+        let key = (self.key)(&value);
+        let mut last = self.last.lock().unwrap();
+        if last.as_ref() == Some(&key) {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.queue.push(value)?;
+        *last = Some(key);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn smoke() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let dq = DedupQueue::new(super::DoubleQueue::new(8));
+        for value in [1, 1, 2, 2, 2, 1] {
+            dq.push(value).unwrap();
+        }
+        assert_eq!(dq.suppressed(), 3);
+        assert_eq!(dq.pop(), Some(1));
+        assert_eq!(dq.pop(), Some(2));
+        assert_eq!(dq.pop(), Some(1));
+        assert_eq!(dq.pop(), None);
+    })
+}
+
+#[test]
+fn key() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let dq = DedupQueue::with_key(super::AxelQueue::new(8), |&(id, _): &(u32, char)| id);
+        dq.push((0, 'a')).unwrap();
+        dq.push((0, 'b')).unwrap();
+        dq.push((1, 'c')).unwrap();
+        assert_eq!(dq.pop(), Some((0, 'a')));
+        assert_eq!(dq.pop(), Some((1, 'c')));
+    })
+}
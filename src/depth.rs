@@ -0,0 +1,137 @@
+use super::qstd::sync::atomic::{AtomicIsize, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthEvent {
+    /// The depth has risen above the high threshold.
+    Above,
+    /// The depth has fallen below the low threshold.
+    Below,
+}
+
+/// Queue wrapper notifying about the depth crossing the thresholds.
+///
+/// Allows autoscaling and load-shedding logic to react on the queue depth
+/// without polling it in a loop. The callback is invoked on the thread
+/// doing the operation that crossed the threshold.
+///
+/// The depth is counted by the wrapper after each successful operation,
+/// so rejected pushes don't count, and the depth never exceeds the capacity.
+/// Therefore, the high threshold should be below the capacity.
+/// A pop racing with the push of the same element may be counted first,
+/// which is why the counter is signed.
+pub struct DepthMonitor<Q, F> {
+    queue: Q,
+    depth: AtomicIsize,
+    high: isize,
+    low: isize,
+    callback: F,
+}
+
+impl<Q, F: Fn(DepthEvent)> DepthMonitor<Q, F> {
+    pub fn new(queue: Q, high: usize, low: usize, callback: F) -> Self {
+        assert_ne!(low, 0);
+        assert!(
+            high >= low,
+            "high threshold {} is below the low {}",
+            high,
+            low
+        );
+        Self {
+            queue,
+            depth: AtomicIsize::new(0),
+            high: high as isize,
+            low: low as isize,
+            callback,
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed).max(0) as usize
+    }
+
+    fn increment(&self) {
+        if self.depth.fetch_add(1, Ordering::AcqRel) == self.high {
+            (self.callback)(DepthEvent::Above);
+        }
+    }
+
+    fn decrement(&self) {
+        if self.depth.fetch_sub(1, Ordering::AcqRel) == self.low {
+            (self.callback)(DepthEvent::Below);
+        }
+    }
+}
+
+impl<T, Q, F> super::SynQueue<T> for DepthMonitor<Q, F>
+where
+    Q: super::SynQueue<T>,
+    F: Fn(DepthEvent) + Send + Sync,
+{
+    fn push(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)?;
+        self.increment();
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        let value = self.queue.pop()?;
+        self.decrement();
+        Some(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn events() {
+    use super::qstd::sync::Mutex;
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let events = Mutex::new(Vec::new());
+        let dm = DepthMonitor::new(super::DoubleQueue::new(8), 2, 1, |event| {
+            events.lock().unwrap().push(event)
+        });
+        for i in 0..4 {
+            dm.push(i).unwrap();
+        }
+        assert_eq!(dm.depth(), 4);
+        while dm.pop().is_some() {}
+        drop(dm);
+        assert_eq!(
+            events.into_inner().unwrap(),
+            [DepthEvent::Above, DepthEvent::Below]
+        );
+    })
+}
+
+#[test]
+fn rejected() {
+    use super::qstd::sync::Mutex;
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let events = Mutex::new(Vec::new());
+        let dm = DepthMonitor::new(super::DoubleQueue::new(2), 2, 1, |event| {
+            events.lock().unwrap().push(event)
+        });
+        for i in 0..4 {
+            let _ = dm.push(i);
+        }
+        assert_eq!(dm.depth(), 2);
+        drop(dm);
+        assert!(events.into_inner().unwrap().is_empty());
+    })
+}
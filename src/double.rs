@@ -1,114 +1,199 @@
-use super::qstd::{cell::UnsafeCell, hint, sync::atomic::AtomicUsize, thread};
+use super::pad::CachePadded;
+use super::qstd::{hint, sync::atomic::AtomicUsize};
+use super::storage::{Slot, Slots, Storage};
 use std::mem;
 
-type Pointer = u32;
+/// Half of the machine word, so that the head and tail fit in one atomic.
+#[cfg(target_pointer_width = "64")]
+pub(super) type Pointer = u32;
+#[cfg(target_pointer_width = "32")]
+pub(super) type Pointer = u16;
 const _BITS_CHECK: usize = (mem::size_of::<usize>() == 2 * mem::size_of::<Pointer>()) as usize - 1;
 
 #[derive(Clone, Copy, Debug)]
-struct State {
-    head: Pointer,
-    tail: Pointer,
+pub(super) struct State {
+    pub(super) head: Pointer,
+    pub(super) tail: Pointer,
 }
 impl State {
     const HEAD_BITS: usize = mem::size_of::<Pointer>() * 8;
     #[inline(always)]
-    fn unpack(raw: usize) -> Self {
+    pub(super) fn unpack(raw: usize) -> Self {
         Self {
             head: raw as Pointer,
             tail: (raw >> Self::HEAD_BITS) as Pointer,
         }
     }
     #[inline(always)]
-    fn pack(self) -> usize {
+    pub(super) fn pack(self) -> usize {
         (self.head as usize) | ((self.tail as usize) << Self::HEAD_BITS)
     }
 }
 
-/// An internally syncrhonized (MPMC) queue.
-///
-/// ## Principle
-/// The basic problem with MPMC queues on arrays is synchronizing
-/// access to data with shifting of head/tail pointers.
-/// To address this, a general solution (you can find in `crossbeam-queue` and others)
-/// is to add one atomic bit per element, which serves as a barrier for advancing
-/// the queue pointers.
-///
-/// `DoubleQueue` tries a different approach. There is no extra bits.
-/// Instead, we are keeping 2 representations of the queue state: wide and narrow.
-/// Pushing advances `wide.head`, writes the data, and then makes `narrow.head` to catch up.
-/// Popping advances `narrow.tail`, reads the data, and then makes `wide.tail` to catch up.
-/// Every operation is thus sequence of CAS loop, data operation, another CAS loop.
+/// The part of a queue the position updates depend on.
+pub(super) trait Ring {
+    /// Number of distinct positions, at which they wrap around.
+    fn wrap(&self) -> usize;
+    /// Largest number of positions between the tail and the head.
+    fn max_len(&self) -> usize {
+        self.wrap() - 1
+    }
+    /// Wait for the `step`-th time until `done` returns true.
+    fn wait_for(&self, step: u32, done: impl Fn() -> bool);
+    /// Wake up the threads waiting in `wait_for` after a state has advanced.
+    fn wake(&self) {}
+    /// Count a failed CAS on one of the states.
+    fn failed_cas(&self) {}
+}
+
+/// Wide and narrow states of the queues built on the `DoubleQueue` algorithm.
 ///
-/// ## Internal invariants.
-/// Considering an infinite sequence (without wraparounds):
-///  `wide.tail <= narrow.tail <= narrow.head <= wide.head`
-pub struct DoubleQueue<T> {
-    wide: AtomicUsize,
-    narrow: AtomicUsize,
-    data: Box<[mem::MaybeUninit<UnsafeCell<T>>]>,
+/// It has a fixed layout, so it can be placed in memory shared between processes.
+#[repr(C)]
+pub(super) struct Positions {
+    /// State used first on push, last on pop.
+    pub(super) wide: CachePadded<AtomicUsize>,
+    /// State used first on pop, last on push.
+    pub(super) narrow: CachePadded<AtomicUsize>,
 }
 
-unsafe impl<T> Sync for DoubleQueue<T> {}
+impl Positions {
+    #[cfg(not(feature = "loom"))]
+    pub(super) const fn new(state: usize) -> Self {
+        Self {
+            wide: CachePadded::new(AtomicUsize::new(state)),
+            narrow: CachePadded::new(AtomicUsize::new(state)),
+        }
+    }
 
-impl<T> DoubleQueue<T> {
-    fn advance(&self, index: Pointer) -> Pointer {
-        if index as usize + 1 == self.data.len() {
+    #[cfg(feature = "loom")]
+    pub(super) fn new(state: usize) -> Self {
+        Self {
+            wide: CachePadded::new(AtomicUsize::new(state)),
+            narrow: CachePadded::new(AtomicUsize::new(state)),
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn advance(ring: &impl Ring, index: Pointer) -> Pointer {
+        if index as usize + 1 == ring.wrap() {
             0
         } else {
             index + 1
         }
     }
-}
 
-impl<T: Send> super::SynQueue<T> for DoubleQueue<T> {
-    fn new(capacity: usize) -> Self {
-        Self {
-            /// State used first on push, last on pop.
-            wide: AtomicUsize::new(0),
-            /// State used first on pop, last on push.
-            narrow: AtomicUsize::new(0),
-            /// In order to differentiate between empty and full states, we
-            /// are never going to use the full array, so get one extra element.
-            data: (0..=capacity).map(|_| mem::MaybeUninit::uninit()).collect(),
-        }
+    /// Number of positions between the tail and the head.
+    #[inline(always)]
+    pub(super) fn len(ring: &impl Ring, s: State) -> usize {
+        (s.head as usize + ring.wrap() - s.tail as usize) % ring.wrap()
     }
 
-    #[profiling::function]
-    fn push(&self, value: T) -> Result<(), T> {
-        // acqure a new position within the wide state
-        let mut state = self.wide.load(super::LOAD_ORDER);
-        let (head, next) = loop {
-            log::trace!("Push pre-CAS: {:x}", state);
+    /// Acquire a free position within the wide state.
+    ///
+    /// Starts from the `hint` state if given, and returns the new state with the positions.
+    pub(super) fn claim_free(
+        &self,
+        ring: &impl Ring,
+        hint: Option<usize>,
+    ) -> Option<(Pointer, Pointer, usize)> {
+        let mut state = hint.unwrap_or_else(|| self.wide.load(super::LOAD_ORDER));
+        let mut fresh = hint.is_none();
+        loop {
+            trace!("Push pre-CAS: {:x}", state);
             let s = State::unpack(state);
-            let next = self.advance(s.head);
-            if next == s.tail {
-                return Err(value);
+            if Self::len(ring, s) == ring.max_len() {
+                if fresh {
+                    return None;
+                }
+                // the hint is stale, check the actual state
+                state = self.wide.load(super::LOAD_ORDER);
+                fresh = true;
+                continue;
             }
+            let next = Self::advance(ring, s.head);
+            let new_state = State { head: next, ..s }.pack();
             match self.wide.compare_exchange_weak(
                 state,
-                State { head: next, ..s }.pack(),
+                new_state,
                 super::CAS_ORDER,
                 super::LOAD_ORDER,
             ) {
-                Ok(_) => break (s.head, next),
-                Err(other) => state = other,
+                Ok(_) => return Some((s.head, next, new_state)),
+                Err(other) => {
+                    ring.failed_cas();
+                    state = other;
+                    fresh = true;
+                }
             }
             hint::spin_loop();
-        };
+        }
+    }
 
-        log::trace!("Push success, next head = {:x}", next);
-        // write the data
-        unsafe {
-            super::UnsafeCellHelper::write(self.data.get_unchecked(head as usize).as_ptr(), value)
-        };
+    /// Acquire up to `max` oldest positions within the narrow state.
+    pub(super) fn claim_oldest(&self, ring: &impl Ring, max: usize) -> Option<(Pointer, Pointer)> {
+        self.claim_oldest_from(ring, max, None)
+            .map(|(tail, next, _)| (tail, next))
+    }
 
-        // advance the narrow state
-        state = self.narrow.load(super::LOAD_ORDER);
-        log::trace!("Push narrow state: {:x}", state);
+    /// Like `claim_oldest`, but starts from the `hint` state if given,
+    /// and also returns the new state.
+    pub(super) fn claim_oldest_from(
+        &self,
+        ring: &impl Ring,
+        max: usize,
+        hint: Option<usize>,
+    ) -> Option<(Pointer, Pointer, usize)> {
+        let mut state = hint.unwrap_or_else(|| self.narrow.load(super::LOAD_ORDER));
+        let mut fresh = hint.is_none();
+        loop {
+            trace!("Pop pre-CAS: {:x}", state);
+            let s = State::unpack(state);
+            if s.head == s.tail {
+                if fresh {
+                    return None;
+                }
+                // the hint is stale, check the actual state
+                state = self.narrow.load(super::LOAD_ORDER);
+                fresh = true;
+                continue;
+            }
+            let next = if max == 1 {
+                Self::advance(ring, s.tail)
+            } else {
+                let wrap = ring.wrap();
+                ((s.tail as usize + Self::len(ring, s).min(max)) % wrap) as Pointer
+            };
+            let new_state = State { tail: next, ..s }.pack();
+            match self.narrow.compare_exchange_weak(
+                state,
+                new_state,
+                super::CAS_ORDER,
+                super::LOAD_ORDER,
+            ) {
+                Ok(_) => return Some((s.tail, next, new_state)),
+                Err(other) => {
+                    ring.failed_cas();
+                    state = other;
+                    fresh = true;
+                }
+            }
+            hint::spin_loop();
+        }
+    }
+
+    /// Advance the narrow state over the written position.
+    pub(super) fn publish(&self, ring: &impl Ring, head: Pointer, next: Pointer) {
+        let state = self.narrow.load(super::LOAD_ORDER);
+        trace!("Push narrow state: {:x}", state);
         let mut s = State::unpack(state);
+        let mut step = 0;
         loop {
             if s.head != head {
-                thread::yield_now();
+                ring.wait_for(step, || {
+                    State::unpack(self.narrow.load(super::LOAD_ORDER)).head == head
+                });
+                step = step.saturating_add(1);
             }
             match self.narrow.compare_exchange_weak(
                 State { head, ..s }.pack(),
@@ -116,43 +201,382 @@ impl<T: Send> super::SynQueue<T> for DoubleQueue<T> {
                 super::CAS_ORDER,
                 super::LOAD_ORDER,
             ) {
-                Ok(_) => break,
+                Ok(_) => break ring.wake(),
                 Err(other) => {
-                    log::trace!("Push post-CAS: {:x}", other);
+                    ring.failed_cas();
+                    trace!("Push post-CAS: {:x}", other);
                     hint::spin_loop();
                     s = State::unpack(other);
                 }
             }
         }
+    }
 
-        // done
-        Ok(())
+    /// Advance the wide state over the read positions.
+    pub(super) fn release(&self, ring: &impl Ring, tail: Pointer, next: Pointer) {
+        let state = self.wide.load(super::LOAD_ORDER);
+        let mut s = State::unpack(state);
+        trace!("Pop wide state: {:x}", state);
+        let mut step = 0;
+        loop {
+            if s.tail != tail {
+                ring.wait_for(step, || {
+                    State::unpack(self.wide.load(super::LOAD_ORDER)).tail == tail
+                });
+                step = step.saturating_add(1);
+            }
+            match self.wide.compare_exchange_weak(
+                State { tail, ..s }.pack(),
+                State { tail: next, ..s }.pack(),
+                super::CAS_ORDER,
+                super::LOAD_ORDER,
+            ) {
+                Ok(_) => break ring.wake(),
+                Err(other) => {
+                    ring.failed_cas();
+                    trace!("Pop post-CAS: {:x}", other);
+                    hint::spin_loop();
+                    s = State::unpack(other);
+                }
+            }
+        }
     }
 
-    #[profiling::function]
-    fn pop(&self) -> Option<T> {
-        // acquire the oldest position within the narrow state
-        let mut state = self.narrow.load(super::LOAD_ORDER);
-        let (tail, next) = loop {
-            log::trace!("Pop pre-CAS: {:x}", state);
+    pub(super) fn is_empty(&self) -> bool {
+        let s = State::unpack(self.wide.load(super::LOAD_ORDER));
+        s.head == s.tail
+    }
+
+    pub(super) fn is_full(&self, ring: &impl Ring) -> bool {
+        Self::len(ring, State::unpack(self.wide.load(super::LOAD_ORDER))) == ring.max_len()
+    }
+}
+
+/// An internally syncrhonized (MPMC) queue.
+///
+/// ## Principle
+/// The basic problem with MPMC queues on arrays is synchronizing
+/// access to data with shifting of head/tail pointers.
+/// To address this, a general solution (you can find in `crossbeam-queue` and others)
+/// is to add one atomic bit per element, which serves as a barrier for advancing
+/// the queue pointers.
+///
+/// `DoubleQueue` tries a different approach. There is no extra bits.
+/// Instead, we are keeping 2 representations of the queue state: wide and narrow.
+/// Pushing advances `wide.head`, writes the data, and then makes `narrow.head` to catch up.
+/// Popping advances `narrow.tail`, reads the data, and then makes `wide.tail` to catch up.
+/// Every operation is thus sequence of CAS loop, data operation, another CAS loop.
+///
+/// ## Internal invariants.
+/// Considering an infinite sequence (without wraparounds):
+///  `wide.tail <= narrow.tail <= narrow.head <= wide.head`
+///
+/// ## Operations in flight
+/// A push counts the positions still claimed by the unfinished pushes and pops,
+/// so it can report the queue as full while a pop, which only sees the finished pushes,
+/// finds it empty. `try_pop` reports the latter as `TryPopError::Busy`.
+///
+/// ## Fixed capacity
+/// If `N` is non-zero, it's the capacity known at compile time, and `new` has to be
+/// called with the same value. The slots are then stored inline instead of on the heap,
+/// and the positions wrap around at `2 * N`, so all of the `N` slots are used.
+/// The constructors taking a buffer or allocation options are only available
+/// for the dynamic capacity.
+///
+/// ## Capacity limit
+/// Both positions share a single machine word, so the capacity can't exceed
+/// `MAX_CAPACITY`, which is `u32::MAX` on 64-bit targets, or half of it for
+/// the fixed capacity. Larger capacities are rejected by `try_new`
+/// instead of having the positions truncated.
+pub struct DoubleQueue<T, const N: usize = 0> {
+    positions: Positions,
+    data: Slots<Slot<T>, Storage<Slot<T>>, N>,
+    wait: super::WaitStrategy,
+    parking: super::wait::Parking,
+    stats: super::stats::Counters,
+    drop_order: super::DropOrder,
+}
+
+unsafe impl<T, const N: usize> Sync for DoubleQueue<T, N> {}
+
+impl<T, const N: usize> DoubleQueue<T, N> {
+    /// The largest capacity addressable by the packed positions.
+    pub const MAX_CAPACITY: usize = if N == 0 {
+        Pointer::MAX as usize
+    } else {
+        Pointer::MAX as usize / 2
+    };
+
+    /// Snapshot of the contention counters.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> super::Stats {
+        self.stats.snapshot()
+    }
+
+    /// Override the process-wide default wait strategy.
+    pub fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+
+    /// Set the order of dropping the remaining elements.
+    pub fn with_drop_order(mut self, order: super::DropOrder) -> Self {
+        self.drop_order = order;
+        self
+    }
+
+    /// Fails the build for a fixed capacity the positions can't address.
+    const FIXED_CAPACITY_CHECK: () = assert!(
+        N <= Self::MAX_CAPACITY,
+        "the fixed capacity doesn't fit the positions"
+    );
+
+    fn check_capacity(capacity: usize) -> Result<(), super::NewError> {
+        let () = Self::FIXED_CAPACITY_CHECK;
+        if (N != 0 && capacity != N) || capacity > Self::MAX_CAPACITY {
+            Err(super::NewError::InvalidCapacity(capacity))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn from_storage(data: Storage<Slot<T>>) -> Self {
+        Self::from_slots(Slots::new(data))
+    }
+
+    fn from_slots(data: Slots<Slot<T>, Storage<Slot<T>>, N>) -> Self {
+        Self {
+            positions: Positions::new(0),
+            data,
+            wait: super::WaitStrategy::default(),
+            parking: super::wait::Parking::new(),
+            stats: super::stats::Counters::default(),
+            drop_order: super::DropOrder::default(),
+        }
+    }
+}
+
+impl<T> DoubleQueue<T> {
+    /// Create a queue with the slot array allocated according to the options.
+    pub fn with_alloc_options(
+        capacity: usize,
+        options: super::AllocOptions,
+    ) -> Result<Self, super::NewError> {
+        Self::check_capacity(capacity)?;
+        let data = Storage::allocate(capacity + 1, options, mem::MaybeUninit::uninit)?;
+        Ok(Self::from_storage(data))
+    }
+
+    /// Decompose the queue into the data pointer, capacity, head, and tail indices.
+    ///
+    /// The data has `capacity + 1` slots, and the ones in `tail..head` range
+    /// (wrapping around) are initialized.
+    ///
+    /// Panics if the queue was created over a user buffer, or with `AllocOptions`.
+    #[cfg(not(feature = "loom"))]
+    pub fn into_raw_parts(self) -> (*mut T, usize, usize, usize) {
+        assert!(self.data.heap().is_boxed(), "The queue buffer is not boxed");
+        let this = mem::ManuallyDrop::new(self);
+        let state = this.positions.wide.load(super::LOAD_ORDER);
+        assert_eq!(state, this.positions.narrow.load(super::LOAD_ORDER));
+        let s = State::unpack(state);
+        let data = unsafe { std::ptr::read(&this.data) }.into_heap();
+        let capacity = data.len() - 1;
+        (
+            data.into_raw() as *mut T,
+            capacity,
+            s.head as usize,
+            s.tail as usize,
+        )
+    }
+
+    /// Reconstruct the queue from its raw parts.
+    ///
+    /// # Safety
+    /// The parts have to come from `DoubleQueue::into_raw_parts`.
+    #[cfg(not(feature = "loom"))]
+    pub unsafe fn from_raw_parts(data: *mut T, capacity: usize, head: usize, tail: usize) -> Self {
+        debug_assert!(head <= capacity && tail <= capacity);
+        let state = State {
+            head: head as Pointer,
+            tail: tail as Pointer,
+        };
+        Self {
+            positions: Positions::new(state.pack()),
+            data: Slots::new(
+                Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                    data as *mut Slot<T>,
+                    capacity + 1,
+                ))
+                .into(),
+            ),
+            wait: super::WaitStrategy::default(),
+            parking: super::wait::Parking::new(),
+            stats: super::stats::Counters::default(),
+            drop_order: super::DropOrder::default(),
+        }
+    }
+
+    /// Create an empty queue over a caller-provided buffer, such as a static array.
+    ///
+    /// The capacity is one less than the buffer length. The remaining elements
+    /// are dropped with the queue, but the buffer is left alone.
+    #[cfg(not(feature = "loom"))]
+    pub fn with_buffer(buffer: &'static mut [mem::MaybeUninit<T>]) -> Self {
+        unsafe { Self::with_raw_buffer(buffer.as_mut_ptr(), buffer.len()) }
+    }
+
+    /// Create an empty queue over a caller-provided buffer of `len` slots,
+    /// such as a DMA region or an arena allocation.
+    ///
+    /// # Safety
+    /// The buffer has to be valid for reads and writes, and not accessed
+    /// by anything else for as long as the queue is alive.
+    #[cfg(not(feature = "loom"))]
+    pub unsafe fn with_raw_buffer(data: *mut mem::MaybeUninit<T>, len: usize) -> Self {
+        assert!(len != 0 && len - 1 <= Self::MAX_CAPACITY);
+        Self::from_storage(Storage::borrowed(data as *mut Slot<T>, len))
+    }
+}
+
+impl<T, const N: usize> Ring for DoubleQueue<T, N> {
+    #[inline(always)]
+    fn wrap(&self) -> usize {
+        self.data.wrap()
+    }
+
+    #[inline(always)]
+    fn max_len(&self) -> usize {
+        self.data.capacity()
+    }
+
+    fn wait_for(&self, step: u32, done: impl Fn() -> bool) {
+        if self.wait.parks(step) {
+            self.stats.parked();
+            self.parking.park_until(done);
+        } else {
+            self.stats.wait(&self.wait, step);
+        }
+    }
+
+    fn wake(&self) {
+        if let super::WaitStrategy::SpinThenPark(_) = self.wait {
+            self.parking.unpark_all();
+        }
+    }
+
+    fn failed_cas(&self) {
+        self.stats.failed_cas();
+    }
+}
+
+impl<T: Send, const N: usize> DoubleQueue<T, N> {
+    /// Move as many elements as fit from the front of `values`, returning their count.
+    ///
+    /// The positions are acquired with a single CAS, and the elements
+    /// become visible to consumers all at once.
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn try_extend(&self, values: &mut Vec<T>) -> usize {
+        if values.is_empty() {
+            return 0;
+        }
+        let wrap = self.data.wrap();
+        let mut state = self.positions.wide.load(super::LOAD_ORDER);
+        let (head, count, next) = loop {
+            trace!("Extend pre-CAS: {:x}", state);
             let s = State::unpack(state);
-            if s.head == s.tail {
-                return None;
+            let free = self.data.capacity() - self.data.distance(s.tail as usize, s.head as usize);
+            let count = free.min(values.len());
+            if count == 0 {
+                return 0;
             }
-            let next = self.advance(s.tail);
-            match self.narrow.compare_exchange_weak(
+            let next = ((s.head as usize + count) % wrap) as Pointer;
+            match self.positions.wide.compare_exchange_weak(
                 state,
-                State { tail: next, ..s }.pack(),
+                State { head: next, ..s }.pack(),
                 super::CAS_ORDER,
                 super::LOAD_ORDER,
             ) {
-                Ok(_) => break (s.tail, next),
-                Err(other) => state = other,
+                Ok(_) => break (s.head as usize, count, next),
+                Err(other) => {
+                    self.stats.failed_cas();
+                    state = other;
+                }
             }
             hint::spin_loop();
         };
 
-        log::trace!("Pop success, next tail = {:x}", next);
+        trace!("Extend success, next head = {:x}", next);
+        for (i, value) in values.drain(..count).enumerate() {
+            let position = (head + i) % wrap;
+            unsafe {
+                super::UnsafeCellHelper::write(self.data.get_unchecked(position).as_ptr(), value)
+            };
+        }
+        self.positions.publish(self, head as Pointer, next);
+        count
+    }
+
+    /// Move up to `max` oldest elements into `out`, returning their count.
+    ///
+    /// The positions are acquired with a single CAS, so a draining consumer
+    /// pays for the synchronization once per batch instead of once per element.
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn pop_many(&self, out: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        // a failed allocation can't be allowed to leave the positions claimed
+        out.reserve(max.min(self.data.capacity()));
+        let Some((tail, next)) = self.positions.claim_oldest(self, max) else {
+            return 0;
+        };
+        let wrap = self.data.wrap();
+        let count = self.data.distance(tail as usize, next as usize);
+        trace!("Pop many success, next tail = {:x}", next);
+        for i in 0..count {
+            let position = (tail as usize + i) % wrap;
+            out.push(unsafe {
+                self.data
+                    .get_unchecked(position)
+                    .assume_init_read()
+                    .into_inner()
+            });
+        }
+        self.positions.release(self, tail, next);
+        count
+    }
+
+    /// Push an element, starting from the `hint` state if given.
+    ///
+    /// Returns the wide state after acquiring the position.
+    fn push_from(&self, hint: Option<usize>, value: T) -> Result<usize, T> {
+        // acqure a new position within the wide state
+        let Some((head, next, state)) = self.positions.claim_free(self, hint) else {
+            return Err(value);
+        };
+
+        trace!("Push success, next head = {:x}", next);
+        trace_slot!(head);
+        // write the data
+        unsafe {
+            super::UnsafeCellHelper::write(self.data.get_unchecked(head as usize).as_ptr(), value)
+        };
+
+        self.positions.publish(self, head, next);
+
+        // done
+        Ok(state)
+    }
+
+    /// Pop an element, starting from the `hint` state if given.
+    ///
+    /// Returns the narrow state after acquiring the position.
+    fn pop_from(&self, hint: Option<usize>) -> Option<(T, usize)> {
+        let (tail, next, state) = self.positions.claim_oldest_from(self, 1, hint)?;
+
+        trace!("Pop success, next tail = {:x}", next);
+        trace_slot!(tail);
         // read the data
         let value = unsafe {
             self.data
@@ -161,51 +585,196 @@ impl<T: Send> super::SynQueue<T> for DoubleQueue<T> {
                 .into_inner()
         };
 
-        // advance the wide state
-        state = self.wide.load(super::LOAD_ORDER);
-        let mut s = State::unpack(state);
-        log::trace!("Pop wide state: {:x}", state);
+        self.positions.release(self, tail, next);
+
+        // done
+        Some((value, state))
+    }
+
+    /// Create a token caching the wide state for `push_with_token`.
+    pub fn producer_token(&self) -> super::ProducerToken {
+        super::ProducerToken {
+            hint: self.positions.wide.load(super::LOAD_ORDER),
+        }
+    }
+
+    /// Create a token caching the narrow state for `pop_with_token`.
+    pub fn consumer_token(&self) -> super::ConsumerToken {
+        super::ConsumerToken {
+            hint: self.positions.narrow.load(super::LOAD_ORDER),
+        }
+    }
+
+    /// Push an element, starting from the state cached in the token.
+    ///
+    /// If this producer is the only one active, the position is acquired
+    /// without loading the wide state first.
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn push_with_token(&self, token: &mut super::ProducerToken, value: T) -> Result<(), T> {
+        token.hint = self.push_from(Some(token.hint), value)?;
+        Ok(())
+    }
+
+    /// Pop an element, starting from the state cached in the token.
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn pop_with_token(&self, token: &mut super::ConsumerToken) -> Option<T> {
+        let (value, state) = self.pop_from(Some(token.hint))?;
+        token.hint = state;
+        Some(value)
+    }
+
+    /// Push an element, evicting the oldest one if the queue is full.
+    ///
+    /// The eviction claims the oldest position like `pop` does, and then
+    /// advances the wide state for both the freed position and the new one
+    /// in the same CAS, so no other producer can take the space in between.
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn force_push(&self, mut value: T) -> Option<T> {
+        use super::SynQueue as _;
+
         loop {
-            if s.tail != tail {
-                thread::yield_now();
+            match self.push(value) {
+                Ok(()) => return None,
+                Err(other) => value = other,
             }
-            match self.wide.compare_exchange_weak(
-                State { tail, ..s }.pack(),
-                State { tail: next, ..s }.pack(),
-                super::CAS_ORDER,
-                super::LOAD_ORDER,
-            ) {
-                Ok(_) => break,
-                Err(other) => {
-                    log::trace!("Pop post-CAS: {:x}", other);
-                    hint::spin_loop();
-                    s = State::unpack(other);
+            let Some((tail, next_tail)) = self.positions.claim_oldest(self, 1) else {
+                // all the elements are in flight
+                hint::spin_loop();
+                continue;
+            };
+
+            trace!("Evict success, next tail = {:x}", next_tail);
+            let evicted = unsafe {
+                self.data
+                    .get_unchecked(tail as usize)
+                    .assume_init_read()
+                    .into_inner()
+            };
+
+            // advance the wide state, claiming a new position
+            let mut s = State::unpack(self.positions.wide.load(super::LOAD_ORDER));
+            let mut step = 0;
+            let (head, next) = loop {
+                if s.tail != tail {
+                    self.wait_for(step, || {
+                        State::unpack(self.positions.wide.load(super::LOAD_ORDER)).tail == tail
+                    });
+                    step = step.saturating_add(1);
                 }
-            }
+                let next = Positions::advance(self, s.head);
+                match self.positions.wide.compare_exchange_weak(
+                    State { tail, ..s }.pack(),
+                    State {
+                        head: next,
+                        tail: next_tail,
+                    }
+                    .pack(),
+                    super::CAS_ORDER,
+                    super::LOAD_ORDER,
+                ) {
+                    Ok(_) => {
+                        self.wake();
+                        break (s.head, next);
+                    }
+                    Err(other) => {
+                        self.stats.failed_cas();
+                        hint::spin_loop();
+                        s = State::unpack(other);
+                    }
+                }
+            };
+
+            unsafe {
+                super::UnsafeCellHelper::write(
+                    self.data.get_unchecked(head as usize).as_ptr(),
+                    value,
+                )
+            };
+            self.positions.publish(self, head, next);
+            return Some(evicted);
         }
+    }
+}
 
-        // done
-        Some(value)
+impl<T: Send, const N: usize> super::NewQueue<T> for DoubleQueue<T, N> {
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        Self::check_capacity(capacity)?;
+        // In order to differentiate between empty and full states, we
+        // are never going to use the full array, so get one extra element.
+        let data = if N == 0 {
+            super::try_alloc(capacity + 1, mem::MaybeUninit::uninit)?.into()
+        } else {
+            Storage::default()
+        };
+        Ok(Self::from_storage(data))
+    }
+}
+
+impl<T: Send, const N: usize> super::SynQueue<T> for DoubleQueue<T, N> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(queue = ?(self as *const Self)))
+    )]
+    fn push(&self, value: T) -> Result<(), T> {
+        self.push_from(None, value).map(|_| ())
+    }
+
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(queue = ?(self as *const Self)))
+    )]
+    fn pop(&self) -> Option<T> {
+        self.pop_from(None).map(|(value, _)| value)
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        self.pop().ok_or_else(|| {
+            let narrow = State::unpack(self.positions.narrow.load(super::LOAD_ORDER));
+            let wide = State::unpack(self.positions.wide.load(super::LOAD_ORDER));
+            // a push has acquired a position, but didn't advance the narrow state yet
+            if narrow.head != wide.head {
+                super::TryPopError::Busy
+            } else {
+                super::TryPopError::Empty
+            }
+        })
     }
 
     fn is_empty(&self) -> bool {
-        let state = self.wide.load(super::LOAD_ORDER);
-        let s = State::unpack(state);
-        s.head == s.tail
+        self.positions.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.positions.is_full(self)
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.capacity()
     }
 }
 
-impl<T> Drop for DoubleQueue<T> {
+impl<T: Send, const N: usize> super::ExtendQueue<T> for DoubleQueue<T, N> {
+    fn try_extend(&self, values: &mut Vec<T>) -> usize {
+        DoubleQueue::try_extend(self, values)
+    }
+}
+
+impl<T, const N: usize> Drop for DoubleQueue<T, N> {
     fn drop(&mut self) {
-        let state = self.wide.load(super::LOAD_ORDER);
-        log::trace!("Drop state: {:x}", state);
-        assert_eq!(state, self.narrow.load(super::LOAD_ORDER));
+        let state = self.positions.wide.load(super::LOAD_ORDER);
+        trace!("Drop state: {:x}", state);
+        assert_eq!(state, self.positions.narrow.load(super::LOAD_ORDER));
         let s = State::unpack(state);
-        let mut cursor = s.tail;
-        while cursor != s.head {
-            unsafe { self.data[cursor as usize].assume_init_drop() };
-            cursor = self.advance(cursor);
-        }
+        unsafe {
+            self.data
+                .drop_range(s.tail as usize, s.head as usize, self.drop_order)
+        };
     }
 }
 
@@ -214,12 +783,240 @@ fn overflow() {
     super::test_overflow::<DoubleQueue<i32>>();
 }
 
+#[test]
+fn max_capacity() {
+    use super::NewQueue as _;
+    super::loom::model(|| {
+        let capacity = DoubleQueue::<()>::MAX_CAPACITY + 1;
+        assert_eq!(
+            DoubleQueue::<()>::try_new(capacity).err(),
+            Some(super::NewError::InvalidCapacity(capacity))
+        );
+    })
+}
+
 #[test]
 fn smoke() {
     super::test_smoke::<DoubleQueue<i32>>();
 }
 
+#[test]
+fn close() {
+    super::test_close::<super::Closable<DoubleQueue<i32>>>();
+}
+
+#[test]
+fn try_pop_busy() {
+    use super::{NewQueue as _, SynQueue as _, TryPopError};
+    super::loom::model(|| {
+        let dq = super::DoubleQueue::new(2);
+        let empty = dq.positions.narrow.load(super::LOAD_ORDER);
+        dq.push(1).unwrap();
+        // pretend the push didn't advance the narrow state yet
+        let full = dq.positions.narrow.swap(empty, super::CAS_ORDER);
+        assert_eq!(dq.try_pop(), Err(TryPopError::Busy));
+        dq.positions.narrow.swap(full, super::CAS_ORDER);
+        assert_eq!(dq.try_pop(), Ok(1));
+    })
+}
+
+#[test]
+fn fixed() {
+    super::test_overflow::<DoubleQueue<i32, 2>>();
+    super::test_smoke::<DoubleQueue<i32, 16>>();
+    super::test_differential::<DoubleQueue<u32, 4>>();
+}
+
 #[test]
 fn barrage() {
     super::test_barrage::<DoubleQueue<usize>>();
 }
+
+#[test]
+fn try_extend() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let sq = super::DoubleQueue::new(4);
+        sq.push(0).unwrap();
+        let mut values = vec![1, 2, 3, 4, 5];
+        assert_eq!(sq.try_extend(&mut values), 3);
+        assert_eq!(values, [4, 5]);
+        assert_eq!(sq.try_extend(&mut values), 0);
+        assert_eq!(sq.pop(), Some(0));
+        assert_eq!(sq.pop(), Some(1));
+        assert_eq!(sq.try_extend(&mut values), 2);
+        assert!(values.is_empty());
+        assert_eq!(sq.try_iter().collect::<Vec<_>>(), [2, 3, 4, 5]);
+    })
+}
+
+#[test]
+fn pop_many() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let sq = super::DoubleQueue::new(4);
+        let mut out = Vec::new();
+        assert_eq!(sq.pop_many(&mut out, 4), 0);
+        for i in 0..4 {
+            sq.push(i).unwrap();
+        }
+        assert_eq!(sq.pop_many(&mut out, 3), 3);
+        sq.push(4).unwrap();
+        sq.push(5).unwrap();
+        assert_eq!(sq.pop_many(&mut out, 10), 3);
+        assert_eq!(out, [0, 1, 2, 3, 4, 5]);
+        assert!(sq.is_empty());
+    })
+}
+
+#[test]
+fn force_push() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let sq = super::DoubleQueue::new(2);
+        assert_eq!(sq.force_push(1), None);
+        assert_eq!(sq.force_push(2), None);
+        assert_eq!(sq.force_push(3), Some(1));
+        assert_eq!(sq.force_push(4), Some(2));
+        assert!(sq.is_full());
+        assert_eq!(sq.pop(), Some(3));
+        assert_eq!(sq.force_push(5), None);
+        assert_eq!(sq.pop(), Some(4));
+        assert_eq!(sq.pop(), Some(5));
+        assert_eq!(sq.pop(), None);
+    })
+}
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn raw_parts() {
+    super::test_raw_parts(
+        super::DoubleQueue::into_raw_parts,
+        |data, capacity, head, tail| unsafe {
+            super::DoubleQueue::from_raw_parts(data, capacity, head, tail)
+        },
+    );
+}
+
+#[test]
+fn park() {
+    use super::qstd::{sync::Arc, thread};
+    use super::{NewQueue as _, SynQueue as _};
+
+    super::loom::model(|| {
+        let dq = Arc::new(
+            super::DoubleQueue::new(2).with_wait_strategy(super::WaitStrategy::SpinThenPark(0)),
+        );
+        let empty = dq.positions.narrow.load(super::LOAD_ORDER);
+        dq.push(1).unwrap();
+        // pretend the push didn't advance the narrow state yet
+        let full = dq.positions.narrow.swap(empty, super::CAS_ORDER);
+        let dq2 = Arc::clone(&dq);
+        // this one has to park until the first push completes
+        let handle = thread::spawn(move || dq2.push(2).unwrap());
+        thread::yield_now();
+        dq.positions.narrow.swap(full, super::CAS_ORDER);
+        dq.wake();
+        handle.join().unwrap();
+        assert_eq!(dq.try_iter().collect::<Vec<_>>(), [1, 2]);
+    })
+}
+
+#[test]
+fn tokens() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let dq = super::DoubleQueue::new(2);
+        let mut producer = dq.producer_token();
+        let mut consumer = dq.consumer_token();
+        dq.push_with_token(&mut producer, 1).unwrap();
+        // make the token stale
+        dq.push(2).unwrap();
+        assert_eq!(dq.push_with_token(&mut producer, 3), Err(3));
+        assert_eq!(dq.pop_with_token(&mut consumer), Some(1));
+        dq.push_with_token(&mut producer, 3).unwrap();
+        assert_eq!(dq.pop(), Some(2));
+        assert_eq!(dq.pop_with_token(&mut consumer), Some(3));
+        assert_eq!(dq.pop_with_token(&mut consumer), None);
+        dq.push(4).unwrap();
+        assert_eq!(dq.pop_with_token(&mut consumer), Some(4));
+    })
+}
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn with_buffer() {
+    use super::SynQueue as _;
+    use std::sync::Arc;
+
+    super::loom::model(|| {
+        // stands for a static array
+        let buffer = Box::leak(Box::new([mem::MaybeUninit::uninit(); 3]));
+        let dq = super::DoubleQueue::with_buffer(buffer);
+        assert_eq!(dq.capacity(), 2);
+        dq.push(1).unwrap();
+        dq.push(2).unwrap();
+        assert_eq!(dq.push(3), Err(3));
+        assert_eq!(dq.pop(), Some(1));
+
+        let value = Arc::new(());
+        let mut arena = Vec::with_capacity(4);
+        arena.resize_with(4, mem::MaybeUninit::uninit);
+        let aq = unsafe { super::DoubleQueue::with_raw_buffer(arena.as_mut_ptr(), arena.len()) };
+        aq.push(Arc::clone(&value)).unwrap();
+        aq.push(Arc::clone(&value)).unwrap();
+        drop(aq);
+        assert_eq!(Arc::strong_count(&value), 1);
+        drop(arena);
+    })
+}
+
+#[test]
+fn alloc_options() {
+    use super::SynQueue as _;
+    use std::sync::Arc;
+    super::loom::model(|| {
+        let value = Arc::new(());
+        let options = super::AllocOptions::default().alignment(4096);
+        let dq = super::DoubleQueue::with_alloc_options(3, options).unwrap();
+        assert_eq!(dq.data.heap().as_ptr() as usize % 4096, 0);
+        dq.push(Arc::clone(&value)).unwrap();
+        drop(dq);
+        assert_eq!(Arc::strong_count(&value), 1);
+
+        let options = super::AllocOptions::default().huge_pages(true);
+        let dq = super::DoubleQueue::<u8>::with_alloc_options(3, options).unwrap();
+        assert_eq!(dq.data.heap().as_ptr() as usize % super::HUGE_PAGE_SIZE, 0);
+        assert_eq!(dq.capacity(), 3);
+        assert_eq!(
+            super::DoubleQueue::<u8>::with_alloc_options(3, options.alignment(3)).err(),
+            Some(super::NewError::InvalidAlignment(3))
+        );
+    })
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let dq = super::DoubleQueue::<i32>::new(2);
+        dq.push(1).unwrap();
+        assert_eq!(dq.pop(), Some(1));
+        // nothing to contend with
+        assert_eq!(dq.stats(), super::Stats::default());
+    })
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    // the failures can be spurious while operations are in flight, see the type docs
+    use super::lincheck::Failures;
+    super::lincheck::check_mpmc_with::<DoubleQueue<u32>>(Failures::InFlight);
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<DoubleQueue<u32>>();
+}
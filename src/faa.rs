@@ -0,0 +1,223 @@
+use super::qstd::{cell::UnsafeCell, sync::atomic::AtomicUsize};
+use std::mem;
+
+const PHASE_MASK: usize = 3;
+const EMPTY: usize = 0;
+const WRITING: usize = 1;
+const FULL: usize = 2;
+
+struct Slot<T> {
+    /// Ticket shifted by 2 bits, combined with the phase.
+    state: AtomicUsize,
+    value: mem::MaybeUninit<UnsafeCell<T>>,
+}
+
+/// An internally synchronized (MPMC) queue acquiring positions with fetch-and-add.
+///
+/// ## Principle
+/// This follows the CRQ design, which is the building block of LCRQ.
+/// Instead of CAS loops on the head and tail, every operation takes a ticket
+/// with a single `fetch_add`, so contended operations don't have to retry.
+/// The slot for a ticket is selected by its lower bits, and the slot state
+/// contains the ticket it's ready for, together with the phase.
+///
+/// A consumer that gets ahead of the producers "burns" the ticket by moving the slot
+/// to the next lap, so the late producer has to take another one.
+/// A producer that gets ahead of the consumers abandons its ticket, and reports the queue as full.
+/// As a result, `push` may spuriously fail when the queue is nearly full under contention.
+///
+/// Unlike LCRQ, closed rings aren't linked into a list, since that requires
+/// a double-width CAS. The queue is bounded, and the capacity has to be a power of two.
+pub struct FaaQueue<T> {
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    slots: Box<[Slot<T>]>,
+    wait: super::WaitStrategy,
+}
+
+unsafe impl<T> Sync for FaaQueue<T> {}
+
+impl<T> FaaQueue<T> {
+    /// Override the process-wide default wait strategy.
+    pub fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+
+    #[inline(always)]
+    fn slot(&self, ticket: usize) -> &Slot<T> {
+        unsafe { self.slots.get_unchecked(ticket & (self.slots.len() - 1)) }
+    }
+
+    fn len(&self) -> isize {
+        let tail = self.tail.load(super::LOAD_ORDER);
+        self.head.load(super::LOAD_ORDER).wrapping_sub(tail) as isize
+    }
+}
+
+/// Compare the ticket of a slot state with the given ticket, returning
+/// the phase and the ordering between the two.
+#[inline(always)]
+fn compare(state: usize, ticket: usize) -> (usize, isize) {
+    let diff = (state & !PHASE_MASK).wrapping_sub(ticket << 2) as isize;
+    (state & PHASE_MASK, diff)
+}
+
+impl<T: Send> super::NewQueue<T> for FaaQueue<T> {
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        if !capacity.is_power_of_two() {
+            return Err(super::NewError::InvalidCapacity(capacity));
+        }
+        let mut index = 0;
+        Ok(Self {
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            slots: super::try_alloc(capacity, || {
+                index += 1;
+                Slot {
+                    state: AtomicUsize::new(((index - 1) << 2) | EMPTY),
+                    value: mem::MaybeUninit::uninit(),
+                }
+            })?,
+            wait: super::WaitStrategy::default(),
+        })
+    }
+}
+
+impl<T: Send> super::SynQueue<T> for FaaQueue<T> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn push(&self, value: T) -> Result<(), T> {
+        loop {
+            if self.len() >= self.slots.len() as isize {
+                return Err(value);
+            }
+            let ticket = self.head.fetch_add(1, super::CAS_ORDER);
+            let slot = self.slot(ticket);
+            let state = slot.state.load(super::LOAD_ORDER);
+            trace!("Push ticket {:x}, state {:x}", ticket, state);
+            let claimed = match compare(state, ticket) {
+                (EMPTY, 0) => slot
+                    .state
+                    .compare_exchange(
+                        state,
+                        (ticket << 2) | WRITING,
+                        super::CAS_ORDER,
+                        super::LOAD_ORDER,
+                    )
+                    .is_ok(),
+                // the slot is still used on the previous lap
+                (_, diff) if diff < 0 => return Err(value),
+                _ => false,
+            };
+            if claimed {
+                unsafe { super::UnsafeCellHelper::write(slot.value.as_ptr(), value) };
+                slot.state.store((ticket << 2) | FULL, super::STORE_ORDER);
+                return Ok(());
+            }
+            // the ticket got burned by a consumer, take another one
+        }
+    }
+
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn pop(&self) -> Option<T> {
+        loop {
+            if self.len() <= 0 {
+                return None;
+            }
+            let ticket = self.tail.fetch_add(1, super::CAS_ORDER);
+            let slot = self.slot(ticket);
+            let next_lap = ticket.wrapping_add(self.slots.len()) << 2;
+            let mut step = 0;
+            loop {
+                let state = slot.state.load(super::LOAD_ORDER);
+                trace!("Pop ticket {:x}, state {:x}", ticket, state);
+                match compare(state, ticket) {
+                    (FULL, 0) => {
+                        let value = unsafe { slot.value.assume_init_read().into_inner() };
+                        slot.state.store(next_lap | EMPTY, super::STORE_ORDER);
+                        return Some(value);
+                    }
+                    (EMPTY, 0) => {
+                        // the producer is late, burn the ticket
+                        if slot
+                            .state
+                            .compare_exchange(
+                                state,
+                                next_lap | EMPTY,
+                                super::CAS_ORDER,
+                                super::LOAD_ORDER,
+                            )
+                            .is_ok()
+                        {
+                            break;
+                        }
+                    }
+                    // the producer is writing, or the previous lap is still in progress
+                    (_, diff) if diff <= 0 => {
+                        self.wait.wait(step);
+                        step = step.saturating_add(1);
+                    }
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() <= 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() >= self.slots.len() as isize
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+impl<T> Drop for FaaQueue<T> {
+    fn drop(&mut self) {
+        // abandoned and burned tickets leave gaps, so check every slot
+        let tail = self.tail.load(super::LOAD_ORDER);
+        let mask = self.slots.len() - 1;
+        for i in 0..self.slots.len() {
+            let slot = &mut self.slots[tail.wrapping_add(i) & mask];
+            if slot.state.load(super::LOAD_ORDER) & PHASE_MASK == FULL {
+                unsafe { slot.value.assume_init_drop() };
+            }
+        }
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<FaaQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<FaaQueue<i32>>();
+}
+
+#[test]
+fn barrage() {
+    super::test_barrage::<FaaQueue<usize>>();
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    // `push` may fail spuriously, see the type docs
+    use super::lincheck::Failures;
+    super::lincheck::check_mpmc_with::<FaaQueue<u32>>(Failures::SpuriousFull);
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<FaaQueue<u32>>();
+}
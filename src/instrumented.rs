@@ -0,0 +1,182 @@
+use hdrhistogram::Histogram;
+use std::{
+    marker::PhantomData,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Latency measured by `Instrumented`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Latency {
+    /// Duration of the successful pushes.
+    Push,
+    /// Duration of the successful pops.
+    Pop,
+    /// Time between pushing an element and popping it,
+    /// recorded only with `with_time_in_queue`.
+    InQueue,
+}
+
+/// Element stored by `Instrumented`, with the time of its push if needed.
+pub struct Stamped<T> {
+    pushed_at: Option<Instant>,
+    value: T,
+}
+
+/// Queue wrapper recording the latencies of the operations into HDR histograms.
+///
+/// The values are in nanoseconds, with 3 significant digits.
+/// Only the successful operations are recorded, so a consumer polling
+/// an empty queue doesn't skew the percentiles.
+///
+/// Recording takes a lock on the histogram, so this is meant for validating
+/// the latency targets, not for the hottest paths in production.
+/// Like `Padded`, the elements are wrapped for the inner queue,
+/// e.g. `Instrumented<T, MaskedQueue<Stamped<T>>>`.
+pub struct Instrumented<T, Q = super::DoubleQueue<Stamped<T>>> {
+    queue: Q,
+    time_in_queue: bool,
+    push: Mutex<Histogram<u64>>,
+    pop: Mutex<Histogram<u64>>,
+    in_queue: Mutex<Histogram<u64>>,
+    _marker: PhantomData<fn(T) -> T>,
+}
+
+fn new_histogram() -> Mutex<Histogram<u64>> {
+    Mutex::new(Histogram::new(3).unwrap())
+}
+
+fn record(histogram: &Mutex<Histogram<u64>>, duration: Duration) {
+    let nanos = duration.as_nanos().try_into().unwrap_or(u64::MAX);
+    histogram.lock().unwrap().saturating_record(nanos);
+}
+
+impl<T, Q> Instrumented<T, Q> {
+    pub fn new(queue: Q) -> Self {
+        Self {
+            queue,
+            time_in_queue: false,
+            push: new_histogram(),
+            pop: new_histogram(),
+            in_queue: new_histogram(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Also record the time the elements spend in the queue.
+    pub fn with_time_in_queue(mut self, enabled: bool) -> Self {
+        self.time_in_queue = enabled;
+        self
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    fn histogram_ref(&self, latency: Latency) -> &Mutex<Histogram<u64>> {
+        match latency {
+            Latency::Push => &self.push,
+            Latency::Pop => &self.pop,
+            Latency::InQueue => &self.in_queue,
+        }
+    }
+
+    /// Copy of the histogram with the recorded nanoseconds.
+    pub fn histogram(&self, latency: Latency) -> Histogram<u64> {
+        self.histogram_ref(latency).lock().unwrap().clone()
+    }
+
+    /// Latency at the given percentile, from 0 to 100.
+    pub fn percentile(&self, latency: Latency, percentile: f64) -> Duration {
+        let histogram = self.histogram_ref(latency).lock().unwrap();
+        Duration::from_nanos(histogram.value_at_percentile(percentile))
+    }
+
+    /// Clear all the histograms.
+    pub fn reset(&self) {
+        for latency in [Latency::Push, Latency::Pop, Latency::InQueue] {
+            self.histogram_ref(latency).lock().unwrap().reset();
+        }
+    }
+
+    fn popped(&self, start: Instant, stamped: Stamped<T>) -> T {
+        let now = Instant::now();
+        record(&self.pop, now - start);
+        if let Some(pushed_at) = stamped.pushed_at {
+            record(&self.in_queue, now - pushed_at);
+        }
+        stamped.value
+    }
+}
+
+impl<T: Send, Q: super::SynQueue<Stamped<T>>> super::SynQueue<T> for Instrumented<T, Q> {
+    fn push(&self, value: T) -> Result<(), T> {
+        let start = Instant::now();
+        let stamped = Stamped {
+            pushed_at: self.time_in_queue.then_some(start),
+            value,
+        };
+        self.queue.push(stamped).map_err(|stamped| stamped.value)?;
+        record(&self.push, start.elapsed());
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        let start = Instant::now();
+        let stamped = self.queue.pop()?;
+        Some(self.popped(start, stamped))
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let start = Instant::now();
+        let stamped = self.queue.try_pop()?;
+        Ok(self.popped(start, stamped))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<T: Send, Q: super::NewQueue<Stamped<T>>> super::NewQueue<T> for Instrumented<T, Q> {
+    fn new(capacity: usize) -> Self {
+        Self::new(Q::new(capacity))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        Q::try_new(capacity).map(Self::new)
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<Instrumented<i32>>();
+}
+
+#[test]
+fn latencies() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let iq = Instrumented::new(super::DoubleQueue::new(2)).with_time_in_queue(true);
+        iq.push(1).unwrap();
+        iq.push(2).unwrap();
+        assert_eq!(iq.push(3), Err(3));
+        assert_eq!(iq.pop(), Some(1));
+        assert_eq!(iq.histogram(Latency::Push).len(), 2);
+        assert_eq!(iq.histogram(Latency::Pop).len(), 1);
+        assert_eq!(iq.histogram(Latency::InQueue).len(), 1);
+        assert!(iq.percentile(Latency::InQueue, 50.0) >= iq.percentile(Latency::Pop, 0.0));
+        iq.reset();
+        assert_eq!(iq.pop(), Some(2));
+        assert_eq!(iq.histogram(Latency::Push).len(), 0);
+        assert_eq!(iq.histogram(Latency::Pop).len(), 1);
+    })
+}
@@ -0,0 +1,108 @@
+/// Interceptor of the values going through a `Wrapped` queue.
+///
+/// All the methods pass the value through by default, so implementations
+/// only need to override the ones they care about.
+pub trait Layer<T>: Send + Sync {
+    /// Observe or transform a value before it's pushed.
+    fn on_push(&self, value: T) -> T {
+        value
+    }
+    /// Observe or transform a value after it's popped.
+    fn on_pop(&self, value: T) -> T {
+        value
+    }
+    /// Handle a value returned by a failed push, which has already
+    /// gone through `on_push`. Transforming layers should undo it here.
+    fn on_reject(&self, value: T) -> T {
+        value
+    }
+}
+
+/// Queue wrapper passing all the values through a `Layer`.
+///
+/// Layers can be stacked by wrapping a `Wrapped` queue again,
+/// in which case the outermost layer sees the pushed values first.
+pub struct Wrapped<Q, L> {
+    queue: Q,
+    layer: L,
+}
+
+impl<Q, L> Wrapped<Q, L> {
+    pub fn new(queue: Q, layer: L) -> Self {
+        Self { queue, layer }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    pub fn layer(&self) -> &L {
+        &self.layer
+    }
+}
+
+impl<T, Q, L> super::SynQueue<T> for Wrapped<Q, L>
+where
+    Q: super::SynQueue<T>,
+    L: Layer<T>,
+{
+    fn push(&self, value: T) -> Result<(), T> {
+        self.queue
+            .push(self.layer.on_push(value))
+            .map_err(|value| self.layer.on_reject(value))
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.queue.pop().map(|value| self.layer.on_pop(value))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn layers() {
+    use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+    use super::{NewQueue as _, SynQueue as _};
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+    impl<T> Layer<T> for Counter {
+        fn on_push(&self, value: T) -> T {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            value
+        }
+    }
+    struct Scramble(u32);
+    impl Layer<u32> for Scramble {
+        fn on_push(&self, value: u32) -> u32 {
+            value ^ self.0
+        }
+        fn on_pop(&self, value: u32) -> u32 {
+            value ^ self.0
+        }
+        fn on_reject(&self, value: u32) -> u32 {
+            value ^ self.0
+        }
+    }
+
+    super::loom::model(|| {
+        let scrambled = Wrapped::new(super::DoubleQueue::new(1), Scramble(0xFF));
+        let wq = Wrapped::new(&scrambled, Counter::default());
+        wq.push(1).unwrap();
+        assert_eq!(wq.push(2), Err(2));
+        assert_eq!(wq.layer().0.load(Ordering::Relaxed), 2);
+        assert_eq!(scrambled.inner().pop(), Some(0xFE));
+        scrambled.push(3).unwrap();
+        assert_eq!(wq.pop(), Some(3));
+    })
+}
@@ -1,36 +1,467 @@
+/// Internal diagnostics, going to `defmt` if enabled, then `tracing`, or `log` otherwise.
+///
+/// They are on the hot path, so they are compiled out unless the `trace` feature is enabled.
+/// The `defmt` output only changes the logging backend, it doesn't make the crate `no_std`.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(all(feature = "trace", feature = "defmt"))]
+        defmt::trace!($($arg)*);
+        #[cfg(all(feature = "trace", feature = "tracing", not(feature = "defmt")))]
+        tracing::trace!($($arg)*);
+        #[cfg(all(
+            feature = "trace",
+            feature = "log",
+            not(any(feature = "tracing", feature = "defmt"))
+        ))]
+        log::trace!($($arg)*);
+        #[cfg(not(all(feature = "trace", any(feature = "log", feature = "tracing", feature = "defmt"))))]
+        let _ = ($($arg)*);
+    };
+}
+
+/// Internal invariant, which is also checked in release builds with the `checked` feature.
+macro_rules! checked_assert {
+    ($($arg:tt)*) => {
+        if cfg!(any(debug_assertions, feature = "checked")) {
+            assert!($($arg)*);
+        }
+    };
+}
+
+/// Event with the slot index claimed by the current operation, if `tracing` is enabled.
+///
+/// The queue identity comes from the span of the operation.
+macro_rules! trace_slot {
+    ($slot:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(slot = $slot as usize, "claimed");
+    };
+}
+
+mod adapter;
+#[cfg(feature = "stats")]
+mod adaptive;
+#[cfg(feature = "async")]
+mod async_queue;
+mod auto;
 mod axel;
+mod batch;
+mod blocking;
+mod chunk;
+mod close;
+mod convert;
+mod counted;
+mod deadline;
+mod dedup;
+mod depth;
 mod double;
+mod faa;
+#[cfg(feature = "hdrhistogram")]
+mod instrumented;
+mod layer;
+#[cfg(all(test, not(any(feature = "loom", feature = "shuttle"))))]
+mod lincheck;
+mod linked;
+mod locked;
 mod masked;
+#[cfg(feature = "metrics")]
+mod metered;
+mod mock;
+mod mpsc;
+mod pad;
+mod pool;
+mod priority;
+mod sampled;
+#[cfg(feature = "arbitrary")]
+mod scenario;
+mod select;
+mod sharded;
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+mod shared;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod spill;
+mod split;
+mod spmc;
+mod spsc;
+mod static_queue;
+mod stats;
+mod storage;
+mod tee;
+mod throttled;
+mod token;
+mod vyukov;
+mod wait;
+mod watermark;
+mod wheel;
+#[cfg(feature = "portable-atomic")]
+mod wide;
 
-pub use axel::AxelQueue;
-pub use double::DoubleQueue;
-pub use masked::MaskedQueue;
+pub use adapter::{Filtered, Mapped};
+#[cfg(feature = "stats")]
+pub use adaptive::AdaptiveQueue;
+#[cfg(feature = "sink")]
+pub use async_queue::QueueSink;
+#[cfg(feature = "async")]
+pub use async_queue::{AsyncQueue, PopFuture, PushFuture};
+pub use auto::AutoQueue;
+/// See `fixed::AxelQueue` for the details.
+pub type AxelQueue<T> = axel::AxelQueue<T>;
+pub use batch::BatchProducer;
+pub use blocking::BlockingQueue;
+pub use chunk::{ChunkQueue, ReadChunk, WriteChunk, CHUNK_ALIGNMENT};
+pub use close::Closable;
+pub use convert::ConvertError;
+pub use counted::Counted;
+pub use deadline::DeadlineQueue;
+pub use dedup::DedupQueue;
+pub use depth::{DepthEvent, DepthMonitor};
+/// See `fixed::DoubleQueue` for the details.
+pub type DoubleQueue<T> = double::DoubleQueue<T>;
+pub use faa::FaaQueue;
+#[cfg(feature = "hdrhistogram")]
+pub use instrumented::{Instrumented, Latency, Stamped};
+pub use layer::{Layer, Wrapped};
+pub use linked::MsQueue;
+pub use locked::LockedQueue;
+#[cfg(feature = "metrics")]
+pub use metered::Metered;
+/// See `fixed::MaskedQueue` for the details.
+pub type MaskedQueue<T> = masked::MaskedQueue<T>;
+pub use mock::MockQueue;
+pub use mpsc::{MpscConsumer, MpscProducer, MpscQueue};
+pub use pad::{CachePadded, Padded, CACHE_LINE};
+pub use pool::{Pool, Pooled};
+pub use priority::PriorityQueue;
+pub use sampled::SampledQueue;
+#[cfg(feature = "arbitrary")]
+pub use scenario::{Operation, QueueKind, Scenario};
+pub use select::{pop_biased, RoundRobin, Weighted};
+pub use sharded::ShardedQueue;
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+pub use shared::{SharedError, SharedQueue};
+#[cfg(feature = "serde")]
+pub use snapshot::{load_state, save_state, Snapshot};
+pub use spill::SpillQueue;
+pub use split::{split, Consumer, Producer};
+pub use spmc::{SpmcConsumer, SpmcProducer, SpmcQueue};
+#[cfg(not(feature = "loom"))]
+pub use spsc::Reservation;
+pub use spsc::{SpscConsumer, SpscProducer, SpscQueue};
+pub use static_queue::StaticQueue;
+#[cfg(feature = "stats")]
+pub use stats::{HandleStats, Stats};
+pub use storage::{AllocOptions, HUGE_PAGE_SIZE};
+pub use tee::{Tee, TeePolicy};
+pub use throttled::Throttled;
+pub use token::{ConsumerToken, ProducerToken};
+pub use vyukov::VyukovQueue;
+pub use wait::{
+    set_default_wait_strategy, Backoff, ExponentialSpin, NoBackoff, Park, WaitStrategy,
+};
+pub use watermark::HighWater;
+pub use wheel::TimerWheel;
+#[cfg(feature = "portable-atomic")]
+pub use wide::WideQueue;
+
+/// Queue variants generic over the capacity `N` known at compile time.
+///
+/// The aliases at the crate root use `N = 0`, which means the capacity is given at runtime.
+/// With non-zero `N`, the slots are stored inline in the queue instead of on the heap,
+/// although `AxelQueue` still allocates its occupation bitmap.
+pub mod fixed {
+    pub use super::axel::AxelQueue;
+    pub use super::double::DoubleQueue;
+    pub use super::masked::MaskedQueue;
+}
 
 #[cfg(feature = "loom")]
 use loom as qstd;
-#[cfg(not(feature = "loom"))]
+/// Primitives of `shuttle`, which explores the interleavings with randomized
+/// scheduling instead of the exhaustive search of `loom`.
+#[cfg(all(feature = "shuttle", not(feature = "loom")))]
+mod qstd {
+    pub use shuttle::{hint, sync, thread};
+    pub use std::cell;
+}
+#[cfg(not(any(feature = "loom", feature = "shuttle", feature = "portable-atomic")))]
 use std as qstd;
+/// `std` with the atomics provided by `portable-atomic`, for the hosted targets
+/// that don't have native 64-bit atomics. The crate still needs `std`,
+/// so this doesn't bring it to the `no_std` targets without atomic CAS, like thumbv6m.
+#[cfg(all(
+    not(any(feature = "loom", feature = "shuttle")),
+    feature = "portable-atomic"
+))]
+mod qstd {
+    pub use std::{cell, hint, thread};
+    pub mod sync {
+        pub use portable_atomic as atomic;
+        pub use std::sync::*;
+    }
+}
 
 use qstd::sync::atomic::Ordering;
+use std::marker::PhantomData;
 
+// The `seq-cst` feature makes every queue operation sequentially consistent,
+// which helps telling apart ordering bugs from logic bugs when debugging.
+#[cfg(not(feature = "seq-cst"))]
 const CAS_ORDER: Ordering = Ordering::AcqRel;
+#[cfg(not(feature = "seq-cst"))]
 const LOAD_ORDER: Ordering = Ordering::Acquire;
+#[cfg(not(feature = "seq-cst"))]
+const STORE_ORDER: Ordering = Ordering::Release;
+#[cfg(feature = "seq-cst")]
+const CAS_ORDER: Ordering = Ordering::SeqCst;
+#[cfg(feature = "seq-cst")]
+const LOAD_ORDER: Ordering = Ordering::SeqCst;
+#[cfg(feature = "seq-cst")]
+const STORE_ORDER: Ordering = Ordering::SeqCst;
 
 pub trait SynQueue<T>: Send + Sync {
-    fn new(capacity: usize) -> Self;
     fn push(&self, value: T) -> Result<(), T>;
     fn pop(&self) -> Option<T>;
     fn is_empty(&self) -> bool;
+    /// Check if a push would fail, which is when `capacity()` elements are in the queue.
+    fn is_full(&self) -> bool;
+    fn capacity(&self) -> usize;
+
+    /// Pop an element, telling apart the true emptiness from contention.
+    ///
+    /// `TryPopError::Busy` means that an element is being pushed concurrently,
+    /// so the caller can retry right away instead of sleeping.
+    /// The queues that can't detect it only report `TryPopError::Empty`.
+    fn try_pop(&self) -> Result<T, TryPopError> {
+        self.pop().ok_or(TryPopError::Empty)
+    }
+
+    /// Iterate over the elements, popping them until the queue is momentarily empty.
+    fn try_iter(&self) -> TryIter<'_, Self, T>
+    where
+        Self: Sized,
+    {
+        TryIter {
+            queue: self,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Iterator returned by `SynQueue::try_iter`.
+///
+/// It ends as soon as a pop fails, but it's not fused: calling `next` again
+/// can return the elements pushed afterwards.
+pub struct TryIter<'a, Q, T> {
+    queue: &'a Q,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<Q: SynQueue<T>, T> Iterator for TryIter<'_, Q, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.queue.pop()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryPopError {
+    /// There are no elements.
+    Empty,
+    /// There are no elements ready, but some are being pushed.
+    Busy,
+    /// There are no elements, and the queue is closed.
+    Closed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushError<T> {
+    /// The queue is full.
+    Full(T),
+    /// The queue is closed.
+    Closed(T),
+}
+
+impl<T> PushError<T> {
+    /// Get back the value that failed to be pushed.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(value) | Self::Closed(value) => value,
+        }
+    }
+}
+
+/// Queue that can be closed, telling the consumers that no more elements are coming.
+///
+/// The plain queues opt into it by being wrapped in `Closable`.
+pub trait CloseQueue<T>: SynQueue<T> {
+    /// Make all the subsequent pushes fail.
+    ///
+    /// The elements already in the queue can still be popped,
+    /// after which `try_pop` reports `TryPopError::Closed`.
+    fn close(&self);
+    fn is_closed(&self) -> bool;
+
+    /// Push a value, telling apart a full queue from a closed one.
+    fn try_push(&self, value: T) -> Result<(), PushError<T>> {
+        self.push(value).map_err(|value| {
+            if self.is_closed() {
+                PushError::Closed(value)
+            } else {
+                PushError::Full(value)
+            }
+        })
+    }
+}
+
+/// Queue that can take multiple elements at once.
+pub trait ExtendQueue<T>: SynQueue<T> {
+    /// Move as many elements as fit from the front of `values`, returning their count.
+    ///
+    /// The default implementation pushes them one by one until the first failure.
+    fn try_extend(&self, values: &mut Vec<T>) -> usize {
+        let mut count = 0;
+        let mut rest = Vec::new();
+        let mut drain = values.drain(..);
+        for value in drain.by_ref() {
+            if let Err(value) = self.push(value) {
+                rest.push(value);
+                rest.extend(drain.by_ref());
+                break;
+            }
+            count += 1;
+        }
+        drop(drain);
+        // put the rest back, keeping the allocation of `values`
+        values.append(&mut rest);
+        count
+    }
+}
+
+/// Queue that can be created from just the capacity.
+///
+/// It's separate from `SynQueue`, so that the latter can be implemented
+/// for references, smart pointers, and wrappers with extra configuration.
+pub trait NewQueue<T>: SynQueue<T> + Sized {
+    fn new(capacity: usize) -> Self;
+
+    /// Create a queue, reporting invalid capacities and allocation failures instead of panicking.
+    ///
+    /// The queues that can't detect the failures just call `new`.
+    fn try_new(capacity: usize) -> Result<Self, NewError> {
+        Ok(Self::new(capacity))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NewError {
+    /// The queue doesn't support the given capacity.
+    InvalidCapacity(usize),
+    /// The storage couldn't be allocated.
+    AllocationFailed,
+    /// The requested alignment is not a power of two.
+    InvalidAlignment(usize),
+}
+
+impl std::fmt::Display for NewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Self::InvalidCapacity(capacity) => write!(f, "capacity {} is not supported", capacity),
+            Self::AllocationFailed => write!(f, "failed to allocate the storage"),
+            Self::InvalidAlignment(alignment) => {
+                write!(f, "alignment {} is not a power of two", alignment)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NewError {}
+
+/// Allocate uninitialized storage for `count` slots.
+fn try_alloc<S>(count: usize, fun: impl FnMut() -> S) -> Result<Box<[S]>, NewError> {
+    let mut data = Vec::new();
+    data.try_reserve_exact(count)
+        .map_err(|_| NewError::AllocationFailed)?;
+    data.resize_with(count, fun);
+    Ok(data.into_boxed_slice())
+}
+
+macro_rules! impl_deref_queue {
+    ($($ty:ty),*) => {$(
+        impl<T, Q: SynQueue<T> + ?Sized> SynQueue<T> for $ty {
+            fn push(&self, value: T) -> Result<(), T> {
+                (**self).push(value)
+            }
+            fn pop(&self) -> Option<T> {
+                (**self).pop()
+            }
+            fn is_empty(&self) -> bool {
+                (**self).is_empty()
+            }
+            fn is_full(&self) -> bool {
+                (**self).is_full()
+            }
+            fn capacity(&self) -> usize {
+                (**self).capacity()
+            }
+            fn try_pop(&self) -> Result<T, TryPopError> {
+                (**self).try_pop()
+            }
+        }
+    )*};
+}
+
+impl_deref_queue!(&Q, Box<Q>, std::sync::Arc<Q>);
+
+/// Order of dropping the elements remaining in a queue when it's dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropOrder {
+    /// Oldest elements are dropped first, in the order they would be popped.
+    #[default]
+    Fifo,
+    /// Newest elements are dropped first.
+    Lifo,
+}
+
+/// Drop the initialized slots in `tail..head` range, wrapping around.
+unsafe fn drop_slots<T>(
+    data: &mut [std::mem::MaybeUninit<qstd::cell::UnsafeCell<T>>],
+    tail: usize,
+    head: usize,
+    order: DropOrder,
+) {
+    let count = (head + data.len() - tail) % data.len();
+    for i in 0..count {
+        let offset = match order {
+            DropOrder::Fifo => i,
+            DropOrder::Lifo => count - 1 - i,
+        };
+        data[(tail + offset) % data.len()].assume_init_drop();
+    }
 }
 
 trait UnsafeCellHelper<T> {
     unsafe fn write(this: *const Self, value: T);
+    unsafe fn peek<R>(this: *const Self, fun: impl FnOnce(&T) -> R) -> R;
+    unsafe fn with_uninit<R>(
+        this: *const Self,
+        fun: impl FnOnce(&mut std::mem::MaybeUninit<T>) -> R,
+    ) -> R;
 }
 
 impl<T> UnsafeCellHelper<T> for std::cell::UnsafeCell<T> {
     unsafe fn write(this: *const Self, value: T) {
         std::cell::UnsafeCell::raw_get(this).write(value);
     }
+    unsafe fn peek<R>(this: *const Self, fun: impl FnOnce(&T) -> R) -> R {
+        fun(&*std::cell::UnsafeCell::raw_get(this))
+    }
+    unsafe fn with_uninit<R>(
+        this: *const Self,
+        fun: impl FnOnce(&mut std::mem::MaybeUninit<T>) -> R,
+    ) -> R {
+        fun(&mut *(std::cell::UnsafeCell::raw_get(this) as *mut std::mem::MaybeUninit<T>))
+    }
 }
 
 #[cfg(feature = "loom")]
@@ -38,9 +469,38 @@ impl<T> UnsafeCellHelper<T> for loom::cell::UnsafeCell<T> {
     unsafe fn write(this: *const Self, value: T) {
         (*this).with_mut(|pointer| std::ptr::write(pointer, value));
     }
+    unsafe fn peek<R>(this: *const Self, fun: impl FnOnce(&T) -> R) -> R {
+        (*this).with(|pointer| fun(&*pointer))
+    }
+    unsafe fn with_uninit<R>(
+        this: *const Self,
+        fun: impl FnOnce(&mut std::mem::MaybeUninit<T>) -> R,
+    ) -> R {
+        (*this).with_mut(|pointer| fun(&mut *(pointer as *mut std::mem::MaybeUninit<T>)))
+    }
 }
 
-#[cfg(all(test, not(feature = "loom")))]
+#[cfg(all(test, feature = "loom"))]
+#[allow(clippy::single_component_path_imports)]
+use loom;
+#[cfg(all(test, feature = "shuttle", not(feature = "loom")))]
+mod loom {
+    /// Run the model under the PCT scheduler, which finds bugs of depth 3 with high probability.
+    pub fn model(fun: impl Fn() + Send + Sync + 'static) {
+        const ITERATIONS: usize = 100;
+        shuttle::check_pct(
+            move || {
+                // PCT refuses to run a model without concurrency, which the sequential tests are
+                let idle = shuttle::thread::spawn(|| {});
+                fun();
+                idle.join().unwrap();
+            },
+            ITERATIONS,
+            3,
+        );
+    }
+}
+#[cfg(all(test, not(any(feature = "loom", feature = "shuttle"))))]
 mod loom {
     pub fn model(mut fun: impl FnMut()) {
         fun();
@@ -48,34 +508,189 @@ mod loom {
 }
 
 #[cfg(test)]
-fn test_overflow<Q: SynQueue<i32>>() {
+fn test_overflow<Q: NewQueue<i32>>() {
     loom::model(|| {
         let sq = Q::new(2);
+        assert_eq!(sq.capacity(), 2);
+        assert!(!sq.is_full());
         sq.push(2).unwrap();
         sq.push(3).unwrap();
+        assert!(sq.is_full());
         assert_eq!(sq.push(4), Err(4));
+        assert_eq!(sq.pop(), Some(2));
+        assert!(!sq.is_full());
+        sq.push(4).unwrap();
+        assert!(sq.is_full());
     })
 }
 
 #[cfg(test)]
-fn test_smoke<Q: SynQueue<i32>>() {
+fn test_smoke<Q: NewQueue<i32>>() {
     loom::model(|| {
         let sq = Q::new(16);
         assert_eq!(sq.pop(), None);
         sq.push(5).unwrap();
         sq.push(10).unwrap();
         assert_eq!(sq.pop(), Some(5));
-        assert_eq!(sq.pop(), Some(10));
+        assert_eq!(sq.try_pop(), Ok(10));
+        assert_eq!(sq.try_pop(), Err(TryPopError::Empty));
+    })
+}
+
+#[cfg(test)]
+fn test_close<Q: NewQueue<i32> + CloseQueue<i32>>() {
+    loom::model(|| {
+        let sq = Q::new(2);
+        sq.push(1).unwrap();
+        sq.push(2).unwrap();
+        assert_eq!(sq.try_push(3), Err(PushError::Full(3)));
+        sq.close();
+        assert!(sq.is_closed());
+        assert_eq!(sq.pop(), Some(1));
+        assert_eq!(sq.try_push(4), Err(PushError::Closed(4)));
+        assert_eq!(sq.try_pop(), Ok(2));
+        assert_eq!(sq.try_pop(), Err(TryPopError::Closed));
+        assert_eq!(sq.pop(), None);
+    })
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+fn test_raw_parts<Q: NewQueue<String>>(
+    into_raw_parts: impl Fn(Q) -> (*mut String, usize, usize, usize) + Send + Sync + 'static,
+    from_raw_parts: impl Fn(*mut String, usize, usize, usize) -> Q + Send + Sync + 'static,
+) {
+    loom::model(move || {
+        let sq = Q::new(4);
+        for i in 0..4 {
+            sq.push(i.to_string()).unwrap();
+        }
+        assert_eq!(sq.pop().as_deref(), Some("0"));
+        let (data, capacity, head, tail) = into_raw_parts(sq);
+        assert_eq!(capacity, 4);
+        let sq = from_raw_parts(data, capacity, head, tail);
+        sq.push("4".to_string()).unwrap();
+        assert_eq!(sq.push("5".to_string()), Err("5".to_string()));
+        assert_eq!(sq.pop().as_deref(), Some("1"));
+        // the rest is dropped with the queue
+    })
+}
+
+#[test]
+fn try_iter() {
+    loom::model(|| {
+        let dq = DoubleQueue::new(4);
+        for i in 0..3 {
+            dq.push(i).unwrap();
+        }
+        assert_eq!(dq.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+        let mut iter = dq.try_iter();
+        assert_eq!(iter.next(), None);
+        dq.push(3).unwrap();
+        assert_eq!(iter.next(), Some(3));
+    })
+}
+
+#[test]
+fn try_new() {
+    loom::model(|| {
+        assert_eq!(
+            MaskedQueue::<i32>::try_new(0).err(),
+            Some(NewError::InvalidCapacity(0))
+        );
+        assert_eq!(
+            fixed::DoubleQueue::<i32, 4>::try_new(8).err(),
+            Some(NewError::InvalidCapacity(8))
+        );
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(
+            AxelQueue::<[u64; 1 << 28]>::try_new(1 << 20).err(),
+            Some(NewError::AllocationFailed)
+        );
+        assert!(AutoQueue::<i32>::try_new(12).is_ok());
+        assert_eq!(
+            VyukovQueue::<i32>::try_new(0).err(),
+            Some(NewError::InvalidCapacity(0))
+        );
+        assert_eq!(
+            FaaQueue::<i32>::try_new(6).err(),
+            Some(NewError::InvalidCapacity(6))
+        );
+        assert_eq!(
+            StaticQueue::<i32, 4>::try_new(8).err(),
+            Some(NewError::InvalidCapacity(8))
+        );
+        assert_eq!(
+            ShardedQueue::<i32, FaaQueue<i32>>::try_with_shards(2, 6).err(),
+            Some(NewError::InvalidCapacity(3))
+        );
+    })
+}
+
+#[test]
+fn forwarding() {
+    fn check(sq: impl SynQueue<i32>) {
+        assert_eq!(sq.capacity(), 2);
+        sq.push(1).unwrap();
+        assert_eq!(sq.pop(), Some(1));
+        assert!(sq.is_empty());
+    }
+    loom::model(|| {
+        let dq = DoubleQueue::new(2);
+        check(&dq);
+        check(Box::new(dq));
+        check(std::sync::Arc::new(AxelQueue::new(2)));
+        check(Box::new(MaskedQueue::new(2)) as Box<dyn SynQueue<i32>>);
+    })
+}
+
+#[test]
+fn drop_order() {
+    use std::sync::{Arc, Mutex};
+    struct Noisy(i32, Arc<Mutex<Vec<i32>>>);
+    impl Drop for Noisy {
+        fn drop(&mut self) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+    fn check<Q: SynQueue<Noisy>>(make: impl Fn(DropOrder) -> Q) {
+        for (order, expected) in [(DropOrder::Fifo, [1, 2]), (DropOrder::Lifo, [2, 1])] {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let sq = make(order);
+            for i in 0..2 {
+                sq.push(Noisy(i, Arc::clone(&log))).ok().unwrap();
+            }
+            drop(sq.pop());
+            // wrap around the end of the storage
+            sq.push(Noisy(2, Arc::clone(&log))).ok().unwrap();
+            log.lock().unwrap().clear();
+            drop(sq);
+            assert_eq!(*log.lock().unwrap(), expected);
+        }
+    }
+    loom::model(|| {
+        check(|order| AxelQueue::new(2).with_drop_order(order));
+        check(|order| DoubleQueue::new(2).with_drop_order(order));
+        check(|order| MaskedQueue::new(2).with_drop_order(order));
     })
 }
 
 #[cfg(test)]
-fn test_barrage<Q: SynQueue<usize> + 'static>() {
+fn test_barrage<Q: NewQueue<usize> + 'static>() {
     use qstd::{sync::Arc, thread};
 
     loom::model(|| {
-        const NUM_THREADS: usize = if cfg!(miri) { 2 } else { 8 };
-        const NUM_ELEMENTS: usize = if cfg!(miri) { 1 << 7 } else { 1 << 16 };
+        const NUM_THREADS: usize = if cfg!(any(miri, feature = "shuttle")) {
+            2
+        } else {
+            8
+        };
+        const NUM_ELEMENTS: usize = if cfg!(any(miri, feature = "shuttle")) {
+            1 << 7
+        } else if cfg!(target_pointer_width = "32") {
+            1 << 15
+        } else {
+            1 << 16
+        };
         let sq = Arc::new(Q::new(NUM_ELEMENTS));
         let mut handles = Vec::new();
 
@@ -101,3 +716,35 @@ fn test_barrage<Q: SynQueue<usize> + 'static>() {
         }
     })
 }
+
+/// Run the same random sequence of operations on `Q` and on `LockedQueue`,
+/// expecting them to agree on every result.
+#[cfg(test)]
+fn test_differential<Q: NewQueue<u32>>() {
+    const OPERATIONS: u32 = if cfg!(feature = "loom") {
+        20
+    } else if cfg!(any(miri, feature = "shuttle")) {
+        100
+    } else {
+        10_000
+    };
+    loom::model(|| {
+        let sq = Q::new(4);
+        let reference = LockedQueue::new(sq.capacity());
+        let mut rng = 0x9E37_79B9u32;
+        for i in 0..OPERATIONS {
+            // xorshift32
+            rng ^= rng << 13;
+            rng ^= rng >> 17;
+            rng ^= rng << 5;
+            // lean on pushes in some stretches and on pops in others
+            if rng % 4 < 1 + (i / 64) % 3 {
+                assert_eq!(sq.push(i), reference.push(i), "push #{}", i);
+            } else {
+                assert_eq!(sq.pop(), reference.pop(), "pop #{}", i);
+            }
+            assert_eq!(sq.is_empty(), reference.is_empty(), "emptiness #{}", i);
+            assert_eq!(sq.is_full(), reference.is_full(), "fullness #{}", i);
+        }
+    })
+}
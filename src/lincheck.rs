@@ -0,0 +1,293 @@
+//! Linearizability checking against a sequential FIFO specification.
+//!
+//! The threads run random operations on a small queue, recording the moments
+//! of every call and return on a shared clock. The history is then searched
+//! for an order of the operations that respects the real time (an operation
+//! that returned before another was called comes first) and is valid
+//! for a bounded FIFO queue of the same capacity.
+
+use super::NewQueue;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+const ITERATIONS: usize = if cfg!(miri) { 4 } else { 300 };
+const OPS_PER_THREAD: usize = 6;
+
+/// What a thread is allowed to do, for the queues restricting the roles.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Role {
+    Producer,
+    Consumer,
+    Mixed,
+}
+
+/// When an operation is allowed to fail while the queue isn't full or empty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Failures {
+    /// Never: a push only fails on `capacity` elements, and a pop on none.
+    Exact,
+    /// A push at any moment, for the queues documenting spurious push failures.
+    SpuriousFull,
+    /// A push or a pop overlapping with another operation, for the queues
+    /// where an operation in flight holds up the others.
+    InFlight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Pushed(u32),
+    Full,
+    Popped(u32),
+    Empty,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Event {
+    op: Op,
+    call: usize,
+    ret: usize,
+}
+
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        // xorshift32
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+}
+
+fn run_thread<Q: super::SynQueue<u32>>(
+    queue: &Q,
+    clock: &AtomicUsize,
+    role: Role,
+    index: usize,
+    seed: u32,
+) -> Vec<Event> {
+    let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9) | 1);
+    let mut next_value = index as u32 * 1000;
+    (0..OPS_PER_THREAD)
+        .map(|_| {
+            let bits = rng.next();
+            if bits & 2 != 0 {
+                thread::yield_now();
+            }
+            let push = match role {
+                Role::Producer => true,
+                Role::Consumer => false,
+                Role::Mixed => bits & 1 != 0,
+            };
+            let call = clock.fetch_add(1, Ordering::SeqCst);
+            let op = if push {
+                next_value += 1;
+                match queue.push(next_value) {
+                    Ok(()) => Op::Pushed(next_value),
+                    Err(_) => Op::Full,
+                }
+            } else {
+                match queue.pop() {
+                    Some(value) => Op::Popped(value),
+                    None => Op::Empty,
+                }
+            };
+            let ret = clock.fetch_add(1, Ordering::SeqCst);
+            Event { op, call, ret }
+        })
+        .collect()
+}
+
+/// Check if the events can be ordered into a valid sequential history.
+fn is_linearizable(capacity: usize, failures: Failures, events: &[Event]) -> bool {
+    fn search(
+        capacity: usize,
+        failures: Failures,
+        events: &[Event],
+        done: u64,
+        model: &mut VecDeque<u32>,
+        failed: &mut HashSet<(u64, Vec<u32>)>,
+    ) -> bool {
+        let pending = || (0..events.len()).filter(|&i| done & (1 << i) == 0);
+        let in_flight = |i: usize| {
+            failures == Failures::InFlight
+                && events.iter().enumerate().any(|(j, other)| {
+                    j != i && other.call < events[i].ret && events[i].call < other.ret
+                })
+        };
+        let Some(first_ret) = pending().map(|i| events[i].ret).min() else {
+            return true;
+        };
+        if failed.contains(&(done, Vec::from(model.clone()))) {
+            return false;
+        }
+        // only the operations called before any pending one returned can go next
+        for i in pending().filter(|&i| events[i].call < first_ret) {
+            let found = match events[i].op {
+                Op::Pushed(value) if model.len() < capacity => {
+                    model.push_back(value);
+                    let found = search(capacity, failures, events, done | (1 << i), model, failed);
+                    model.pop_back();
+                    found
+                }
+                Op::Popped(value) if model.front() == Some(&value) => {
+                    model.pop_front();
+                    let found = search(capacity, failures, events, done | (1 << i), model, failed);
+                    model.push_front(value);
+                    found
+                }
+                Op::Full
+                    if failures == Failures::SpuriousFull
+                        || in_flight(i)
+                        || model.len() == capacity =>
+                {
+                    search(capacity, failures, events, done | (1 << i), model, failed)
+                }
+                Op::Empty if model.is_empty() || in_flight(i) => {
+                    search(capacity, failures, events, done | (1 << i), model, failed)
+                }
+                _ => false,
+            };
+            if found {
+                return true;
+            }
+        }
+        failed.insert((done, Vec::from(model.clone())));
+        false
+    }
+
+    assert!(events.len() <= 64);
+    search(
+        capacity,
+        failures,
+        events,
+        0,
+        &mut VecDeque::new(),
+        &mut HashSet::new(),
+    )
+}
+
+/// Run the threads with the given roles on the queue many times,
+/// checking every recorded history.
+pub(crate) fn check<Q: NewQueue<u32>>(capacity: usize, roles: &[Role]) {
+    check_with::<Q>(capacity, roles, Failures::Exact);
+}
+
+/// Same as `check`, with the given failures allowed.
+pub(crate) fn check_with<Q: NewQueue<u32>>(capacity: usize, roles: &[Role], failures: Failures) {
+    for iteration in 0..ITERATIONS {
+        let queue = Q::new(capacity);
+        let clock = AtomicUsize::new(0);
+        let mut events = thread::scope(|scope| {
+            let handles = roles
+                .iter()
+                .enumerate()
+                .map(|(index, &role)| {
+                    let seed = (iteration * roles.len() + index + 1) as u32;
+                    let (queue, clock) = (&queue, &clock);
+                    scope.spawn(move || run_thread(queue, clock, role, index, seed))
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        // drain the leftovers, so that a lost element shows up in the history
+        loop {
+            let call = clock.fetch_add(1, Ordering::SeqCst);
+            let value = queue.pop();
+            let ret = clock.fetch_add(1, Ordering::SeqCst);
+            let op = value.map_or(Op::Empty, Op::Popped);
+            events.push(Event { op, call, ret });
+            if value.is_none() {
+                break;
+            }
+        }
+        assert!(
+            is_linearizable(queue.capacity(), failures, &events),
+            "History is not linearizable: {:#?}",
+            events
+        );
+    }
+}
+
+/// Check a queue that allows any thread to push and pop.
+pub(crate) fn check_mpmc<Q: NewQueue<u32>>() {
+    check_mpmc_with::<Q>(Failures::Exact);
+}
+
+/// Same as `check_mpmc`, with the given failures allowed.
+pub(crate) fn check_mpmc_with<Q: NewQueue<u32>>(failures: Failures) {
+    check_with::<Q>(2, &[Role::Mixed, Role::Mixed, Role::Mixed], failures);
+    check_with::<Q>(
+        2,
+        &[
+            Role::Producer,
+            Role::Producer,
+            Role::Consumer,
+            Role::Consumer,
+        ],
+        failures,
+    );
+}
+
+#[test]
+fn checker() {
+    let event = |op, call, ret| Event { op, call, ret };
+    // overlapping push and pop can go in any order
+    assert!(is_linearizable(
+        1,
+        Failures::Exact,
+        &[event(Op::Pushed(1), 0, 3), event(Op::Empty, 1, 2)]
+    ));
+    // but a pop after the push returned has to see the element
+    assert!(!is_linearizable(
+        1,
+        Failures::Exact,
+        &[event(Op::Pushed(1), 0, 1), event(Op::Empty, 2, 3)]
+    ));
+    // the order of the elements has to be preserved
+    assert!(!is_linearizable(
+        2,
+        Failures::Exact,
+        &[
+            event(Op::Pushed(1), 0, 1),
+            event(Op::Pushed(2), 2, 3),
+            event(Op::Popped(2), 4, 5),
+        ]
+    ));
+    // and the capacity respected
+    assert!(!is_linearizable(
+        2,
+        Failures::Exact,
+        &[event(Op::Pushed(1), 0, 1), event(Op::Full, 2, 3)]
+    ));
+    // unless the queue may fail spuriously
+    assert!(is_linearizable(
+        2,
+        Failures::SpuriousFull,
+        &[event(Op::Pushed(1), 0, 1), event(Op::Full, 2, 3)]
+    ));
+    // or the failure overlaps with another operation in flight
+    assert!(is_linearizable(
+        2,
+        Failures::InFlight,
+        &[
+            event(Op::Pushed(1), 0, 1),
+            event(Op::Pushed(2), 2, 5),
+            event(Op::Empty, 3, 4),
+            event(Op::Popped(1), 6, 7),
+        ]
+    ));
+    // but not once everything is settled
+    assert!(!is_linearizable(
+        2,
+        Failures::InFlight,
+        &[event(Op::Pushed(1), 0, 1), event(Op::Empty, 2, 3)]
+    ));
+}
@@ -0,0 +1,154 @@
+use super::qstd::sync::{
+    atomic::{AtomicPtr, AtomicUsize},
+    Mutex,
+};
+use std::{mem, ptr};
+
+struct Node<T> {
+    value: mem::MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn alloc(value: mem::MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            value,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+/// A linked list queue with separate head and tail locks, following Michael and Scott.
+///
+/// ## Principle
+/// The list always starts with a dummy node, so the producers only touch the tail
+/// and the consumers only touch the head, and they never contend with each other.
+/// Popping takes the value out of the node next to the dummy, which then becomes the new dummy.
+///
+/// This isn't meant for production use: every push allocates, and the locks serialize
+/// the operations on either side. It serves as a baseline for the array-based queues.
+pub struct MsQueue<T> {
+    head: Mutex<*mut Node<T>>,
+    tail: Mutex<*mut Node<T>>,
+    len: AtomicUsize,
+    capacity: usize,
+}
+
+unsafe impl<T: Send> Send for MsQueue<T> {}
+unsafe impl<T: Send> Sync for MsQueue<T> {}
+
+impl<T: Send> super::NewQueue<T> for MsQueue<T> {
+    fn new(capacity: usize) -> Self {
+        let dummy = Node::alloc(mem::MaybeUninit::uninit());
+        Self {
+            head: Mutex::new(dummy),
+            tail: Mutex::new(dummy),
+            len: AtomicUsize::new(0),
+            capacity,
+        }
+    }
+}
+
+impl<T: Send> super::SynQueue<T> for MsQueue<T> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn push(&self, value: T) -> Result<(), T> {
+        // The space is checked and reserved under the tail lock, so that other
+        // producers never see a reservation without the node behind it.
+        let mut tail = self.tail.lock().unwrap();
+        if self.len.load(super::LOAD_ORDER) == self.capacity {
+            return Err(value);
+        }
+        // counted before linking, so that a consumer can't take the node
+        // and bring `len` below zero in the meantime
+        self.len.fetch_add(1, super::CAS_ORDER);
+        let node = Node::alloc(mem::MaybeUninit::new(value));
+        unsafe { (**tail).next.store(node, super::STORE_ORDER) };
+        *tail = node;
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn pop(&self) -> Option<T> {
+        let mut head = self.head.lock().unwrap();
+        let dummy = *head;
+        let next = unsafe { (*dummy).next.load(super::LOAD_ORDER) };
+        if next.is_null() {
+            return None;
+        }
+        let value = unsafe { (*next).value.assume_init_read() };
+        *head = next;
+        // released under the head lock, so that pops take effect in order
+        self.len.fetch_sub(1, super::CAS_ORDER);
+        drop(head);
+
+        // the old dummy isn't reachable any more
+        drop(unsafe { Box::from_raw(dummy) });
+        Some(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len.load(super::LOAD_ORDER) == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len.load(super::LOAD_ORDER) == self.capacity
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T> Drop for MsQueue<T> {
+    fn drop(&mut self) {
+        let dummy = *self.head.lock().unwrap();
+        let mut node = unsafe { Box::from_raw(dummy) }.next.load(super::LOAD_ORDER);
+        while !node.is_null() {
+            let mut boxed = unsafe { Box::from_raw(node) };
+            unsafe { boxed.value.assume_init_drop() };
+            node = boxed.next.load(super::LOAD_ORDER);
+        }
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<MsQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<MsQueue<i32>>();
+}
+
+#[test]
+fn barrage() {
+    super::test_barrage::<MsQueue<usize>>();
+}
+
+#[test]
+fn leftovers() {
+    use super::{NewQueue as _, SynQueue as _};
+    use std::sync::Arc;
+    super::loom::model(|| {
+        let value = Arc::new(());
+        let sq = MsQueue::new(4);
+        for _ in 0..3 {
+            sq.push(Arc::clone(&value)).unwrap();
+        }
+        drop(sq.pop());
+        drop(sq);
+        assert_eq!(Arc::strong_count(&value), 1);
+    })
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    super::lincheck::check_mpmc::<MsQueue<u32>>();
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<MsQueue<u32>>();
+}
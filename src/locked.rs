@@ -0,0 +1,79 @@
+use super::qstd::sync::Mutex;
+use std::collections::VecDeque;
+
+/// Bounded FIFO queue behind a mutex.
+///
+/// Trivially correct, so it serves as the reference in differential tests,
+/// and as the baseline the lock-free queues are benchmarked against.
+pub struct LockedQueue<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+}
+
+impl<T: Send> super::NewQueue<T> for LockedQueue<T> {
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        if capacity == 0 {
+            return Err(super::NewError::InvalidCapacity(capacity));
+        }
+        let mut items = VecDeque::new();
+        items
+            .try_reserve_exact(capacity)
+            .map_err(|_| super::NewError::AllocationFailed)?;
+        Ok(Self {
+            items: Mutex::new(items),
+            capacity,
+        })
+    }
+}
+
+impl<T: Send> super::SynQueue<T> for LockedQueue<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        let mut items = self.items.lock().unwrap();
+        if items.len() == self.capacity {
+            return Err(value);
+        }
+        items.push_back(value);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.items.lock().unwrap().pop_front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.items.lock().unwrap().is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.items.lock().unwrap().len() == self.capacity
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<LockedQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<LockedQueue<i32>>();
+}
+
+#[test]
+fn barrage() {
+    super::test_barrage::<LockedQueue<usize>>();
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    super::lincheck::check_mpmc::<LockedQueue<u32>>();
+}
@@ -1,8 +1,10 @@
-use super::qstd::{cell::UnsafeCell, hint, sync::atomic::AtomicUsize, thread};
+use super::pad::CachePadded;
+use super::qstd::{hint, sync::atomic::AtomicUsize};
+use super::storage::{Slot, Slots};
 use std::mem;
 
 const INDEX_BITS: usize = 20;
-const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+pub(super) const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
 const TOTAL_BITS: usize = mem::size_of::<usize>() * 8;
 
 /// Another internally syncrhonized (MPMC) queue.
@@ -12,28 +14,96 @@ const TOTAL_BITS: usize = mem::size_of::<usize>() * 8;
 /// It maintans the mask as a part of the atomic, keeping head and tail separate.
 /// This makes `MaskedQueue` to also do 2 CAS operations every time, but unlike
 /// `DoubleQueue` the bit releases can complete out of order.
-pub struct MaskedQueue<T> {
-    head: AtomicUsize,
-    tail: AtomicUsize,
-    data: Box<[mem::MaybeUninit<UnsafeCell<T>>]>,
+/// The indices wrap around explicitly, so the capacity doesn't have to be a power of two.
+///
+/// An unfinished operation still holds its slot, so a pop finds the queue empty
+/// while the oldest push is in flight, even if the later ones are complete,
+/// and a push can report the queue as full because of it.
+/// `try_pop` reports the former as `TryPopError::Busy`.
+///
+/// Non-zero `N` fixes the capacity at compile time, like with `DoubleQueue`:
+/// the slots are stored inline, and the indices wrap around at `2 * N`.
+pub struct MaskedQueue<T, const N: usize = 0> {
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    data: Slots<Slot<T>, Box<[Slot<T>]>, N>,
+    wait: super::WaitStrategy,
+    stats: super::stats::Counters,
+    drop_order: super::DropOrder,
+}
+
+unsafe impl<T, const N: usize> Sync for MaskedQueue<T, N> {}
+
+/// Advance the index of the rich value, shifting the in-flight bits up
+/// and setting the bit of the new operation.
+#[inline(always)]
+fn next_rich_index(main: usize, wrap: usize) -> usize {
+    let mut next = ((main & !INDEX_MASK) << 1) | (1 << INDEX_BITS);
+    if (main & INDEX_MASK) + 1 != wrap {
+        next |= (main & INDEX_MASK) + 1;
+    }
+    next
 }
 
-unsafe impl<T> Sync for MaskedQueue<T> {}
+/// Index of the oldest operation still in flight, or the current index if there is none.
+#[inline(always)]
+fn last_used_index(rich_index: usize, wrap: usize) -> usize {
+    let index = rich_index & INDEX_MASK;
+    let offset = (TOTAL_BITS - INDEX_BITS).saturating_sub(rich_index.leading_zeros() as usize);
+    if index >= offset {
+        index - offset
+    } else {
+        index + wrap - offset
+    }
+}
+
+/// In-flight bit of the operation at `done_index` in the rich value.
+#[inline(always)]
+fn release_bit(current: usize, done_index: usize, wrap: usize) -> usize {
+    let cur_index = current & INDEX_MASK;
+    let offset = if cur_index > done_index {
+        cur_index - done_index
+    } else {
+        cur_index + wrap - done_index
+    };
+    assert!(offset + INDEX_BITS <= TOTAL_BITS);
+    1 << (INDEX_BITS - 1 + offset)
+}
 
+/// Number of the slots in use between the guard and the main index,
+/// at which the operation can't proceed.
 enum BoundsCheck {
-    OldValue,
-    NewValue,
+    Empty,
+    Full,
 }
 
-impl<T> MaskedQueue<T> {
+impl<T, const N: usize> MaskedQueue<T, N> {
+    /// Snapshot of the contention counters.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> super::Stats {
+        self.stats.snapshot()
+    }
+
+    /// Override the process-wide default wait strategy.
+    pub fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+
+    /// Set the order of dropping the remaining elements.
+    pub fn with_drop_order(mut self, order: super::DropOrder) -> Self {
+        self.drop_order = order;
+        self
+    }
+
+    /// Fails the build for a fixed capacity the index bits can't address.
+    const FIXED_CAPACITY_CHECK: () = assert!(
+        N <= INDEX_MASK / 2,
+        "the fixed capacity doesn't fit the index bits"
+    );
+
     fn get_last_used_index(&self, rich_index: usize) -> usize {
-        let index = rich_index & INDEX_MASK;
-        let offset = (TOTAL_BITS - INDEX_BITS).saturating_sub(rich_index.leading_zeros() as usize);
-        if index >= offset {
-            index - offset
-        } else {
-            index + self.data.len() - offset
-        }
+        last_used_index(rich_index, self.data.wrap())
     }
 
     fn cas_acquire(
@@ -42,37 +112,36 @@ impl<T> MaskedQueue<T> {
         guard_ref: &AtomicUsize,
         bounds_check: BoundsCheck,
     ) -> Option<(usize, usize)> {
-        let mut guard = guard_ref.load(super::LOAD_ORDER);
-        let mut last_used_index = self.get_last_used_index(guard);
         let mut main = main_ref.load(super::LOAD_ORDER);
         let mut next;
         loop {
+            let mut step = 0;
             while main >= (1 << (TOTAL_BITS - 1)) {
                 // too many operations in flight
-                thread::yield_now();
+                self.stats.wait(&self.wait, step);
+                step = step.saturating_add(1);
                 main = main_ref.load(super::LOAD_ORDER);
             }
 
-            next = ((main & !INDEX_MASK) << 1) | (1 << INDEX_BITS);
-            if (main & INDEX_MASK) + 1 != self.data.len() {
-                next |= (main & INDEX_MASK) + 1;
-            };
+            next = next_rich_index(main, self.data.wrap());
 
-            let check_index = match bounds_check {
-                BoundsCheck::OldValue => main & INDEX_MASK,
-                BoundsCheck::NewValue => next & INDEX_MASK,
+            // The guard has to be loaded after `main`: a stale one may lag
+            // behind by a whole lap, letting the check pass on a full
+            // (or empty) queue and racing with the operation still on the slot.
+            let last_used_index = self.get_last_used_index(guard_ref.load(super::LOAD_ORDER));
+            let in_use = self.data.distance(last_used_index, main & INDEX_MASK);
+            let limit = match bounds_check {
+                BoundsCheck::Empty => 0,
+                BoundsCheck::Full => self.data.capacity(),
             };
-            if check_index == last_used_index {
-                guard = guard_ref.load(super::LOAD_ORDER);
-                last_used_index = self.get_last_used_index(guard);
-                if check_index == last_used_index {
-                    return None;
-                }
+            if in_use == limit {
+                return None;
             }
 
             match main_ref.compare_exchange_weak(main, next, super::CAS_ORDER, super::LOAD_ORDER) {
                 Ok(_) => break,
                 Err(other) => {
+                    self.stats.failed_cas();
                     main = other;
                 }
             }
@@ -83,14 +152,7 @@ impl<T> MaskedQueue<T> {
 
     fn cas_release(&self, atomic_ref: &AtomicUsize, mut current: usize, done_index: usize) {
         loop {
-            let cur_index = current & INDEX_MASK;
-            let offset = if cur_index > done_index {
-                cur_index - done_index
-            } else {
-                cur_index + self.data.len() - done_index
-            };
-            assert!(offset + INDEX_BITS <= TOTAL_BITS);
-            let bit = 1 << (INDEX_BITS - 1 + offset);
+            let bit = release_bit(current, done_index, self.data.wrap());
             assert!(current & bit != 0);
             match atomic_ref.compare_exchange_weak(
                 current,
@@ -100,6 +162,7 @@ impl<T> MaskedQueue<T> {
             ) {
                 Ok(_) => break,
                 Err(other) => {
+                    self.stats.failed_cas();
                     current = other;
                     hint::spin_loop();
                 }
@@ -108,32 +171,96 @@ impl<T> MaskedQueue<T> {
     }
 }
 
-impl<T: Send> super::SynQueue<T> for MaskedQueue<T> {
-    fn new(capacity: usize) -> Self {
-        assert!(capacity.is_power_of_two());
+impl<T> MaskedQueue<T> {
+    /// Decompose the queue into the data pointer, capacity, head, and tail indices.
+    ///
+    /// The data has `capacity + 1` slots, and the ones in `tail..head` range
+    /// (wrapping around) are initialized.
+    #[cfg(not(feature = "loom"))]
+    pub fn into_raw_parts(self) -> (*mut T, usize, usize, usize) {
+        let this = mem::ManuallyDrop::new(self);
+        let head = this.head.load(super::LOAD_ORDER);
+        let tail = this.tail.load(super::LOAD_ORDER);
+        assert_eq!(head & !INDEX_MASK, 0);
+        assert_eq!(tail & !INDEX_MASK, 0);
+        let data = unsafe { std::ptr::read(&this.data) }.into_heap();
+        let capacity = data.len() - 1;
+        (Box::into_raw(data) as *mut T, capacity, head, tail)
+    }
+
+    /// Reconstruct the queue from its raw parts.
+    ///
+    /// # Safety
+    /// The parts have to come from `MaskedQueue::into_raw_parts`.
+    #[cfg(not(feature = "loom"))]
+    pub unsafe fn from_raw_parts(data: *mut T, capacity: usize, head: usize, tail: usize) -> Self {
+        debug_assert!(head <= capacity && tail <= capacity);
         Self {
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0),
-            /// In order to differentiate between empty and full states, we
-            /// are never going to use the full array, so get one extra element.
-            data: (0..=capacity).map(|_| mem::MaybeUninit::uninit()).collect(),
+            head: CachePadded::new(AtomicUsize::new(head)),
+            tail: CachePadded::new(AtomicUsize::new(tail)),
+            data: Slots::new(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                data as *mut Slot<T>,
+                capacity + 1,
+            ))),
+            wait: super::WaitStrategy::default(),
+            stats: super::stats::Counters::default(),
+            drop_order: super::DropOrder::default(),
         }
     }
+}
+
+impl<T: Send, const N: usize> super::NewQueue<T> for MaskedQueue<T, N> {
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-    #[profiling::function]
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        let () = Self::FIXED_CAPACITY_CHECK;
+        if (N != 0 && capacity != N) || capacity == 0 || capacity >= INDEX_MASK {
+            return Err(super::NewError::InvalidCapacity(capacity));
+        }
+        Ok(Self {
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            data: Slots::new(if N == 0 {
+                // In order to differentiate between empty and full states, we
+                // are never going to use the full array, so get one extra element.
+                super::try_alloc(capacity + 1, mem::MaybeUninit::uninit)?
+            } else {
+                Box::default()
+            }),
+            wait: super::WaitStrategy::default(),
+            stats: super::stats::Counters::default(),
+            drop_order: super::DropOrder::default(),
+        })
+    }
+}
+
+impl<T: Send, const N: usize> super::SynQueue<T> for MaskedQueue<T, N> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(queue = ?(self as *const Self)))
+    )]
     fn push(&self, value: T) -> Result<(), T> {
-        let (index, next) = match self.cas_acquire(&self.head, &self.tail, BoundsCheck::NewValue) {
+        let (index, next) = match self.cas_acquire(&self.head, &self.tail, BoundsCheck::Full) {
             Some(pair) => pair,
             None => return Err(value),
         };
+        trace_slot!(index);
         unsafe { super::UnsafeCellHelper::write(self.data.get_unchecked(index).as_ptr(), value) };
         self.cas_release(&self.head, next, index);
-        return Ok(());
+        Ok(())
     }
 
-    #[profiling::function]
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(queue = ?(self as *const Self)))
+    )]
     fn pop(&self) -> Option<T> {
-        let (index, next) = self.cas_acquire(&self.tail, &self.head, BoundsCheck::OldValue)?;
+        let (index, next) = self.cas_acquire(&self.tail, &self.head, BoundsCheck::Empty)?;
+        trace_slot!(index);
         let value = unsafe {
             self.data
                 .get_unchecked(index)
@@ -144,27 +271,43 @@ impl<T: Send> super::SynQueue<T> for MaskedQueue<T> {
         Some(value)
     }
 
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        self.pop().ok_or_else(|| {
+            // any bits above the index are pushes in flight
+            if self.head.load(super::LOAD_ORDER) & !INDEX_MASK != 0 {
+                super::TryPopError::Busy
+            } else {
+                super::TryPopError::Empty
+            }
+        })
+    }
+
     fn is_empty(&self) -> bool {
         let tail = self.tail.load(super::LOAD_ORDER);
         let head = self.head.load(super::LOAD_ORDER);
         head == tail
     }
+
+    fn is_full(&self) -> bool {
+        let head = self.head.load(super::LOAD_ORDER) & INDEX_MASK;
+        let tail = self.tail.load(super::LOAD_ORDER);
+        self.data.distance(self.get_last_used_index(tail), head) == self.data.capacity()
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
 }
 
-impl<T> Drop for MaskedQueue<T> {
+impl<T: Send, const N: usize> super::ExtendQueue<T> for MaskedQueue<T, N> {}
+
+impl<T, const N: usize> Drop for MaskedQueue<T, N> {
     fn drop(&mut self) {
         let head = self.head.load(super::LOAD_ORDER);
         let tail = self.tail.load(super::LOAD_ORDER);
         assert_eq!(head & !INDEX_MASK, 0);
         assert_eq!(tail & !INDEX_MASK, 0);
-        let mut cursor = tail;
-        while cursor != head {
-            unsafe { self.data[cursor].assume_init_drop() };
-            cursor += 1;
-            if cursor == self.data.len() {
-                cursor = 0;
-            }
-        }
+        unsafe { self.data.drop_range(tail, head, self.drop_order) };
     }
 }
 
@@ -178,7 +321,163 @@ fn smoke() {
     super::test_smoke::<MaskedQueue<i32>>();
 }
 
+#[test]
+fn close() {
+    super::test_close::<super::Closable<MaskedQueue<i32>>>();
+}
+
+#[test]
+fn odd_capacity() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let sq = MaskedQueue::<i32>::new(3);
+        for round in 0..3 {
+            for i in 0..3 {
+                sq.push(round * 3 + i).unwrap();
+            }
+            assert!(sq.is_full());
+            assert_eq!(sq.push(-1), Err(-1));
+            for i in 0..3 {
+                assert_eq!(sq.pop(), Some(round * 3 + i));
+            }
+            assert!(sq.is_empty());
+        }
+    })
+}
+
+#[test]
+fn fixed() {
+    super::test_overflow::<MaskedQueue<i32, 2>>();
+    super::test_smoke::<MaskedQueue<i32, 16>>();
+    super::test_differential::<MaskedQueue<u32, 4>>();
+}
+
 #[test]
 fn barrage() {
     super::test_barrage::<MaskedQueue<usize>>();
 }
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn raw_parts() {
+    super::test_raw_parts(
+        super::MaskedQueue::into_raw_parts,
+        |data, capacity, head, tail| unsafe {
+            super::MaskedQueue::from_raw_parts(data, capacity, head, tail)
+        },
+    );
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    // the failures can be spurious while operations are in flight, see the type docs
+    use super::lincheck::Failures;
+    super::lincheck::check_mpmc_with::<MaskedQueue<u32>>(Failures::InFlight);
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<MaskedQueue<u32>>();
+}
+
+/// A push that loaded the tail before it moved mustn't squeeze into a full queue.
+///
+/// The other thread doesn't push enough to bring the head index back
+/// to a value the push could have seen, ruling out an ABA on the head.
+#[cfg(all(feature = "shuttle", not(feature = "loom")))]
+#[test]
+fn stale_guard() {
+    use super::{
+        qstd::{sync::Arc, thread},
+        NewQueue as _, SynQueue as _,
+    };
+    shuttle::check_pct(
+        || {
+            let mq = Arc::new(MaskedQueue::<u32>::new(2));
+            mq.push(0).unwrap();
+            let mq2 = Arc::clone(&mq);
+            let other = thread::spawn(move || {
+                let popped = mq2.pop().is_some() as usize;
+                let pushed = mq2.push(1).is_ok() as usize + mq2.push(2).is_ok() as usize;
+                (pushed, popped)
+            });
+            let stale = mq.push(3).is_ok() as usize;
+            let (pushed, popped) = other.join().unwrap();
+            let mut remaining = 0;
+            while mq.pop().is_some() {
+                remaining += 1;
+            }
+            assert_eq!(1 + stale + pushed, popped + remaining);
+        },
+        10_000,
+        2,
+    );
+}
+
+/// Proofs of the index arithmetic, checked with `cargo kani --features verification`.
+///
+/// The rich values are built from an index and the in-flight bits above it.
+/// At most `slot_count - 1` operations can be in flight, since each of them holds
+/// a distinct slot, and `cas_acquire` waits for the top bit to be clear before shifting.
+#[cfg(all(kani, feature = "verification"))]
+mod verification {
+    use super::*;
+
+    const FLAG_BITS: usize = TOTAL_BITS - INDEX_BITS;
+
+    fn any_slot_count() -> usize {
+        let slot_count: usize = kani::any();
+        kani::assume(slot_count >= 2 && slot_count <= INDEX_MASK);
+        slot_count
+    }
+
+    fn any_index(slot_count: usize) -> usize {
+        let index: usize = kani::any();
+        kani::assume(index < slot_count);
+        index
+    }
+
+    #[kani::proof]
+    fn acquire_shifts_bits() {
+        let slot_count = any_slot_count();
+        let index = any_index(slot_count);
+        let flags: usize = kani::any();
+        kani::assume(flags < 1 << (FLAG_BITS - 1));
+        let main = index | (flags << INDEX_BITS);
+
+        let next = next_rich_index(main, slot_count);
+        assert_eq!(next & INDEX_MASK, (index + 1) % slot_count);
+        assert_eq!(next >> INDEX_BITS, (flags << 1) | 1);
+    }
+
+    #[kani::proof]
+    fn last_used_index_in_bounds() {
+        let slot_count = any_slot_count();
+        let index = any_index(slot_count);
+        let flags: usize = kani::any();
+        kani::assume(flags < 1 << FLAG_BITS.min(slot_count - 1));
+
+        let last = last_used_index(index | (flags << INDEX_BITS), slot_count);
+        assert!(last < slot_count);
+        let distance = TOTAL_BITS - flags.leading_zeros() as usize;
+        assert_eq!((last + distance) % slot_count, index);
+    }
+
+    #[kani::proof]
+    fn release_finds_bit() {
+        let slot_count = any_slot_count();
+        let done_index = any_index(slot_count);
+        // number of operations acquired after the one being released
+        let later: usize = kani::any();
+        kani::assume(later + 2 <= slot_count && later < FLAG_BITS);
+        let flags: usize = kani::any();
+        kani::assume(flags < 1 << FLAG_BITS);
+        let current =
+            ((done_index + 1 + later) % slot_count) | ((flags | (1 << later)) << INDEX_BITS);
+
+        let bit = release_bit(current, done_index, slot_count);
+        assert_eq!(bit, 1 << (INDEX_BITS + later));
+        assert!(current & bit != 0);
+    }
+}
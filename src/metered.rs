@@ -0,0 +1,131 @@
+use metrics::{counter, gauge, Counter, Gauge, SharedString};
+
+/// Queue wrapper reporting to the `metrics` facade.
+///
+/// Registers the following, labeled with `queue` set to the given name:
+///   - `synqueue_depth` gauge, the number of elements in the queue
+///   - `synqueue_pushes` counter, the successful pushes
+///   - `synqueue_pops` counter, the successful pops
+///   - `synqueue_full_rejections` counter, the pushes rejected because the queue was full
+///
+/// The handles are registered once in `new`, so the recorder has to be
+/// installed before the queue is wrapped.
+pub struct Metered<Q> {
+    queue: Q,
+    depth: Gauge,
+    pushes: Counter,
+    pops: Counter,
+    full_rejections: Counter,
+}
+
+impl<Q> Metered<Q> {
+    pub fn new(queue: Q, name: impl Into<SharedString>) -> Self {
+        let name = name.into();
+        Self {
+            queue,
+            depth: gauge!("synqueue_depth", "queue" => name.clone()),
+            pushes: counter!("synqueue_pushes", "queue" => name.clone()),
+            pops: counter!("synqueue_pops", "queue" => name.clone()),
+            full_rejections: counter!("synqueue_full_rejections", "queue" => name),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    fn popped(&self) {
+        self.pops.increment(1);
+        self.depth.decrement(1.0);
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> super::SynQueue<T> for Metered<Q> {
+    fn push(&self, value: T) -> Result<(), T> {
+        match self.queue.push(value) {
+            Ok(()) => {
+                self.pushes.increment(1);
+                self.depth.increment(1.0);
+                Ok(())
+            }
+            Err(value) => {
+                self.full_rejections.increment(1);
+                Err(value)
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let value = self.queue.pop()?;
+        self.popped();
+        Some(value)
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let value = self.queue.try_pop()?;
+        self.popped();
+        Ok(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn passthrough() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        // without a recorder, the handles do nothing
+        let mq = Metered::new(super::DoubleQueue::new(1), "test");
+        mq.push(1).unwrap();
+        assert_eq!(mq.push(2), Err(2));
+        assert_eq!(mq.pop(), Some(1));
+        assert_eq!(mq.try_pop(), Err(super::TryPopError::Empty));
+    })
+}
+
+#[test]
+fn recorded() {
+    use super::{NewQueue as _, SynQueue as _};
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    super::loom::model(|| {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let mq = Metered::new(super::DoubleQueue::new(2), "test");
+            mq.push(1).unwrap();
+            mq.push(2).unwrap();
+            assert_eq!(mq.push(3), Err(3));
+            assert_eq!(mq.pop(), Some(1));
+        });
+        let values = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let (_, key) = key.into_parts();
+                assert_eq!(key.labels().next().map(|l| l.value()), Some("test"));
+                let value = match value {
+                    DebugValue::Counter(count) => count as f64,
+                    DebugValue::Gauge(level) => level.into_inner(),
+                    DebugValue::Histogram(_) => unreachable!(),
+                };
+                (key.name().to_string(), value)
+            })
+            .collect::<std::collections::HashMap<_, _>>();
+        assert_eq!(values["synqueue_depth"], 1.0);
+        assert_eq!(values["synqueue_pushes"], 2.0);
+        assert_eq!(values["synqueue_pops"], 1.0);
+        assert_eq!(values["synqueue_full_rejections"], 1.0);
+    })
+}
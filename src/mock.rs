@@ -0,0 +1,145 @@
+use super::qstd::sync::Mutex;
+use std::{collections::VecDeque, thread, time::Duration};
+
+struct Script {
+    forced_full: bool,
+    forced_empty: bool,
+    push_failures: usize,
+    pop_failures: usize,
+    latency: Duration,
+}
+
+/// Test double with scriptable behavior.
+///
+/// Applications written against `SynQueue` can use it to test their backpressure
+/// and error paths deterministically: fullness and emptiness can be forced,
+/// failures injected, and every operation can be slowed down.
+/// Without any scripting, it behaves as a regular bounded FIFO queue.
+pub struct MockQueue<T> {
+    items: Mutex<VecDeque<T>>,
+    script: Mutex<Script>,
+    capacity: usize,
+}
+
+impl<T> MockQueue<T> {
+    /// Make every push fail as if the queue was full.
+    pub fn set_full(&self, full: bool) {
+        self.script.lock().unwrap().forced_full = full;
+    }
+
+    /// Make every pop fail as if the queue was empty.
+    pub fn set_empty(&self, empty: bool) {
+        self.script.lock().unwrap().forced_empty = empty;
+    }
+
+    /// Make the next `count` pushes fail.
+    pub fn fail_pushes(&self, count: usize) {
+        self.script.lock().unwrap().push_failures = count;
+    }
+
+    /// Make the next `count` pops fail.
+    pub fn fail_pops(&self, count: usize) {
+        self.script.lock().unwrap().pop_failures = count;
+    }
+
+    /// Sleep for the given duration in every push and pop.
+    pub fn set_latency(&self, latency: Duration) {
+        self.script.lock().unwrap().latency = latency;
+    }
+
+    /// Check the script, returning `true` if the operation needs to fail.
+    fn rehearse(&self, pick: impl FnOnce(&mut Script) -> (bool, &mut usize)) -> bool {
+        let mut script = self.script.lock().unwrap();
+        let latency = script.latency;
+        let (forced, failures) = pick(&mut script);
+        let fail = forced || *failures != 0;
+        *failures = failures.saturating_sub(1);
+        drop(script);
+        if !latency.is_zero() {
+            thread::sleep(latency);
+        }
+        fail
+    }
+}
+
+impl<T: Send> super::NewQueue<T> for MockQueue<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            script: Mutex::new(Script {
+                forced_full: false,
+                forced_empty: false,
+                push_failures: 0,
+                pop_failures: 0,
+                latency: Duration::ZERO,
+            }),
+            capacity,
+        }
+    }
+}
+
+impl<T: Send> super::SynQueue<T> for MockQueue<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        if self.rehearse(|s| (s.forced_full, &mut s.push_failures)) {
+            return Err(value);
+        }
+        let mut items = self.items.lock().unwrap();
+        if items.len() == self.capacity {
+            return Err(value);
+        }
+        items.push_back(value);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        if self.rehearse(|s| (s.forced_empty, &mut s.pop_failures)) {
+            return None;
+        }
+        self.items.lock().unwrap().pop_front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.script.lock().unwrap().forced_empty || self.items.lock().unwrap().is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.script.lock().unwrap().forced_full || self.items.lock().unwrap().len() == self.capacity
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<MockQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<MockQueue<i32>>();
+}
+
+#[test]
+fn scripted() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let mq = MockQueue::new(4);
+        mq.fail_pushes(1);
+        assert_eq!(mq.push(1), Err(1));
+        mq.push(2).unwrap();
+        mq.set_full(true);
+        assert_eq!(mq.push(3), Err(3));
+        mq.set_full(false);
+        mq.push(4).unwrap();
+        mq.fail_pops(1);
+        assert_eq!(mq.pop(), None);
+        mq.set_empty(true);
+        assert!(mq.is_empty());
+        assert_eq!(mq.pop(), None);
+        mq.set_empty(false);
+        assert_eq!(mq.pop(), Some(2));
+        assert_eq!(mq.pop(), Some(4));
+    })
+}
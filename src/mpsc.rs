@@ -0,0 +1,331 @@
+use super::qstd::{
+    cell::UnsafeCell,
+    hint,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use std::mem;
+
+/// A multi-producer single-consumer queue.
+///
+/// ## Principle
+/// Producers work like in `DoubleQueue`: a CAS loop on `claimed` acquires a slot,
+/// the data is written, and then `head` catches up in the order of the claims.
+/// The consumer is the only one moving the tail, so popping is a plain load
+/// of the slot followed by a store of the new tail, without any CAS loop.
+///
+/// ## Internal invariants.
+/// Considering an infinite sequence (without wraparounds):
+///  `tail <= head <= claimed`
+///
+/// ## Single consumer
+/// The queue isn't used directly: `split` turns it into the `MpscProducer`
+/// and `MpscConsumer` halves. Only the producer can be cloned,
+/// and the single consumer is enforced by it requiring `&mut self`.
+pub struct MpscQueue<T> {
+    claimed: AtomicUsize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    data: Box<[mem::MaybeUninit<UnsafeCell<T>>]>,
+    wait: super::WaitStrategy,
+}
+
+unsafe impl<T: Send> Sync for MpscQueue<T> {}
+
+impl<T: Send> MpscQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            claimed: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            data: (0..=capacity).map(|_| mem::MaybeUninit::uninit()).collect(),
+            wait: super::WaitStrategy::default(),
+        }
+    }
+
+    /// Split the queue into the producer and consumer halves.
+    pub fn split(self) -> (MpscProducer<T>, MpscConsumer<T>) {
+        let shared = Arc::new(self);
+        (
+            MpscProducer {
+                queue: Arc::clone(&shared),
+            },
+            MpscConsumer { queue: shared },
+        )
+    }
+}
+
+impl<T> MpscQueue<T> {
+    fn advance(&self, index: usize) -> usize {
+        if index + 1 == self.data.len() {
+            0
+        } else {
+            index + 1
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len() - 1
+    }
+
+    /// Override the process-wide default wait strategy.
+    pub fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+}
+
+impl<T> Drop for MpscQueue<T> {
+    fn drop(&mut self) {
+        let head = self.head.load(super::LOAD_ORDER);
+        assert_eq!(head, self.claimed.load(super::LOAD_ORDER));
+        let tail = self.tail.load(super::LOAD_ORDER);
+        unsafe { super::drop_slots(&mut self.data, tail, head, super::DropOrder::Fifo) };
+    }
+}
+
+/// The producing half of `MpscQueue`, which can be cloned for every producer.
+pub struct MpscProducer<T> {
+    queue: Arc<MpscQueue<T>>,
+}
+
+impl<T> Clone for MpscProducer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: Arc::clone(&self.queue),
+        }
+    }
+}
+
+impl<T: Send> MpscProducer<T> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let q = &*self.queue;
+        // acquire a new position
+        let mut head = q.claimed.load(super::LOAD_ORDER);
+        let next = loop {
+            trace!("Push pre-CAS: {:x}", head);
+            let next = q.advance(head);
+            if next == q.tail.load(super::LOAD_ORDER) {
+                return Err(value);
+            }
+            match q
+                .claimed
+                .compare_exchange_weak(head, next, super::CAS_ORDER, super::LOAD_ORDER)
+            {
+                Ok(_) => break next,
+                Err(other) => head = other,
+            }
+            hint::spin_loop();
+        };
+
+        trace!("Push success, next head = {:x}", next);
+        // write the data
+        unsafe { super::UnsafeCellHelper::write(q.data.get_unchecked(head).as_ptr(), value) };
+
+        // publish the slot after the earlier claims are published
+        let mut step = 0;
+        while q.head.load(super::LOAD_ORDER) != head {
+            q.wait.wait(step);
+            step = step.saturating_add(1);
+        }
+        q.head.store(next, super::STORE_ORDER);
+        Ok(())
+    }
+
+    pub fn is_full(&self) -> bool {
+        let q = &*self.queue;
+        q.advance(q.claimed.load(super::LOAD_ORDER)) == q.tail.load(super::LOAD_ORDER)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+/// The consuming half of `MpscQueue`.
+pub struct MpscConsumer<T> {
+    queue: Arc<MpscQueue<T>>,
+}
+
+impl<T: Send> MpscConsumer<T> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn pop(&mut self) -> Option<T> {
+        let q = &*self.queue;
+        // only the consumer moves the tail
+        let tail = q.tail.load(Ordering::Relaxed);
+        if tail == q.head.load(super::LOAD_ORDER) {
+            return None;
+        }
+        trace!("Pop tail = {:x}", tail);
+        let value = unsafe { q.data.get_unchecked(tail).assume_init_read().into_inner() };
+        q.tail.store(q.advance(tail), super::STORE_ORDER);
+        Some(value)
+    }
+
+    /// Pop the oldest element only if the predicate accepts it.
+    ///
+    /// This is only possible because there is a single consumer:
+    /// nobody else can take the element while it's being inspected.
+    pub fn pop_if(&mut self, pred: impl FnOnce(&T) -> bool) -> Option<T> {
+        let q = &*self.queue;
+        let tail = q.tail.load(Ordering::Relaxed);
+        if tail == q.head.load(super::LOAD_ORDER) {
+            return None;
+        }
+        let slot = unsafe { q.data.get_unchecked(tail) };
+        if !unsafe { super::UnsafeCellHelper::peek(slot.as_ptr(), pred) } {
+            return None;
+        }
+        let value = unsafe { slot.assume_init_read().into_inner() };
+        q.tail.store(q.advance(tail), super::STORE_ORDER);
+        Some(value)
+    }
+
+    pub fn try_pop(&mut self) -> Result<T, super::TryPopError> {
+        self.pop().ok_or_else(|| {
+            let q = &*self.queue;
+            // a push has acquired a position, but didn't publish it yet
+            if q.head.load(super::LOAD_ORDER) != q.claimed.load(super::LOAD_ORDER) {
+                super::TryPopError::Busy
+            } else {
+                super::TryPopError::Empty
+            }
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let q = &*self.queue;
+        q.claimed.load(super::LOAD_ORDER) == q.tail.load(super::LOAD_ORDER)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+/// Both halves behind `SynQueue`, for the generic tests.
+///
+/// The lock on the consumer is never contended with a single consuming thread.
+#[cfg(test)]
+struct Halves<T> {
+    producer: MpscProducer<T>,
+    consumer: super::qstd::sync::Mutex<MpscConsumer<T>>,
+}
+
+#[cfg(test)]
+impl<T: Send> super::NewQueue<T> for Halves<T> {
+    fn new(capacity: usize) -> Self {
+        let (producer, consumer) = MpscQueue::new(capacity).split();
+        Self {
+            producer,
+            consumer: super::qstd::sync::Mutex::new(consumer),
+        }
+    }
+}
+
+#[cfg(test)]
+impl<T: Send> super::SynQueue<T> for Halves<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.producer.push(value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.consumer.lock().unwrap().pop()
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        self.consumer.lock().unwrap().try_pop()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.consumer.lock().unwrap().is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.producer.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.producer.capacity()
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<Halves<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<Halves<i32>>();
+}
+
+#[test]
+fn pop_if() {
+    super::loom::model(|| {
+        let (tx, mut rx) = MpscQueue::new(4).split();
+        assert_eq!(rx.pop_if(|_| true), None::<i32>);
+        tx.push(1).unwrap();
+        tx.push(2).unwrap();
+        assert_eq!(rx.pop_if(|&v| v > 1), None);
+        assert_eq!(rx.pop_if(|&v| v == 1), Some(1));
+        assert_eq!(rx.pop_if(|&v| v > 1), Some(2));
+        assert!(rx.is_empty());
+    })
+}
+
+#[test]
+fn producers() {
+    use super::qstd::thread;
+
+    super::loom::model(|| {
+        const NUM_THREADS: usize = 2;
+        const NUM_ELEMENTS: usize = if cfg!(miri) { 1 << 5 } else { 1 << 12 };
+        let (tx, mut rx) = MpscQueue::new(16).split();
+        let handles = (0..NUM_THREADS)
+            .map(|t| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..NUM_ELEMENTS {
+                        let mut value = (t, i);
+                        while let Err(v) = tx.push(value) {
+                            value = v;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // elements of every producer come out in order
+        let mut expected = [0; NUM_THREADS];
+        while expected.iter().any(|&e| e != NUM_ELEMENTS) {
+            match rx.pop() {
+                Some((t, i)) => {
+                    assert_eq!(expected[t], i);
+                    expected[t] += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(rx.is_empty());
+    })
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    use super::lincheck::Role;
+    super::lincheck::check::<Halves<u32>>(2, &[Role::Producer, Role::Producer, Role::Consumer]);
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<Halves<u32>>();
+}
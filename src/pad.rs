@@ -0,0 +1,123 @@
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+/// Size of the cache line assumed for padding.
+///
+/// It's 128 bytes with the `cache-line-128` feature, which matches the adjacent line
+/// prefetcher of modern x86 CPUs and the cache lines of Apple silicon.
+pub const CACHE_LINE: usize = if cfg!(feature = "cache-line-128") {
+    128
+} else {
+    64
+};
+
+/// Wrapper aligning a value to the cache line, so that it doesn't share
+/// the line with the neighbouring fields or array elements.
+#[cfg_attr(not(feature = "cache-line-128"), repr(C, align(64)))]
+#[cfg_attr(feature = "cache-line-128", repr(C, align(128)))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CachePadded<T>(T);
+
+impl<T> CachePadded<T> {
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Queue wrapper storing every element in its own cache line.
+///
+/// Small elements in adjacent slots share cache lines, so a push and a pop
+/// working on the neighbouring slots keep stealing the line from each other.
+/// Padding the slots to the cache line stride avoids that, at the cost of memory.
+/// The elements are wrapped and unwrapped transparently, so this can be used
+/// in place of the inner queue, e.g. `Padded<T, MaskedQueue<CachePadded<T>>>`.
+pub struct Padded<T, Q = super::DoubleQueue<CachePadded<T>>> {
+    queue: Q,
+    _marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T, Q> Padded<T, Q> {
+    pub fn new(queue: Q) -> Self {
+        Self {
+            queue,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+}
+
+impl<T: Send, Q: super::SynQueue<CachePadded<T>>> super::SynQueue<T> for Padded<T, Q> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.queue
+            .push(CachePadded(value))
+            .map_err(CachePadded::into_inner)
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.queue.pop().map(CachePadded::into_inner)
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        self.queue.try_pop().map(CachePadded::into_inner)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<T: Send, Q: super::NewQueue<CachePadded<T>>> super::NewQueue<T> for Padded<T, Q> {
+    fn new(capacity: usize) -> Self {
+        Self::new(Q::new(capacity))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        Q::try_new(capacity).map(Self::new)
+    }
+}
+
+#[test]
+fn alignment() {
+    assert_eq!(std::mem::align_of::<CachePadded<u8>>(), CACHE_LINE);
+    assert_eq!(std::mem::size_of::<CachePadded<u8>>(), CACHE_LINE);
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<Padded<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<Padded<i32, super::MaskedQueue<CachePadded<i32>>>>();
+}
@@ -0,0 +1,113 @@
+use std::{
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
+
+/// Pool of reusable objects, kept in a queue.
+///
+/// Taking an object pops it from the queue, and the returned guard
+/// pushes it back on drop, so any number of threads can share the pool.
+pub struct Pool<T, Q = super::DoubleQueue<T>> {
+    queue: Q,
+    _marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T, Q: super::NewQueue<T>> Pool<T, Q> {
+    /// Create a pool filled with `capacity` objects produced by `init`.
+    pub fn new(capacity: usize, mut init: impl FnMut() -> T) -> Self {
+        let queue = Q::new(capacity);
+        for _ in 0..capacity {
+            if queue.push(init()).is_err() {
+                unreachable!("Pool queue is too small");
+            }
+        }
+        Self::from_queue(queue)
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> Pool<T, Q> {
+    /// Create a pool from a queue with the objects already in it.
+    pub fn from_queue(queue: Q) -> Self {
+        Self {
+            queue,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    /// Take an object from the pool, if there is any left.
+    pub fn get(&self) -> Option<Pooled<'_, T, Q>> {
+        let value = self.queue.pop()?;
+        Some(Pooled {
+            pool: self,
+            value: ManuallyDrop::new(value),
+        })
+    }
+
+    /// Put an object into the pool, returning it back if there is no space.
+    pub fn put(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)
+    }
+}
+
+/// Object taken from a `Pool`, returned to it on drop.
+pub struct Pooled<'a, T, Q: super::SynQueue<T>> {
+    pool: &'a Pool<T, Q>,
+    value: ManuallyDrop<T>,
+}
+
+impl<T, Q: super::SynQueue<T>> Pooled<'_, T, Q> {
+    /// Take the object out, so that it doesn't return to the pool.
+    pub fn detach(self) -> T {
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.value) }
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> Deref for Pooled<'_, T, Q> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> DerefMut for Pooled<'_, T, Q> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> Drop for Pooled<'_, T, Q> {
+    fn drop(&mut self) {
+        let value = unsafe { ManuallyDrop::take(&mut self.value) };
+        // the pool can only be full if objects were added with `put`
+        let _ = self.pool.put(value);
+    }
+}
+
+#[test]
+fn reuse() {
+    super::loom::model(|| {
+        let mut counter = 0;
+        let pool = Pool::<Vec<u32>>::new(2, || {
+            counter += 1;
+            Vec::with_capacity(counter)
+        });
+        let mut first = pool.get().unwrap();
+        first.push(1);
+        let second = pool.get().unwrap();
+        assert!(pool.get().is_none());
+        drop(first);
+        let again = pool.get().unwrap();
+        assert_eq!(*again, [1]);
+        assert!(second.detach().capacity() >= 2);
+        drop(again);
+        assert!(pool.get().is_some());
+        assert_eq!(pool.put(Vec::new()), Ok(()));
+        assert!(pool.put(Vec::new()).is_err());
+    })
+}
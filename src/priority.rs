@@ -0,0 +1,145 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+use std::marker::PhantomData;
+
+/// Strict priority queue composed of one queue per level.
+///
+/// Level 0 has the highest priority, and `pop` only looks at a level
+/// if all the levels above it are empty, like `pop_biased` does.
+/// Each level has its own capacity, so a flood of low-priority elements
+/// can't prevent the high-priority ones from being pushed.
+///
+/// ## Aging
+/// Under a sustained load of high-priority elements, the lower levels starve.
+/// With `with_aging`, every pop served from a higher level ages the non-empty
+/// levels below it, and a level that has aged enough is promoted to the top
+/// for the next pop.
+pub struct PriorityQueue<T, const LEVELS: usize, Q = super::DoubleQueue<T>> {
+    levels: [Q; LEVELS],
+    /// Pops served from the higher levels while each level was waiting.
+    ages: [AtomicUsize; LEVELS],
+    max_age: Option<usize>,
+    _marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T, const LEVELS: usize, Q: super::NewQueue<T>> PriorityQueue<T, LEVELS, Q> {
+    /// Create a queue with the given capacity for each level.
+    pub fn new(capacity: usize) -> Self {
+        Self::from_levels(std::array::from_fn(|_| Q::new(capacity)))
+    }
+}
+
+impl<T, const LEVELS: usize, Q: super::SynQueue<T>> PriorityQueue<T, LEVELS, Q> {
+    /// Fails the build for a queue without any levels.
+    const LEVELS_CHECK: () = assert!(LEVELS != 0, "a priority queue needs at least one level");
+
+    /// Create a queue from the existing ones, ordered from the highest priority.
+    pub fn from_levels(levels: [Q; LEVELS]) -> Self {
+        let () = Self::LEVELS_CHECK;
+        Self {
+            levels,
+            ages: std::array::from_fn(|_| AtomicUsize::new(0)),
+            max_age: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Promote a level once it has waited for `max_age` pops from the higher levels.
+    pub fn with_aging(mut self, max_age: usize) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn level(&self, priority: usize) -> &Q {
+        &self.levels[priority]
+    }
+
+    /// Push an element with the given priority, where 0 is the highest.
+    ///
+    /// A priority without a level is rejected, like a push into a full level.
+    pub fn push(&self, priority: usize, value: T) -> Result<(), T> {
+        match self.levels.get(priority) {
+            Some(level) => level.push(value),
+            None => Err(value),
+        }
+    }
+
+    /// Pop the element with the highest priority, returning its priority with the value.
+    ///
+    /// With aging, a level that has waited long enough goes first,
+    /// the lowest one if there are several.
+    pub fn pop_indexed(&self) -> Option<(usize, T)> {
+        let Some(max_age) = self.max_age else {
+            return super::pop_biased(&self.levels);
+        };
+        for (index, age) in self.ages.iter().enumerate().rev() {
+            if age.load(Ordering::Relaxed) >= max_age {
+                age.store(0, Ordering::Relaxed);
+                if let Some(value) = self.levels[index].pop() {
+                    return Some((index, value));
+                }
+            }
+        }
+        let (index, value) = super::pop_biased(&self.levels)?;
+        for (level, age) in self.levels.iter().zip(&self.ages).skip(index + 1) {
+            if !level.is_empty() {
+                age.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Some((index, value))
+    }
+
+    /// Pop the element with the highest priority.
+    pub fn pop(&self) -> Option<T> {
+        self.pop_indexed().map(|(_, value)| value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.iter().all(|level| level.is_empty())
+    }
+
+    /// Total capacity of all the levels.
+    pub fn capacity(&self) -> usize {
+        self.levels.iter().map(|level| level.capacity()).sum()
+    }
+}
+
+#[test]
+fn priorities() {
+    super::loom::model(|| {
+        let pq = PriorityQueue::<i32, 3>::new(2);
+        assert_eq!(pq.capacity(), 6);
+        pq.push(2, 20).unwrap();
+        pq.push(1, 10).unwrap();
+        pq.push(2, 21).unwrap();
+        assert_eq!(pq.push(2, 22), Err(22));
+        assert_eq!(pq.push(3, 30), Err(30));
+        pq.push(0, 0).unwrap();
+        assert_eq!(pq.pop_indexed(), Some((0, 0)));
+        assert_eq!(pq.pop(), Some(10));
+        assert_eq!(pq.pop(), Some(20));
+        pq.push(1, 11).unwrap();
+        assert_eq!(pq.pop(), Some(11));
+        assert_eq!(pq.pop(), Some(21));
+        assert!(pq.is_empty());
+        assert_eq!(pq.pop(), None);
+    })
+}
+
+#[test]
+fn aging() {
+    super::loom::model(|| {
+        let pq = PriorityQueue::<i32, 3>::new(4).with_aging(2);
+        pq.push(2, 20).unwrap();
+        pq.push(1, 10).unwrap();
+        // sustained high-priority load
+        let mut popped = Vec::new();
+        for i in 0..6 {
+            pq.push(0, i).unwrap();
+            popped.push(pq.pop_indexed().unwrap());
+        }
+        assert_eq!(popped, [(0, 0), (0, 1), (2, 20), (1, 10), (0, 2), (0, 3)]);
+        assert_eq!(pq.pop(), Some(4));
+        assert_eq!(pq.pop(), Some(5));
+        assert!(pq.is_empty());
+    })
+}
@@ -0,0 +1,109 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+
+/// Queue wrapper with a sampled drop policy on overflow.
+///
+/// A plain `SynQueue` rejects the newest element when full, which most
+/// producers just drop on the floor. `SampledQueue` makes the choice explicit:
+/// one in `period` overflows evicts the oldest element to make room for the new one,
+/// while the others reject the new element, handing it back from `push`.
+/// If another producer takes the freed slot before the new element gets in,
+/// that overflow both evicts the oldest element and rejects the new one.
+/// All of them are accounted for in the counters.
+pub struct SampledQueue<Q> {
+    queue: Q,
+    period: usize,
+    overflows: AtomicUsize,
+    dropped_newest: AtomicUsize,
+    dropped_oldest: AtomicUsize,
+}
+
+impl<Q> SampledQueue<Q> {
+    pub fn new(queue: Q, period: usize) -> Self {
+        assert_ne!(period, 0);
+        Self {
+            queue,
+            period,
+            overflows: AtomicUsize::new(0),
+            dropped_newest: AtomicUsize::new(0),
+            dropped_oldest: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    /// Number of pushes that found the queue full.
+    pub fn overflows(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    /// Number of new elements rejected on overflow.
+    pub fn dropped_newest(&self) -> usize {
+        self.dropped_newest.load(Ordering::Relaxed)
+    }
+
+    /// Number of old elements evicted on overflow.
+    pub fn dropped_oldest(&self) -> usize {
+        self.dropped_oldest.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, Q> super::SynQueue<T> for SampledQueue<Q>
+where
+    Q: super::SynQueue<T>,
+{
+    fn push(&self, value: T) -> Result<(), T> {
+        let value = match self.queue.push(value) {
+            Ok(()) => return Ok(()),
+            Err(value) => value,
+        };
+        let overflow = self.overflows.fetch_add(1, Ordering::Relaxed);
+        let value = if overflow.is_multiple_of(self.period) && self.queue.pop().is_some() {
+            self.dropped_oldest.fetch_add(1, Ordering::Relaxed);
+            match self.queue.push(value) {
+                Ok(()) => return Ok(()),
+                // somebody else took the freed slot, so this overflow cost us two
+                Err(value) => value,
+            }
+        } else {
+            value
+        };
+        self.dropped_newest.fetch_add(1, Ordering::Relaxed);
+        Err(value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn overflow() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let sq = SampledQueue::new(super::DoubleQueue::new(2), 2);
+        for i in 0..6 {
+            assert_eq!(sq.push(i).is_ok(), i % 2 == 0 || i < 2);
+        }
+        // pushing 2 and 4 evicts, while 3 and 5 are dropped
+        assert_eq!(sq.dropped_oldest(), 2);
+        assert_eq!(sq.dropped_newest(), 2);
+        assert_eq!(sq.overflows(), 4);
+        assert_eq!(sq.pop(), Some(2));
+        assert_eq!(sq.pop(), Some(4));
+        assert_eq!(sq.pop(), None::<i32>);
+    })
+}
@@ -0,0 +1,81 @@
+use arbitrary::{Arbitrary, Result, Unstructured};
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, PartialEq, Arbitrary)]
+pub enum QueueKind {
+    Axel,
+    Double,
+    Masked,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Arbitrary)]
+pub enum Operation {
+    Push(u32),
+    Pop,
+}
+
+/// Structured single-threaded scenario for fuzzing.
+///
+/// The capacity is between 1 and 64, which every queue kind accepts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    pub kind: QueueKind,
+    pub capacity: usize,
+    pub operations: Vec<Operation>,
+}
+
+impl<'a> Arbitrary<'a> for Scenario {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            kind: u.arbitrary()?,
+            capacity: u.int_in_range(1..=64)?,
+            operations: u.arbitrary()?,
+        })
+    }
+}
+
+impl Scenario {
+    /// Run the scenario, checking every result against a sequential model.
+    pub fn run(&self) {
+        match self.kind {
+            QueueKind::Axel => self.run_with::<super::AxelQueue<u32>>(),
+            QueueKind::Double => self.run_with::<super::DoubleQueue<u32>>(),
+            QueueKind::Masked => self.run_with::<super::MaskedQueue<u32>>(),
+        }
+    }
+
+    fn run_with<Q: super::NewQueue<u32>>(&self) {
+        let queue = Q::new(self.capacity);
+        let mut model = VecDeque::with_capacity(self.capacity);
+        for &op in self.operations.iter() {
+            match op {
+                Operation::Push(value) => {
+                    let expected = if model.len() < self.capacity {
+                        model.push_back(value);
+                        Ok(())
+                    } else {
+                        Err(value)
+                    };
+                    assert_eq!(queue.push(value), expected, "{:?}", op);
+                }
+                Operation::Pop => {
+                    assert_eq!(queue.pop(), model.pop_front(), "{:?}", op);
+                }
+            }
+            assert_eq!(queue.is_empty(), model.is_empty());
+            assert_eq!(queue.is_full(), model.len() == self.capacity);
+        }
+    }
+}
+
+#[test]
+fn replay() {
+    let bytes = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+    let mut u = Unstructured::new(&bytes);
+    while let Ok(scenario) = Scenario::arbitrary(&mut u) {
+        super::loom::model(|| scenario.run());
+        if u.is_empty() {
+            break;
+        }
+    }
+}
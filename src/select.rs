@@ -0,0 +1,139 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+
+/// Fair round-robin consumption across multiple queues, such as shards.
+///
+/// Every call starts polling from the queue next to the one where
+/// the previous call started, so each non-empty queue is guaranteed
+/// to be polled first once in `queues.len()` calls, regardless of
+/// how busy the other queues are.
+#[derive(Default)]
+pub struct RoundRobin {
+    cursor: AtomicUsize,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pop from the first non-empty queue, returning its index with the value.
+    pub fn pop_indexed<T, Q: super::SynQueue<T>>(&self, queues: &[Q]) -> Option<(usize, T)> {
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        (0..queues.len()).find_map(|offset| {
+            let index = start.wrapping_add(offset) % queues.len();
+            queues[index].pop().map(|value| (index, value))
+        })
+    }
+
+    pub fn pop<T, Q: super::SynQueue<T>>(&self, queues: &[Q]) -> Option<T> {
+        self.pop_indexed(queues).map(|(_, value)| value)
+    }
+}
+
+/// Pop from the first non-empty queue in the slice order, returning its index with the value.
+///
+/// This is a strict priority: a queue is only polled if all the preceding ones are empty,
+/// so a busy high-priority queue can starve the rest.
+pub fn pop_biased<T, Q: super::SynQueue<T>>(queues: &[Q]) -> Option<(usize, T)> {
+    queues
+        .iter()
+        .enumerate()
+        .find_map(|(index, queue)| queue.pop().map(|value| (index, value)))
+}
+
+/// Weighted consumption across multiple queues.
+///
+/// Out of every `sum(weights)` calls, each queue is polled first in
+/// the number of calls equal to its weight. If that queue is empty,
+/// the others are polled in order, so no value is left behind while
+/// any queue has some.
+pub struct Weighted {
+    bounds: Box<[usize]>,
+    cursor: AtomicUsize,
+}
+
+impl Weighted {
+    pub fn new(weights: &[usize]) -> Self {
+        let bounds = weights
+            .iter()
+            .scan(0, |total, &weight| {
+                *total += weight;
+                Some(*total)
+            })
+            .collect::<Box<[_]>>();
+        assert_ne!(bounds.last().copied().unwrap_or(0), 0);
+        Self {
+            bounds,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pop from the first non-empty queue, returning its index with the value.
+    pub fn pop_indexed<T, Q: super::SynQueue<T>>(&self, queues: &[Q]) -> Option<(usize, T)> {
+        assert_eq!(queues.len(), self.bounds.len());
+        let total = self.bounds[self.bounds.len() - 1];
+        let tick = self.cursor.fetch_add(1, Ordering::Relaxed) % total;
+        let start = self.bounds.partition_point(|&end| end <= tick);
+        (0..queues.len()).find_map(|offset| {
+            let index = (start + offset) % queues.len();
+            queues[index].pop().map(|value| (index, value))
+        })
+    }
+
+    pub fn pop<T, Q: super::SynQueue<T>>(&self, queues: &[Q]) -> Option<T> {
+        self.pop_indexed(queues).map(|(_, value)| value)
+    }
+}
+
+#[test]
+fn round_robin() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let queues = [super::DoubleQueue::new(4), super::DoubleQueue::new(4)];
+        for i in 0..3 {
+            queues[0].push(i).unwrap();
+        }
+        queues[1].push(10).unwrap();
+        let rr = RoundRobin::new();
+        assert_eq!(rr.pop_indexed(&queues), Some((0, 0)));
+        assert_eq!(rr.pop_indexed(&queues), Some((1, 10)));
+        assert_eq!(rr.pop(&queues), Some(1));
+        assert_eq!(rr.pop(&queues), Some(2));
+        assert_eq!(rr.pop(&queues), None);
+    })
+}
+
+#[test]
+fn biased() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let queues = [super::DoubleQueue::new(4), super::DoubleQueue::new(4)];
+        queues[0].push(0).unwrap();
+        queues[1].push(10).unwrap();
+        queues[1].push(11).unwrap();
+        assert_eq!(pop_biased(&queues), Some((0, 0)));
+        assert_eq!(pop_biased(&queues), Some((1, 10)));
+        queues[0].push(1).unwrap();
+        assert_eq!(pop_biased(&queues), Some((0, 1)));
+        assert_eq!(pop_biased(&queues), Some((1, 11)));
+        assert_eq!(pop_biased(&queues), None);
+    })
+}
+
+#[test]
+fn weighted() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let queues = [super::DoubleQueue::new(8), super::DoubleQueue::new(8)];
+        for i in 0..6 {
+            queues[0].push(i).unwrap();
+        }
+        queues[1].push(10).unwrap();
+        let w = Weighted::new(&[3, 1]);
+        let indices = (0..7)
+            .map(|_| w.pop_indexed(&queues).unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(w.pop(&queues), None);
+    })
+}
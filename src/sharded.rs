@@ -0,0 +1,232 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+use std::{marker::PhantomData, sync::atomic::AtomicUsize as StaticCounter};
+
+// Loom atomics can't be used in statics.
+static NEXT_THREAD: StaticCounter = StaticCounter::new(0);
+
+std::thread_local! {
+    static THREAD_INDEX: usize = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Queue striped across multiple shards for throughput.
+///
+/// Every thread has a home shard, which it pushes to and pops from first,
+/// so the threads mostly contend on different atomics.
+/// When the home shard is full or empty, the other shards are tried in order.
+///
+/// ## Ordering
+/// This is not a FIFO queue. Only the elements within the same shard
+/// are popped in the order they were pushed. The elements pushed by one thread
+/// keep their order as long as its home shard doesn't overflow and the consumers
+/// don't fall back to other shards, which can't be relied upon in general.
+///
+/// ## Tokens
+/// Instead of the home shard of the thread, the operations can start from
+/// the shard cached in a token. The tokens are assigned to the shards in turns,
+/// and then stick to the shard where the last operation succeeded.
+pub struct ShardedQueue<T, Q = super::DoubleQueue<T>> {
+    shards: Box<[Q]>,
+    next_token: AtomicUsize,
+    _marker: PhantomData<fn(T) -> T>,
+}
+
+impl<T, Q: super::NewQueue<T>> ShardedQueue<T, Q> {
+    /// Create a queue with the total capacity spread evenly across the shards.
+    pub fn with_shards(shard_count: usize, capacity: usize) -> Self {
+        Self::try_with_shards(shard_count, capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `with_shards`, but returns an error if the capacity isn't supported.
+    ///
+    /// Every shard needs a non-zero capacity, which the inner queue has to support.
+    pub fn try_with_shards(shard_count: usize, capacity: usize) -> Result<Self, super::NewError> {
+        if shard_count == 0 || capacity < shard_count {
+            return Err(super::NewError::InvalidCapacity(capacity));
+        }
+        let shards = (0..shard_count)
+            .map(|i| Q::try_new(capacity / shard_count + (i < capacity % shard_count) as usize))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_shards(shards))
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> ShardedQueue<T, Q> {
+    pub fn from_shards(shards: Vec<Q>) -> Self {
+        assert!(!shards.is_empty());
+        Self {
+            shards: shards.into_boxed_slice(),
+            next_token: AtomicUsize::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn shards(&self) -> &[Q] {
+        &self.shards
+    }
+
+    /// Index of the shard the current thread works with first.
+    pub fn home_shard(&self) -> usize {
+        THREAD_INDEX.with(|&index| index % self.shards.len())
+    }
+
+    /// Iterate over the shards, starting with the home one.
+    fn shards_from_home(&self) -> impl Iterator<Item = &Q> {
+        self.shards_from(self.home_shard()).map(|(_, shard)| shard)
+    }
+
+    /// Iterate over the shards with their indices, starting with the given one.
+    fn shards_from(&self, start: usize) -> impl Iterator<Item = (usize, &Q)> {
+        let start = start % self.shards.len();
+        let (before, after) = self.shards.split_at(start);
+        (start..).zip(after).chain((0..).zip(before))
+    }
+
+    fn next_hint(&self) -> usize {
+        self.next_token.fetch_add(1, Ordering::Relaxed) % self.shards.len()
+    }
+
+    /// Create a token with its own shard assignment for `push_with_token`.
+    pub fn producer_token(&self) -> super::ProducerToken {
+        super::ProducerToken {
+            hint: self.next_hint(),
+        }
+    }
+
+    /// Create a token with its own shard assignment for `pop_with_token`.
+    pub fn consumer_token(&self) -> super::ConsumerToken {
+        super::ConsumerToken {
+            hint: self.next_hint(),
+        }
+    }
+
+    /// Push an element, starting from the shard cached in the token.
+    pub fn push_with_token(&self, token: &mut super::ProducerToken, mut value: T) -> Result<(), T> {
+        for (index, shard) in self.shards_from(token.hint) {
+            match shard.push(value) {
+                Ok(()) => {
+                    token.hint = index;
+                    return Ok(());
+                }
+                Err(other) => value = other,
+            }
+        }
+        Err(value)
+    }
+
+    /// Pop an element, starting from the shard cached in the token.
+    pub fn pop_with_token(&self, token: &mut super::ConsumerToken) -> Option<T> {
+        self.shards_from(token.hint).find_map(|(index, shard)| {
+            let value = shard.pop()?;
+            token.hint = index;
+            Some(value)
+        })
+    }
+}
+
+impl<T: Send, Q: super::SynQueue<T>> super::SynQueue<T> for ShardedQueue<T, Q> {
+    fn push(&self, mut value: T) -> Result<(), T> {
+        for shard in self.shards_from_home() {
+            match shard.push(value) {
+                Ok(()) => return Ok(()),
+                Err(other) => value = other,
+            }
+        }
+        Err(value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.shards_from_home().find_map(|shard| shard.pop())
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let mut error = super::TryPopError::Empty;
+        for shard in self.shards_from_home() {
+            match shard.try_pop() {
+                Ok(value) => return Ok(value),
+                Err(super::TryPopError::Busy) => error = super::TryPopError::Busy,
+                Err(_) => {}
+            }
+        }
+        Err(error)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_empty())
+    }
+
+    fn is_full(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_full())
+    }
+
+    fn capacity(&self) -> usize {
+        self.shards.iter().map(|shard| shard.capacity()).sum()
+    }
+}
+
+impl<T: Send, Q: super::NewQueue<T>> super::NewQueue<T> for ShardedQueue<T, Q> {
+    /// Create a shard per available CPU, but no more than `capacity`.
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::try_with_shards(parallelism.min(capacity).max(1), capacity)
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<ShardedQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<ShardedQueue<i32>>();
+}
+
+#[test]
+fn spill_over() {
+    use super::SynQueue as _;
+    super::loom::model(|| {
+        let sq = ShardedQueue::<i32>::with_shards(3, 7);
+        assert_eq!(sq.capacity(), 7);
+        let home = sq.home_shard();
+        for i in 0..7 {
+            sq.push(i).unwrap();
+        }
+        assert!(sq.is_full());
+        assert_eq!(sq.push(7), Err(7));
+        assert!(sq.shards()[home].is_full());
+        // the home shard is drained first, in order
+        let home_len = sq.shards()[home].capacity() as i32;
+        for i in 0..home_len {
+            assert_eq!(sq.pop(), Some(i));
+        }
+        let mut rest = sq.try_iter().collect::<Vec<_>>();
+        rest.sort();
+        assert_eq!(rest, (home_len..7).collect::<Vec<_>>());
+    })
+}
+
+#[test]
+fn tokens() {
+    use super::SynQueue as _;
+    super::loom::model(|| {
+        let sq = ShardedQueue::<i32>::with_shards(2, 4);
+        let mut first = sq.producer_token();
+        let mut second = sq.producer_token();
+        let mut consumer = sq.consumer_token();
+        for i in 0..2 {
+            sq.push_with_token(&mut first, i).unwrap();
+            sq.push_with_token(&mut second, 10 + i).unwrap();
+        }
+        assert!(sq.shards().iter().all(|shard| shard.is_full()));
+        // the consumer sticks to a shard until it's drained
+        let a = sq.pop_with_token(&mut consumer).unwrap();
+        assert_eq!(sq.pop_with_token(&mut consumer), Some(a + 1));
+        let b = sq.pop_with_token(&mut consumer).unwrap();
+        assert_eq!(sq.pop_with_token(&mut consumer), Some(b + 1));
+        assert_eq!(sq.pop_with_token(&mut consumer), None);
+    })
+}
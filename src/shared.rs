@@ -0,0 +1,309 @@
+//! Queue living in memory shared between processes.
+
+use super::double::{Pointer, Positions, Ring};
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+use std::{fmt, mem, ptr};
+
+const INITIALIZING: usize = 1;
+/// "SYNQUEUE" in ASCII, truncated on 32-bit targets.
+const READY: usize = 0x5359_4E51_5545_5545u64 as usize;
+
+/// Header at the start of the shared region.
+///
+/// All the references into the region are offsets from its start,
+/// so it can be mapped at different addresses in different processes.
+#[repr(C)]
+struct Header {
+    status: AtomicUsize,
+    header_size: usize,
+    element_size: usize,
+    element_align: usize,
+    slot_count: usize,
+    data_offset: usize,
+    positions: Positions,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SharedError {
+    /// The region isn't aligned for the header or the elements.
+    Misaligned,
+    /// The region is too small for the requested capacity or the recorded one.
+    TooSmall,
+    /// The capacity is not supported.
+    InvalidCapacity(usize),
+    /// The region has already been initialized, or is being initialized.
+    AlreadyInitialized,
+    /// The region hasn't been initialized yet.
+    NotInitialized,
+    /// The region was initialized for different elements or by a different build.
+    LayoutMismatch,
+}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Misaligned => write!(f, "the shared region is misaligned"),
+            Self::TooSmall => write!(f, "the shared region is too small"),
+            Self::InvalidCapacity(capacity) => write!(f, "capacity {} is not supported", capacity),
+            Self::AlreadyInitialized => write!(f, "the shared region is already initialized"),
+            Self::NotInitialized => write!(f, "the shared region is not initialized"),
+            Self::LayoutMismatch => write!(f, "the shared region has a different layout"),
+        }
+    }
+}
+
+impl std::error::Error for SharedError {}
+
+/// `DoubleQueue` over a memory region shared between processes, such as a memory-mapped file.
+///
+/// ## Protocol
+/// One process creates the region, zero-filled and at least `required_size` bytes,
+/// and calls `init` on it. The others call `attach`, which fails with
+/// `SharedError::NotInitialized` until the initialization is complete.
+/// Every process gets its own handle, which can be used from multiple threads.
+///
+/// The whole state is kept in the region, and nothing in it refers to the addresses
+/// of a particular process. For the same reason, the elements have to be plain data:
+/// they are `Copy`, and they shouldn't contain pointers or references.
+///
+/// The processes waiting on each other can only spin or yield,
+/// so `WaitStrategy::SpinThenPark` behaves like `SpinThenYield` here.
+pub struct SharedQueue<T> {
+    header: *const Header,
+    data: *mut T,
+    wait: super::WaitStrategy,
+}
+
+unsafe impl<T: Send> Send for SharedQueue<T> {}
+unsafe impl<T: Send> Sync for SharedQueue<T> {}
+
+impl<T: Copy> SharedQueue<T> {
+    fn data_offset() -> usize {
+        mem::size_of::<Header>().next_multiple_of(mem::align_of::<T>())
+    }
+
+    /// Alignment required for the region.
+    pub fn required_align() -> usize {
+        mem::align_of::<Header>().max(mem::align_of::<T>())
+    }
+
+    /// Size of the region required for the given capacity.
+    ///
+    /// Panics if the size overflows `usize`.
+    pub fn required_size(capacity: usize) -> usize {
+        Self::checked_size(capacity).expect("the shared region size overflows")
+    }
+
+    fn checked_size(capacity: usize) -> Option<usize> {
+        // one extra slot differentiates between empty and full states
+        capacity
+            .checked_add(1)?
+            .checked_mul(mem::size_of::<T>())?
+            .checked_add(Self::data_offset())
+    }
+
+    fn check_region(region: *mut u8, size: usize) -> Result<(), SharedError> {
+        if region as usize & (Self::required_align() - 1) != 0 {
+            Err(SharedError::Misaligned)
+        } else if size < mem::size_of::<Header>() {
+            Err(SharedError::TooSmall)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Initialize the queue in a zero-filled region.
+    ///
+    /// # Safety
+    /// The region has to be valid for reads and writes of `size` bytes,
+    /// for as long as any handle to the queue is alive in this process.
+    pub unsafe fn init(region: *mut u8, size: usize, capacity: usize) -> Result<Self, SharedError> {
+        Self::check_region(region, size)?;
+        if capacity == 0 || capacity > Pointer::MAX as usize {
+            return Err(SharedError::InvalidCapacity(capacity));
+        }
+        if Self::checked_size(capacity).is_none_or(|required| size < required) {
+            return Err(SharedError::TooSmall);
+        }
+        let header = region as *mut Header;
+        // the status is at the start, and the region is zero-filled
+        let status = &*(region as *const AtomicUsize);
+        if status
+            .compare_exchange(0, INITIALIZING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(SharedError::AlreadyInitialized);
+        }
+        ptr::addr_of_mut!((*header).header_size).write(mem::size_of::<Header>());
+        ptr::addr_of_mut!((*header).element_size).write(mem::size_of::<T>());
+        ptr::addr_of_mut!((*header).element_align).write(mem::align_of::<T>());
+        ptr::addr_of_mut!((*header).slot_count).write(capacity + 1);
+        ptr::addr_of_mut!((*header).data_offset).write(Self::data_offset());
+        ptr::addr_of_mut!((*header).positions).write(Positions::new(0));
+        status.store(READY, super::STORE_ORDER);
+        Ok(Self::from_header(header))
+    }
+
+    /// Attach to a queue initialized by another process, or another handle.
+    ///
+    /// # Safety
+    /// The region has to be valid for reads and writes of `size` bytes,
+    /// for as long as any handle to the queue is alive in this process.
+    pub unsafe fn attach(region: *mut u8, size: usize) -> Result<Self, SharedError> {
+        Self::check_region(region, size)?;
+        let header = region as *const Header;
+        match (*header).status.load(super::LOAD_ORDER) {
+            READY => {}
+            0 | INITIALIZING => return Err(SharedError::NotInitialized),
+            _ => return Err(SharedError::LayoutMismatch),
+        }
+        let h = &*header;
+        if h.header_size != mem::size_of::<Header>()
+            || h.element_size != mem::size_of::<T>()
+            || h.element_align != mem::align_of::<T>()
+            || h.data_offset != Self::data_offset()
+        {
+            return Err(SharedError::LayoutMismatch);
+        }
+        // the header comes from another process, so it's not trusted to be sane
+        if h.slot_count < 2 || h.slot_count - 1 > Pointer::MAX as usize {
+            return Err(SharedError::InvalidCapacity(h.slot_count.saturating_sub(1)));
+        }
+        if Self::checked_size(h.slot_count - 1).is_none_or(|required| size < required) {
+            return Err(SharedError::TooSmall);
+        }
+        Ok(Self::from_header(header))
+    }
+
+    unsafe fn from_header(header: *const Header) -> Self {
+        Self {
+            header,
+            data: (header as *mut u8).add((*header).data_offset) as *mut T,
+            wait: super::WaitStrategy::default(),
+        }
+    }
+}
+
+impl<T> SharedQueue<T> {
+    /// Override the process-wide default wait strategy for this handle.
+    pub fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+
+    #[inline(always)]
+    fn header(&self) -> &Header {
+        unsafe { &*self.header }
+    }
+}
+
+impl<T> Ring for SharedQueue<T> {
+    #[inline(always)]
+    fn wrap(&self) -> usize {
+        self.header().slot_count
+    }
+
+    fn wait_for(&self, step: u32, _done: impl Fn() -> bool) {
+        self.wait.wait(step);
+    }
+}
+
+impl<T: Copy + Send> super::SynQueue<T> for SharedQueue<T> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn push(&self, value: T) -> Result<(), T> {
+        let positions = &self.header().positions;
+        let Some((head, next, _)) = positions.claim_free(self, None) else {
+            return Err(value);
+        };
+        unsafe { self.data.add(head as usize).write(value) };
+        positions.publish(self, head, next);
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn pop(&self) -> Option<T> {
+        let positions = &self.header().positions;
+        let (tail, next) = positions.claim_oldest(self, 1)?;
+        let value = unsafe { self.data.add(tail as usize).read() };
+        positions.release(self, tail, next);
+        Some(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.header().positions.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.header().positions.is_full(self)
+    }
+
+    fn capacity(&self) -> usize {
+        self.header().slot_count - 1
+    }
+}
+
+#[test]
+fn init_attach() {
+    use super::SynQueue as _;
+
+    let size = SharedQueue::<u32>::required_size(3);
+    // stands for a memory-mapped region, with no padding bytes left uninitialized
+    let mut region = vec![
+        super::pad::CachePadded::new([0u8; super::pad::CACHE_LINE]);
+        size.div_ceil(super::pad::CACHE_LINE)
+    ];
+    let ptr = region.as_mut_ptr() as *mut u8;
+    unsafe {
+        assert_eq!(
+            SharedQueue::<u32>::attach(ptr, size).err(),
+            Some(SharedError::NotInitialized)
+        );
+        assert_eq!(
+            SharedQueue::<u32>::init(ptr, size - 1, 3).err(),
+            Some(SharedError::TooSmall)
+        );
+        let producer = SharedQueue::<u32>::init(ptr, size, 3).unwrap();
+        assert_eq!(
+            SharedQueue::<u32>::init(ptr, size, 3).err(),
+            Some(SharedError::AlreadyInitialized)
+        );
+        assert_eq!(
+            SharedQueue::<u64>::attach(ptr, size).err(),
+            Some(SharedError::LayoutMismatch)
+        );
+        let consumer = SharedQueue::<u32>::attach(ptr, size).unwrap();
+        assert_eq!(consumer.capacity(), 3);
+        for i in 0..3 {
+            producer.push(i).unwrap();
+        }
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(consumer.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+        assert!(producer.is_empty());
+    }
+}
+
+#[test]
+fn corrupt_header() {
+    let size = SharedQueue::<u32>::required_size(3);
+    let mut region = vec![
+        super::pad::CachePadded::new([0u8; super::pad::CACHE_LINE]);
+        size.div_ceil(super::pad::CACHE_LINE)
+    ];
+    let ptr = region.as_mut_ptr() as *mut u8;
+    unsafe {
+        SharedQueue::<u32>::init(ptr, size, 3).unwrap();
+        let header = ptr as *mut Header;
+        for slot_count in [0, 1, usize::MAX] {
+            (*header).slot_count = slot_count;
+            assert!(matches!(
+                SharedQueue::<u32>::attach(ptr, size),
+                Err(SharedError::InvalidCapacity(_))
+            ));
+        }
+        (*header).slot_count = 1 << 12;
+        assert_eq!(
+            SharedQueue::<u32>::attach(ptr, size).err(),
+            Some(SharedError::TooSmall)
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io;
+
+const MAGIC: [u8; 4] = *b"SYNQ";
+const VERSION: u32 = 1;
+
+/// Serializable snapshot of the queue contents and capacity.
+///
+/// Allows bounded work buffers to be checkpointed as a part of the application state.
+/// Taking a snapshot drains the queue, so concurrent producers should be stopped
+/// beforehand if the snapshot needs to be complete.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    pub capacity: usize,
+    pub elements: Vec<T>,
+}
+
+impl<T> Snapshot<T> {
+    /// Drain the queue into a new snapshot.
+    pub fn drain<Q: super::SynQueue<T>>(queue: &Q) -> Self {
+        let mut elements = Vec::new();
+        while let Some(value) = queue.pop() {
+            elements.push(value);
+        }
+        Self {
+            capacity: queue.capacity(),
+            elements,
+        }
+    }
+
+    /// Reconstruct a queue from the snapshot.
+    ///
+    /// Fails with `NewError::InvalidCapacity` if the elements don't fit.
+    pub fn restore<Q: super::NewQueue<T>>(self) -> Result<Q, super::NewError> {
+        if self.elements.len() > self.capacity {
+            return Err(super::NewError::InvalidCapacity(self.capacity));
+        }
+        let queue = Q::try_new(self.capacity)?;
+        for value in self.elements {
+            if queue.push(value).is_err() {
+                return Err(super::NewError::InvalidCapacity(self.capacity));
+            }
+        }
+        Ok(queue)
+    }
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Write the queue contents into a versioned binary format, keeping them in the queue.
+///
+/// This is meant for handing the contents off across process restarts,
+/// or between shards. The exclusive borrow makes sure that nothing else
+/// uses the queue, so the elements can be taken out for serializing,
+/// and then pushed back in the same order, whether the writing succeeds or not.
+pub fn save_state<T: Serialize, Q: super::SynQueue<T>>(
+    queue: &mut Q,
+    mut writer: impl io::Write,
+) -> io::Result<()> {
+    let snapshot = Snapshot::drain(&*queue);
+    let result = bincode::serialize(&snapshot)
+        .map_err(invalid_data)
+        .and_then(|data| {
+            writer.write_all(&MAGIC)?;
+            writer.write_all(&VERSION.to_le_bytes())?;
+            writer.write_all(&data)
+        });
+    for value in snapshot.elements {
+        if queue.push(value).is_err() {
+            unreachable!("The queue had room for the element");
+        }
+    }
+    result
+}
+
+/// Create a queue from the data written by `save_state`.
+pub fn load_state<T: DeserializeOwned, Q: super::NewQueue<T>>(
+    mut reader: impl io::Read,
+) -> io::Result<Q> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    if header[..4] != MAGIC {
+        return Err(invalid_data("not a queue state"));
+    }
+    let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if version != VERSION {
+        return Err(invalid_data(format!("unsupported version {}", version)));
+    }
+    let snapshot: Snapshot<T> = bincode::deserialize_from(reader).map_err(invalid_data)?;
+    snapshot.restore().map_err(invalid_data)
+}
+
+#[test]
+fn roundtrip() {
+    use super::{DoubleQueue, MaskedQueue, NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let dq = DoubleQueue::new(4);
+        dq.push(1).unwrap();
+        dq.push(2).unwrap();
+        let json = serde_json::to_string(&Snapshot::drain(&dq)).unwrap();
+        assert!(dq.is_empty());
+        let snapshot: Snapshot<i32> = serde_json::from_str(&json).unwrap();
+        let mq: MaskedQueue<i32> = snapshot.restore().unwrap();
+        assert_eq!(mq.capacity(), 4);
+        assert_eq!(mq.pop(), Some(1));
+        assert_eq!(mq.pop(), Some(2));
+        assert_eq!(mq.pop(), None);
+
+        let overfull = Snapshot {
+            capacity: 1,
+            elements: vec![1, 2],
+        };
+        assert_eq!(
+            overfull.restore::<MaskedQueue<i32>>().err(),
+            Some(super::NewError::InvalidCapacity(1))
+        );
+    })
+}
+
+#[test]
+fn binary() {
+    use super::{AxelQueue, DoubleQueue, NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let mut dq = DoubleQueue::new(4);
+        dq.push("a".to_string()).unwrap();
+        dq.push("b".to_string()).unwrap();
+        let mut data = Vec::new();
+        save_state(&mut dq, &mut data).unwrap();
+        // the queue keeps its elements
+        assert_eq!(dq.try_iter().collect::<Vec<_>>(), ["a", "b"]);
+        let aq: AxelQueue<String> = load_state(&data[..]).unwrap();
+        assert_eq!(aq.capacity(), 4);
+        assert_eq!(aq.try_iter().collect::<Vec<_>>(), ["a", "b"]);
+
+        data[4] += 1;
+        let error = load_state::<String, AxelQueue<_>>(&data[..]).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        // a failed write keeps the elements in the queue too
+        dq.push("c".to_string()).unwrap();
+        dq.push("d".to_string()).unwrap();
+        let mut short = [0u8; 4];
+        let error = save_state(&mut dq, &mut short[..]).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::WriteZero);
+        assert_eq!(dq.try_iter().collect::<Vec<_>>(), ["c", "d"]);
+
+        // capacity that the queue doesn't support
+        let mut data = Vec::new();
+        save_state(&mut dq, &mut data).unwrap();
+        let error = load_state::<String, super::double::DoubleQueue<_, 2>>(&data[..])
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    })
+}
@@ -0,0 +1,81 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+
+/// Queue wrapper that spills overflowing elements into a secondary dead letter queue.
+///
+/// This makes overflow events observable and recoverable, instead of returning
+/// the rejected value to a producer that can only drop it.
+/// Only if the dead letter queue is also full, the value is returned back.
+pub struct SpillQueue<Q, D> {
+    queue: Q,
+    dead_letter: D,
+    spilled: AtomicUsize,
+}
+
+impl<Q, D> SpillQueue<Q, D> {
+    pub fn new(queue: Q, dead_letter: D) -> Self {
+        Self {
+            queue,
+            dead_letter,
+            spilled: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    pub fn dead_letter(&self) -> &D {
+        &self.dead_letter
+    }
+
+    /// Number of elements successfully spilled into the dead letter queue.
+    pub fn spilled(&self) -> usize {
+        self.spilled.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, Q, D> super::SynQueue<T> for SpillQueue<Q, D>
+where
+    Q: super::SynQueue<T>,
+    D: super::SynQueue<T>,
+{
+    fn push(&self, value: T) -> Result<(), T> {
+        let value = match self.queue.push(value) {
+            Ok(()) => return Ok(()),
+            Err(value) => value,
+        };
+        self.dead_letter.push(value)?;
+        self.spilled.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn overflow() {
+    use super::{AxelQueue, DoubleQueue, NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let sq = SpillQueue::new(DoubleQueue::new(1), AxelQueue::new(1));
+        sq.push(1).unwrap();
+        sq.push(2).unwrap();
+        assert_eq!(sq.push(3), Err(3));
+        assert_eq!(sq.spilled(), 1);
+        assert_eq!(sq.pop(), Some(1));
+        assert_eq!(sq.dead_letter().pop(), Some(2));
+    })
+}
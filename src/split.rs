@@ -0,0 +1,187 @@
+use super::stats::HandleCounters;
+use std::{marker::PhantomData, sync::Arc};
+
+/// Split a queue into the producer and consumer handles.
+///
+/// This allows restricting statically which side of the queue
+/// a subsystem can use. Both handles can be cloned cheaply.
+///
+/// With the `stats` feature, every handle counts its own operations,
+/// and a clone starts counting from zero.
+pub fn split<T, Q: super::SynQueue<T>>(queue: Q) -> (Producer<Q, T>, Consumer<Q, T>) {
+    let queue = Arc::new(queue);
+    (
+        Producer {
+            queue: Arc::clone(&queue),
+            counters: HandleCounters::default(),
+            _marker: PhantomData,
+        },
+        Consumer {
+            queue,
+            counters: HandleCounters::default(),
+            _marker: PhantomData,
+        },
+    )
+}
+
+/// Pushing side of a queue.
+pub struct Producer<Q, T> {
+    queue: Arc<Q>,
+    counters: HandleCounters,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<Q, T> Clone for Producer<Q, T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: Arc::clone(&self.queue),
+            counters: HandleCounters::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Q, T> Producer<Q, T> {
+    /// Snapshot of the counters of this handle.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> super::HandleStats {
+        self.counters.snapshot()
+    }
+}
+
+impl<Q: super::SynQueue<T>, T> Producer<Q, T> {
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let result = self.queue.push(value);
+        self.counters.record(result.is_ok());
+        result
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<Q: super::CloseQueue<T>, T> Producer<Q, T> {
+    pub fn try_push(&self, value: T) -> Result<(), super::PushError<T>> {
+        let result = self.queue.try_push(value);
+        self.counters.record(result.is_ok());
+        result
+    }
+
+    /// Tell the consumers that no more elements are coming.
+    pub fn close(&self) {
+        self.queue.close()
+    }
+}
+
+/// Popping side of a queue.
+pub struct Consumer<Q, T> {
+    queue: Arc<Q>,
+    counters: HandleCounters,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<Q, T> Clone for Consumer<Q, T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: Arc::clone(&self.queue),
+            counters: HandleCounters::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Q, T> Consumer<Q, T> {
+    /// Snapshot of the counters of this handle.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> super::HandleStats {
+        self.counters.snapshot()
+    }
+}
+
+impl<Q: super::SynQueue<T>, T> Consumer<Q, T> {
+    pub fn pop(&self) -> Option<T> {
+        let value = self.queue.pop();
+        self.counters.record(value.is_some());
+        value
+    }
+
+    pub fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let result = self.queue.try_pop();
+        self.counters.record(result.is_ok());
+        result
+    }
+
+    /// Iterate over the elements, popping them until the queue is momentarily empty.
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.pop())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn handles() {
+    use super::NewQueue as _;
+    super::loom::model(|| {
+        let (producer, consumer) = split(super::Closable::new(super::DoubleQueue::new(2)));
+        let producer2 = producer.clone();
+        producer.push(1).unwrap();
+        producer2.push(2).unwrap();
+        assert!(producer.is_full());
+        producer.close();
+        assert_eq!(producer2.try_push(3), Err(super::PushError::Closed(3)));
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(consumer.clone().try_iter().collect::<Vec<_>>(), [2]);
+        assert_eq!(consumer.try_pop(), Err(super::TryPopError::Closed));
+    })
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn handle_stats() {
+    use super::{HandleStats, NewQueue as _};
+    super::loom::model(|| {
+        let (producer, consumer) = split(super::DoubleQueue::new(1));
+        let producer2 = producer.clone();
+        producer.push(1).unwrap();
+        assert_eq!(producer2.push(2), Err(2));
+        assert_eq!(producer2.push(3), Err(3));
+        assert_eq!(consumer.try_iter().collect::<Vec<_>>(), [1]);
+        producer2.push(4).unwrap();
+        assert_eq!(
+            producer.stats(),
+            HandleStats {
+                ops: 1,
+                failures: 0,
+                retries: 0,
+            }
+        );
+        assert_eq!(
+            producer2.stats(),
+            HandleStats {
+                ops: 1,
+                failures: 2,
+                retries: 2,
+            }
+        );
+        assert_eq!(
+            consumer.stats(),
+            HandleStats {
+                ops: 1,
+                failures: 1,
+                retries: 0,
+            }
+        );
+    })
+}
@@ -0,0 +1,384 @@
+use super::qstd::{
+    cell::UnsafeCell,
+    hint,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use std::mem;
+
+/// A single-producer multi-consumer queue.
+///
+/// ## Principle
+/// The producer is the only one moving the head, so pushing is a plain write
+/// of the slot followed by a store of the new head, without any CAS loop.
+/// Consumers work like in `DoubleQueue`: a CAS loop on `claimed` acquires a slot,
+/// the data is read, and then `tail` catches up in the order of the claims,
+/// releasing the slot back to the producer.
+///
+/// ## Internal invariants.
+/// Considering an infinite sequence (without wraparounds):
+///  `tail <= claimed <= head`
+///
+/// ## Single producer
+/// The queue isn't used directly: `split` turns it into the `SpmcProducer`
+/// and `SpmcConsumer` halves. Only the consumer can be cloned,
+/// and the single producer is enforced by it requiring `&mut self`.
+pub struct SpmcQueue<T> {
+    head: AtomicUsize,
+    claimed: AtomicUsize,
+    tail: AtomicUsize,
+    data: Box<[mem::MaybeUninit<UnsafeCell<T>>]>,
+    wait: super::WaitStrategy,
+}
+
+unsafe impl<T: Send> Sync for SpmcQueue<T> {}
+
+impl<T: Send> SpmcQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            head: AtomicUsize::new(0),
+            claimed: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            data: (0..=capacity).map(|_| mem::MaybeUninit::uninit()).collect(),
+            wait: super::WaitStrategy::default(),
+        }
+    }
+
+    /// Split the queue into the producer and consumer halves.
+    pub fn split(self) -> (SpmcProducer<T>, SpmcConsumer<T>) {
+        let shared = Arc::new(self);
+        (
+            SpmcProducer {
+                queue: Arc::clone(&shared),
+            },
+            SpmcConsumer { queue: shared },
+        )
+    }
+}
+
+impl<T> SpmcQueue<T> {
+    fn advance(&self, index: usize) -> usize {
+        if index + 1 == self.data.len() {
+            0
+        } else {
+            index + 1
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len() - 1
+    }
+
+    /// Override the process-wide default wait strategy.
+    pub fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+}
+
+impl<T> Drop for SpmcQueue<T> {
+    fn drop(&mut self) {
+        let tail = self.tail.load(super::LOAD_ORDER);
+        assert_eq!(tail, self.claimed.load(super::LOAD_ORDER));
+        let head = self.head.load(super::LOAD_ORDER);
+        unsafe { super::drop_slots(&mut self.data, tail, head, super::DropOrder::Fifo) };
+    }
+}
+
+/// The producing half of `SpmcQueue`.
+pub struct SpmcProducer<T> {
+    queue: Arc<SpmcQueue<T>>,
+}
+
+impl<T: Send> SpmcProducer<T> {
+    /// Get the head position if there is space for a new element.
+    fn free_head(&self) -> Option<usize> {
+        let q = &*self.queue;
+        // only the producer moves the head
+        let head = q.head.load(Ordering::Relaxed);
+        if q.advance(head) == q.tail.load(super::LOAD_ORDER) {
+            None
+        } else {
+            Some(head)
+        }
+    }
+
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let Some(head) = self.free_head() else {
+            return Err(value);
+        };
+        let q = &*self.queue;
+        trace!("Push head = {:x}", head);
+        unsafe { super::UnsafeCellHelper::write(q.data.get_unchecked(head).as_ptr(), value) };
+        q.head.store(q.advance(head), super::STORE_ORDER);
+        Ok(())
+    }
+
+    /// Push an element constructed directly in the queue storage.
+    ///
+    /// The constructor gets the uninitialized slot, and returns the reference
+    /// it got from initializing it, e.g. `|slot| slot.write(value)`.
+    /// It isn't called if the queue is full, and it's returned back instead.
+    /// If the constructor panics, the queue is left intact.
+    ///
+    /// The multi-producer queues don't offer this: they publish the claimed positions
+    /// in order, so a constructor that panics or fails would block all the later producers.
+    ///
+    /// Panics if the returned reference doesn't point to the slot.
+    pub fn push_with<F>(&mut self, fun: F) -> Result<(), F>
+    where
+        F: FnOnce(&mut mem::MaybeUninit<T>) -> &mut T,
+    {
+        let Some(head) = self.free_head() else {
+            return Err(fun);
+        };
+        let q = &*self.queue;
+        trace!("Push head = {:x}", head);
+        unsafe {
+            super::UnsafeCellHelper::with_uninit(q.data.get_unchecked(head).as_ptr(), |slot| {
+                let expected = slot.as_ptr();
+                assert_eq!(
+                    fun(slot) as *const T,
+                    expected,
+                    "{}",
+                    super::spsc::WRONG_SLOT
+                );
+            })
+        };
+        q.head.store(q.advance(head), super::STORE_ORDER);
+        Ok(())
+    }
+
+    /// Push an element constructed directly in the queue storage, if the construction succeeds.
+    ///
+    /// Returns `Ok(false)` without calling the constructor if the queue is full.
+    pub fn try_push_with<E>(
+        &mut self,
+        fun: impl FnOnce(&mut mem::MaybeUninit<T>) -> Result<&mut T, E>,
+    ) -> Result<bool, E> {
+        let Some(head) = self.free_head() else {
+            return Ok(false);
+        };
+        let q = &*self.queue;
+        unsafe {
+            super::UnsafeCellHelper::with_uninit(q.data.get_unchecked(head).as_ptr(), |slot| {
+                let expected = slot.as_ptr();
+                fun(slot).map(|value| {
+                    assert_eq!(value as *const T, expected, "{}", super::spsc::WRONG_SLOT)
+                })
+            })
+        }?;
+        q.head.store(q.advance(head), super::STORE_ORDER);
+        Ok(true)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.free_head().is_none()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+/// The consuming half of `SpmcQueue`, which can be cloned for every consumer.
+pub struct SpmcConsumer<T> {
+    queue: Arc<SpmcQueue<T>>,
+}
+
+impl<T> Clone for SpmcConsumer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: Arc::clone(&self.queue),
+        }
+    }
+}
+
+impl<T: Send> SpmcConsumer<T> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    pub fn pop(&self) -> Option<T> {
+        let q = &*self.queue;
+        // acquire the oldest position
+        let mut tail = q.claimed.load(super::LOAD_ORDER);
+        let next = loop {
+            trace!("Pop pre-CAS: {:x}", tail);
+            if tail == q.head.load(super::LOAD_ORDER) {
+                return None;
+            }
+            let next = q.advance(tail);
+            match q
+                .claimed
+                .compare_exchange_weak(tail, next, super::CAS_ORDER, super::LOAD_ORDER)
+            {
+                Ok(_) => break next,
+                Err(other) => tail = other,
+            }
+            hint::spin_loop();
+        };
+
+        trace!("Pop success, next tail = {:x}", next);
+        // read the data
+        let value = unsafe { q.data.get_unchecked(tail).assume_init_read().into_inner() };
+
+        // release the slot after the earlier claims are released
+        let mut step = 0;
+        while q.tail.load(super::LOAD_ORDER) != tail {
+            q.wait.wait(step);
+            step = step.saturating_add(1);
+        }
+        q.tail.store(next, super::STORE_ORDER);
+        Some(value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let q = &*self.queue;
+        q.head.load(super::LOAD_ORDER) == q.claimed.load(super::LOAD_ORDER)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+/// Both halves behind `SynQueue`, for the generic tests.
+///
+/// The lock on the producer is never contended with a single producing thread.
+#[cfg(test)]
+struct Halves<T> {
+    producer: super::qstd::sync::Mutex<SpmcProducer<T>>,
+    consumer: SpmcConsumer<T>,
+}
+
+#[cfg(test)]
+impl<T: Send> super::NewQueue<T> for Halves<T> {
+    fn new(capacity: usize) -> Self {
+        let (producer, consumer) = SpmcQueue::new(capacity).split();
+        Self {
+            producer: super::qstd::sync::Mutex::new(producer),
+            consumer,
+        }
+    }
+}
+
+#[cfg(test)]
+impl<T: Send> super::SynQueue<T> for Halves<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.producer.lock().unwrap().push(value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.consumer.pop()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.consumer.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.producer.lock().unwrap().is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.consumer.capacity()
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<Halves<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<Halves<i32>>();
+}
+
+#[test]
+fn push_with() {
+    super::loom::model(|| {
+        let (mut tx, rx) = SpmcQueue::new(2).split();
+        assert!(tx.push_with(|slot| slot.write(1)).is_ok());
+        assert_eq!(
+            tx.try_push_with(|_| Err::<&mut i32, _>("failed")),
+            Err("failed")
+        );
+        assert_eq!(
+            tx.try_push_with(|slot| Ok::<_, ()>(slot.write(2))),
+            Ok(true)
+        );
+        assert_eq!(
+            tx.try_push_with(|slot| Ok::<_, ()>(slot.write(3))),
+            Ok(false)
+        );
+        assert!(tx.push_with(|slot| slot.write(3)).is_err());
+        assert_eq!(rx.pop(), Some(1));
+        assert_eq!(rx.pop(), Some(2));
+        assert_eq!(rx.pop(), None);
+    })
+}
+
+#[test]
+fn consumers() {
+    use super::qstd::{sync::atomic::AtomicUsize, thread};
+
+    super::loom::model(|| {
+        const NUM_THREADS: usize = 2;
+        const NUM_ELEMENTS: usize = if cfg!(miri) { 1 << 5 } else { 1 << 12 };
+        let (mut tx, rx) = SpmcQueue::new(16).split();
+        let sum = Arc::new(AtomicUsize::new(0));
+        let count = Arc::new(AtomicUsize::new(0));
+        let handles = (0..NUM_THREADS)
+            .map(|_| {
+                let rx = rx.clone();
+                let sum = Arc::clone(&sum);
+                let count = Arc::clone(&count);
+                thread::spawn(move || {
+                    // elements come out in order for every consumer
+                    let mut last = None;
+                    while count.load(Ordering::Acquire) != NUM_ELEMENTS {
+                        match rx.pop() {
+                            Some(i) => {
+                                assert!(last < Some(i));
+                                last = Some(i);
+                                sum.fetch_add(i, Ordering::Relaxed);
+                                count.fetch_add(1, Ordering::AcqRel);
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for i in 0..NUM_ELEMENTS {
+            let mut value = i;
+            while let Err(v) = tx.push(value) {
+                value = v;
+                thread::yield_now();
+            }
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(
+            sum.load(Ordering::Relaxed),
+            NUM_ELEMENTS * (NUM_ELEMENTS - 1) / 2
+        );
+        assert!(rx.is_empty());
+    })
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    use super::lincheck::Role;
+    super::lincheck::check::<Halves<u32>>(2, &[Role::Producer, Role::Consumer, Role::Consumer]);
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<Halves<u32>>();
+}
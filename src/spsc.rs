@@ -0,0 +1,328 @@
+//! Wait-free single-producer single-consumer ring buffer.
+
+use super::qstd::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use std::mem;
+
+pub(super) const WRONG_SLOT: &str = "The constructor has to initialize the given slot";
+
+/// A single-producer single-consumer queue.
+///
+/// ## Principle
+/// Each side owns one index and only reads the other one, so every operation
+/// is a fixed sequence of Acquire loads and Release stores. There are no CAS
+/// loops and no waiting, which makes it suitable for real-time contexts.
+///
+/// The queue isn't used directly: `split` turns it into the `SpscProducer`
+/// and `SpscConsumer` halves, and the single producer and consumer are enforced
+/// by them requiring `&mut self`.
+pub struct SpscQueue<T> {
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    data: Box<[mem::MaybeUninit<UnsafeCell<T>>]>,
+}
+
+unsafe impl<T: Send> Sync for SpscQueue<T> {}
+
+impl<T: Send> SpscQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            // One extra slot differentiates between empty and full states.
+            data: (0..=capacity).map(|_| mem::MaybeUninit::uninit()).collect(),
+        }
+    }
+
+    /// Split the queue into the producer and consumer halves.
+    pub fn split(self) -> (SpscProducer<T>, SpscConsumer<T>) {
+        let shared = Arc::new(self);
+        (
+            SpscProducer {
+                queue: Arc::clone(&shared),
+            },
+            SpscConsumer { queue: shared },
+        )
+    }
+}
+
+impl<T> SpscQueue<T> {
+    fn advance(&self, index: usize) -> usize {
+        if index + 1 == self.data.len() {
+            0
+        } else {
+            index + 1
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len() - 1
+    }
+}
+
+impl<T> Drop for SpscQueue<T> {
+    fn drop(&mut self) {
+        let head = self.head.load(super::LOAD_ORDER);
+        let tail = self.tail.load(super::LOAD_ORDER);
+        unsafe { super::drop_slots(&mut self.data, tail, head, super::DropOrder::Fifo) };
+    }
+}
+
+/// The producing half of `SpscQueue`.
+pub struct SpscProducer<T> {
+    queue: Arc<SpscQueue<T>>,
+}
+
+impl<T: Send> SpscProducer<T> {
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let Some(head) = self.free_head() else {
+            return Err(value);
+        };
+        let q = &*self.queue;
+        unsafe { super::UnsafeCellHelper::write(q.data.get_unchecked(head).as_ptr(), value) };
+        q.head.store(q.advance(head), super::STORE_ORDER);
+        Ok(())
+    }
+
+    /// Push an element constructed directly in the queue storage.
+    ///
+    /// The constructor gets the uninitialized slot, and returns the reference
+    /// it got from initializing it, e.g. `|slot| slot.write(value)`.
+    /// It isn't called if the queue is full, and it's returned back instead.
+    /// If the constructor panics, the queue is left intact.
+    ///
+    /// Panics if the returned reference doesn't point to the slot.
+    pub fn push_with<F>(&mut self, fun: F) -> Result<(), F>
+    where
+        F: FnOnce(&mut mem::MaybeUninit<T>) -> &mut T,
+    {
+        let Some(head) = self.free_head() else {
+            return Err(fun);
+        };
+        let q = &*self.queue;
+        unsafe {
+            super::UnsafeCellHelper::with_uninit(q.data.get_unchecked(head).as_ptr(), |slot| {
+                let expected = slot.as_ptr();
+                assert_eq!(fun(slot) as *const T, expected, "{}", WRONG_SLOT);
+            })
+        };
+        q.head.store(q.advance(head), super::STORE_ORDER);
+        Ok(())
+    }
+
+    /// Push an element constructed directly in the queue storage, if the construction succeeds.
+    ///
+    /// Returns `Ok(false)` without calling the constructor if the queue is full.
+    pub fn try_push_with<E>(
+        &mut self,
+        fun: impl FnOnce(&mut mem::MaybeUninit<T>) -> Result<&mut T, E>,
+    ) -> Result<bool, E> {
+        let Some(head) = self.free_head() else {
+            return Ok(false);
+        };
+        let q = &*self.queue;
+        unsafe {
+            super::UnsafeCellHelper::with_uninit(q.data.get_unchecked(head).as_ptr(), |slot| {
+                let expected = slot.as_ptr();
+                fun(slot).map(|value| assert_eq!(value as *const T, expected, "{}", WRONG_SLOT))
+            })
+        }?;
+        q.head.store(q.advance(head), super::STORE_ORDER);
+        Ok(true)
+    }
+
+    /// Reserve a slot for writing an element in place.
+    ///
+    /// The element becomes visible to the consumer after `Reservation::commit`.
+    #[cfg(not(feature = "loom"))]
+    pub fn reserve(&mut self) -> Option<Reservation<'_, T>> {
+        let head = self.free_head()?;
+        Some(Reservation {
+            producer: self,
+            head,
+        })
+    }
+
+    fn free_head(&self) -> Option<usize> {
+        let q = &*self.queue;
+        let head = q.head.load(Ordering::Relaxed);
+        if q.advance(head) == q.tail.load(super::LOAD_ORDER) {
+            None
+        } else {
+            Some(head)
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        let q = &*self.queue;
+        q.advance(q.head.load(Ordering::Relaxed)) == q.tail.load(super::LOAD_ORDER)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+/// Slot reserved by `SpscProducer::reserve`.
+///
+/// Dropping it without committing aborts the reservation. If the slot was
+/// initialized by then, the element is leaked.
+#[cfg(not(feature = "loom"))]
+pub struct Reservation<'a, T> {
+    producer: &'a mut SpscProducer<T>,
+    head: usize,
+}
+
+#[cfg(not(feature = "loom"))]
+impl<T> Reservation<'_, T> {
+    pub fn slot(&mut self) -> &mut mem::MaybeUninit<T> {
+        let cell = unsafe { self.producer.queue.data.get_unchecked(self.head).as_ptr() };
+        unsafe { &mut *(UnsafeCell::raw_get(cell) as *mut mem::MaybeUninit<T>) }
+    }
+
+    /// Publish the element to the consumer.
+    ///
+    /// # Safety
+    /// The slot has to be initialized.
+    pub unsafe fn commit(self) {
+        let q = &*self.producer.queue;
+        q.head.store(q.advance(self.head), super::STORE_ORDER);
+    }
+}
+
+/// The consuming half of `SpscQueue`.
+pub struct SpscConsumer<T> {
+    queue: Arc<SpscQueue<T>>,
+}
+
+impl<T: Send> SpscConsumer<T> {
+    pub fn pop(&mut self) -> Option<T> {
+        let q = &*self.queue;
+        let tail = q.tail.load(Ordering::Relaxed);
+        if tail == q.head.load(super::LOAD_ORDER) {
+            return None;
+        }
+        let value = unsafe { q.data.get_unchecked(tail).assume_init_read().into_inner() };
+        q.tail.store(q.advance(tail), super::STORE_ORDER);
+        Some(value)
+    }
+
+    /// Pop the oldest element only if the predicate accepts it.
+    pub fn pop_if(&mut self, pred: impl FnOnce(&T) -> bool) -> Option<T> {
+        let q = &*self.queue;
+        let tail = q.tail.load(Ordering::Relaxed);
+        if tail == q.head.load(super::LOAD_ORDER) {
+            return None;
+        }
+        let slot = unsafe { q.data.get_unchecked(tail) };
+        if !unsafe { super::UnsafeCellHelper::peek(slot.as_ptr(), pred) } {
+            return None;
+        }
+        let value = unsafe { slot.assume_init_read().into_inner() };
+        q.tail.store(q.advance(tail), super::STORE_ORDER);
+        Some(value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let q = &*self.queue;
+        q.tail.load(Ordering::Relaxed) == q.head.load(super::LOAD_ORDER)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn overflow() {
+    super::loom::model(|| {
+        let (mut tx, mut rx) = SpscQueue::new(2).split();
+        assert_eq!(tx.capacity(), 2);
+        tx.push(2).unwrap();
+        tx.push(3).unwrap();
+        assert!(tx.is_full());
+        assert_eq!(tx.push(4), Err(4));
+        assert_eq!(rx.pop(), Some(2));
+        tx.push(5).unwrap();
+        assert_eq!(rx.pop(), Some(3));
+        assert_eq!(rx.pop(), Some(5));
+        assert!(rx.is_empty());
+        assert_eq!(rx.pop(), None);
+    })
+}
+
+#[test]
+fn pop_if() {
+    super::loom::model(|| {
+        let (mut tx, mut rx) = SpscQueue::new(2).split();
+        tx.push(1).unwrap();
+        assert_eq!(rx.pop_if(|&v| v > 1), None);
+        assert_eq!(rx.pop_if(|&v| v == 1), Some(1));
+        assert_eq!(rx.pop_if(|_| true), None);
+    })
+}
+
+#[test]
+fn push_with() {
+    super::loom::model(|| {
+        let (mut tx, mut rx) = SpscQueue::new(1).split();
+        assert_eq!(tx.try_push_with(|_| Err::<&mut i32, _>(())), Err(()));
+        assert!(tx.push_with(|slot| slot.write(1)).is_ok());
+        assert_eq!(
+            tx.try_push_with(|slot| Ok::<_, ()>(slot.write(2))),
+            Ok(false)
+        );
+        assert_eq!(rx.pop(), Some(1));
+        assert_eq!(rx.pop(), None);
+    })
+}
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn reserve() {
+    super::loom::model(|| {
+        let (mut tx, mut rx) = SpscQueue::new(1).split();
+        assert!(tx.reserve().is_some());
+        assert!(rx.is_empty());
+        let mut reservation = tx.reserve().unwrap();
+        reservation.slot().write(String::from("zero-copy"));
+        unsafe { reservation.commit() };
+        assert!(tx.reserve().is_none());
+        assert_eq!(rx.pop().as_deref(), Some("zero-copy"));
+    })
+}
+
+#[test]
+fn transfer() {
+    use super::qstd::thread;
+
+    super::loom::model(|| {
+        const NUM_ELEMENTS: usize = if cfg!(miri) { 1 << 5 } else { 1 << 12 };
+        let (mut tx, mut rx) = SpscQueue::new(16).split();
+        let handle = thread::spawn(move || {
+            for i in 0..NUM_ELEMENTS {
+                let mut value = i;
+                while let Err(v) = tx.push(value) {
+                    value = v;
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected != NUM_ELEMENTS {
+            match rx.pop() {
+                Some(i) => {
+                    assert_eq!(i, expected);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        handle.join().unwrap();
+    })
+}
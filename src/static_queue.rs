@@ -0,0 +1,164 @@
+use super::double::{Pointer, Positions, Ring, State};
+use super::qstd::cell::UnsafeCell;
+use std::mem;
+
+/// An internally synchronized (MPMC) queue with inline storage.
+///
+/// ## Principle
+/// This is `DoubleQueue` without the heap allocation, so it can be placed
+/// in a `static`. It still needs `std`, like the rest of the crate.
+/// Instead of having an extra slot to tell between the empty and full states,
+/// the positions wrap around at `2 * N`, and only select the slot modulo `N`,
+/// so the queue fits exactly `N` elements.
+///
+/// Static queues can't follow the process-wide default wait strategy,
+/// so they yield unless configured otherwise.
+pub struct StaticQueue<T, const N: usize> {
+    positions: Positions,
+    data: [mem::MaybeUninit<UnsafeCell<T>>; N],
+    wait: super::WaitStrategy,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for StaticQueue<T, N> {}
+
+macro_rules! static_queue_new {
+    () => {{
+        let () = Self::CAPACITY_CHECK;
+        Self {
+            positions: Positions::new(0),
+            data: [const { mem::MaybeUninit::uninit() }; N],
+            wait: super::WaitStrategy::Yield,
+        }
+    }};
+}
+
+impl<T, const N: usize> StaticQueue<T, N> {
+    /// Fails the build for a capacity the positions can't address.
+    const CAPACITY_CHECK: () = assert!(
+        N != 0 && N <= Pointer::MAX as usize / 2,
+        "the capacity doesn't fit the positions"
+    );
+
+    #[cfg(not(feature = "loom"))]
+    pub const fn new() -> Self {
+        static_queue_new!()
+    }
+
+    #[cfg(feature = "loom")]
+    pub fn new() -> Self {
+        static_queue_new!()
+    }
+
+    /// Override the wait strategy.
+    pub const fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+
+    #[inline(always)]
+    fn slot(&self, index: Pointer) -> &mem::MaybeUninit<UnsafeCell<T>> {
+        unsafe { self.data.get_unchecked(index as usize % N) }
+    }
+}
+
+impl<T, const N: usize> Ring for StaticQueue<T, N> {
+    #[inline(always)]
+    fn wrap(&self) -> usize {
+        2 * N
+    }
+
+    fn max_len(&self) -> usize {
+        N
+    }
+
+    fn wait_for(&self, step: u32, _done: impl Fn() -> bool) {
+        self.wait.wait(step);
+    }
+}
+
+impl<T, const N: usize> Default for StaticQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send, const N: usize> super::NewQueue<T> for StaticQueue<T, N> {
+    fn new(capacity: usize) -> Self {
+        <Self as super::NewQueue<T>>::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        if capacity != N {
+            return Err(super::NewError::InvalidCapacity(capacity));
+        }
+        Ok(Self::new())
+    }
+}
+
+impl<T: Send, const N: usize> super::SynQueue<T> for StaticQueue<T, N> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn push(&self, value: T) -> Result<(), T> {
+        let Some((head, next, _)) = self.positions.claim_free(self, None) else {
+            return Err(value);
+        };
+        unsafe { super::UnsafeCellHelper::write(self.slot(head).as_ptr(), value) };
+        self.positions.publish(self, head, next);
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn pop(&self) -> Option<T> {
+        let (tail, next) = self.positions.claim_oldest(self, 1)?;
+        let value = unsafe { self.slot(tail).assume_init_read().into_inner() };
+        self.positions.release(self, tail, next);
+        Some(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.positions.is_full(self)
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Drop for StaticQueue<T, N> {
+    fn drop(&mut self) {
+        let state = self.positions.wide.load(super::LOAD_ORDER);
+        assert_eq!(state, self.positions.narrow.load(super::LOAD_ORDER));
+        let s = State::unpack(state);
+        let mut index = s.tail;
+        while index != s.head {
+            unsafe { self.data[index as usize % N].assume_init_drop() };
+            index = Positions::advance(self, index);
+        }
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<StaticQueue<i32, 2>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<StaticQueue<i32, 16>>();
+}
+
+// The statics outlive the model executions.
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn in_static() {
+    use super::SynQueue as _;
+    static QUEUE: StaticQueue<u32, 4> = StaticQueue::new();
+    for i in 0..4 {
+        QUEUE.push(i).unwrap();
+    }
+    assert_eq!(QUEUE.push(4), Err(4));
+    assert_eq!(QUEUE.try_iter().collect::<Vec<_>>(), [0, 1, 2, 3]);
+}
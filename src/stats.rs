@@ -0,0 +1,137 @@
+//! Contention counters, collected with the `stats` feature.
+
+#[cfg(all(feature = "stats", feature = "portable-atomic"))]
+use portable_atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(all(feature = "stats", not(feature = "portable-atomic")))]
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Snapshot of the contention counters of a queue.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Compare-and-swap attempts that lost to another thread.
+    pub failed_cas: u64,
+    /// Waits that spun the CPU, including the ones of `WaitStrategy::Custom`.
+    pub spins: u64,
+    /// Waits that gave up the time slice, by yielding or parking.
+    pub yields: u64,
+}
+
+/// Snapshot of the counters of a `Producer` or `Consumer` handle.
+///
+/// Unlike `Stats`, these only cover the operations done through one handle,
+/// which points at the thread responsible for the backpressure.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HandleStats {
+    /// Successful operations.
+    pub ops: u64,
+    /// Operations that failed on a full or empty queue.
+    pub failures: u64,
+    /// Operations attempted right after a failed one, as in a retry loop.
+    pub retries: u64,
+}
+
+/// Counters embedded into a queue, which compile to nothing without the `stats` feature.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    #[cfg(feature = "stats")]
+    failed_cas: AtomicU64,
+    #[cfg(feature = "stats")]
+    spins: AtomicU64,
+    #[cfg(feature = "stats")]
+    yields: AtomicU64,
+}
+
+impl Counters {
+    #[inline(always)]
+    pub(crate) fn failed_cas(&self) {
+        #[cfg(feature = "stats")]
+        self.failed_cas.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn parked(&self) {
+        #[cfg(feature = "stats")]
+        self.yields.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Wait with the strategy for the `step`-th time, and count it.
+    #[inline(always)]
+    pub(crate) fn wait(&self, strategy: &super::WaitStrategy, step: u32) {
+        #[cfg(feature = "stats")]
+        if strategy.yields(step) {
+            self.yields.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.spins.fetch_add(1, Ordering::Relaxed);
+        }
+        strategy.wait(step);
+    }
+
+    #[cfg(feature = "stats")]
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            failed_cas: self.failed_cas.load(Ordering::Relaxed),
+            spins: self.spins.load(Ordering::Relaxed),
+            yields: self.yields.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counters embedded into a handle, which compile to nothing without the `stats` feature.
+#[derive(Debug, Default)]
+pub(crate) struct HandleCounters {
+    #[cfg(feature = "stats")]
+    ops: AtomicU64,
+    #[cfg(feature = "stats")]
+    failures: AtomicU64,
+    #[cfg(feature = "stats")]
+    retries: AtomicU64,
+    /// The last operation has failed.
+    #[cfg(feature = "stats")]
+    failing: AtomicBool,
+}
+
+impl HandleCounters {
+    /// Count an operation with its outcome.
+    #[inline(always)]
+    pub(crate) fn record(&self, _success: bool) {
+        #[cfg(feature = "stats")]
+        {
+            if self.failing.swap(!_success, Ordering::Relaxed) {
+                self.retries.fetch_add(1, Ordering::Relaxed);
+            }
+            let counter = if _success { &self.ops } else { &self.failures };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    pub(crate) fn snapshot(&self) -> HandleStats {
+        HandleStats {
+            ops: self.ops.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn counting() {
+    let counters = Counters::default();
+    let strategy = super::WaitStrategy::SpinThenYield(1);
+    counters.wait(&strategy, 0);
+    counters.wait(&strategy, 1);
+    counters.wait(&super::WaitStrategy::Custom(&super::NoBackoff), 0);
+    counters.failed_cas();
+    counters.parked();
+    assert_eq!(
+        counters.snapshot(),
+        Stats {
+            failed_cas: 1,
+            spins: 2,
+            yields: 2,
+        }
+    );
+}
@@ -0,0 +1,316 @@
+use std::{
+    alloc, mem,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+/// Size of the huge pages requested by `AllocOptions::huge_pages`.
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+/// Options for allocating the slot array.
+///
+/// With `huge_pages`, the array is aligned and sized to multiples of `HUGE_PAGE_SIZE`,
+/// so that the OS can back it with huge pages. On Linux, the `huge-pages` feature
+/// additionally asks for them explicitly with `madvise`, otherwise it depends on
+/// the transparent huge page settings of the system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocOptions {
+    /// Alignment of the start of the array, has to be a power of two.
+    pub alignment: usize,
+    pub huge_pages: bool,
+}
+
+impl Default for AllocOptions {
+    fn default() -> Self {
+        Self {
+            alignment: 1,
+            huge_pages: false,
+        }
+    }
+}
+
+impl AllocOptions {
+    pub fn alignment(self, alignment: usize) -> Self {
+        Self { alignment, ..self }
+    }
+
+    pub fn huge_pages(self, huge_pages: bool) -> Self {
+        Self { huge_pages, ..self }
+    }
+}
+
+enum Owner {
+    Boxed,
+    Allocated(alloc::Layout),
+    #[cfg(not(feature = "loom"))]
+    User,
+}
+
+/// Slot array that is either owned by the queue, or provided by the user.
+pub(crate) struct Storage<S> {
+    slots: NonNull<[S]>,
+    owner: Owner,
+}
+
+unsafe impl<S: Send> Send for Storage<S> {}
+unsafe impl<S: Sync> Sync for Storage<S> {}
+
+impl<S> Storage<S> {
+    /// Allocate uninitialized slots with the given options.
+    pub(crate) fn allocate(
+        len: usize,
+        options: AllocOptions,
+        fun: impl FnMut() -> S,
+    ) -> Result<Self, super::NewError> {
+        if !options.alignment.is_power_of_two() {
+            return Err(super::NewError::InvalidAlignment(options.alignment));
+        }
+        let mut align = options.alignment.max(mem::align_of::<S>());
+        let mut size = len
+            .checked_mul(mem::size_of::<S>())
+            .ok_or(super::NewError::AllocationFailed)?;
+        if options.huge_pages {
+            align = align.max(HUGE_PAGE_SIZE);
+            size = size
+                .checked_next_multiple_of(HUGE_PAGE_SIZE)
+                .ok_or(super::NewError::AllocationFailed)?;
+        }
+        let layout = alloc::Layout::from_size_align(size, align)
+            .map_err(|_| super::NewError::AllocationFailed)?;
+        if layout.size() == 0 {
+            return super::try_alloc(len, fun).map(Self::from);
+        }
+        let data = NonNull::new(unsafe { alloc::alloc(layout) } as *mut S)
+            .ok_or(super::NewError::AllocationFailed)?;
+        #[cfg(all(feature = "huge-pages", target_os = "linux"))]
+        if options.huge_pages {
+            // only a hint, the allocation is usable either way
+            unsafe { libc::madvise(data.as_ptr().cast(), size, libc::MADV_HUGEPAGE) };
+        }
+        // frees the allocation if `fun` panics half way
+        struct Guard<S> {
+            data: NonNull<S>,
+            len: usize,
+            layout: alloc::Layout,
+        }
+        impl<S> Drop for Guard<S> {
+            fn drop(&mut self) {
+                unsafe {
+                    std::ptr::drop_in_place(
+                        NonNull::slice_from_raw_parts(self.data, self.len).as_ptr(),
+                    );
+                    alloc::dealloc(self.data.as_ptr().cast(), self.layout);
+                }
+            }
+        }
+        let mut guard = Guard {
+            data,
+            len: 0,
+            layout,
+        };
+        let mut fun = fun;
+        while guard.len < len {
+            unsafe { data.as_ptr().add(guard.len).write(fun()) };
+            guard.len += 1;
+        }
+        mem::forget(guard);
+        Ok(Self {
+            slots: NonNull::slice_from_raw_parts(data, len),
+            owner: Owner::Allocated(layout),
+        })
+    }
+}
+
+// Only the raw parts API deals with the ownership, which isn't available with loom.
+#[cfg(not(feature = "loom"))]
+impl<S> Storage<S> {
+    /// Use the memory provided by the user, without freeing it on drop.
+    ///
+    /// # Safety
+    /// The memory has to be valid for `len` slots, and not used by anything else
+    /// for as long as the storage is alive.
+    pub(crate) unsafe fn borrowed(data: *mut S, len: usize) -> Self {
+        Self {
+            slots: NonNull::new(std::ptr::slice_from_raw_parts_mut(data, len)).unwrap(),
+            owner: Owner::User,
+        }
+    }
+
+    pub(crate) fn is_boxed(&self) -> bool {
+        matches!(self.owner, Owner::Boxed)
+    }
+
+    /// Give up the ownership of the slots, which has to be taken back with `Box::from_raw`.
+    pub(crate) fn into_raw(self) -> *mut [S] {
+        assert!(self.is_boxed(), "The storage is not boxed");
+        let slots = self.slots.as_ptr();
+        mem::forget(self);
+        slots
+    }
+}
+
+impl<S> From<Box<[S]>> for Storage<S> {
+    fn from(boxed: Box<[S]>) -> Self {
+        Self {
+            slots: NonNull::from(Box::leak(boxed)),
+            owner: Owner::Boxed,
+        }
+    }
+}
+
+impl<S> Default for Storage<S> {
+    fn default() -> Self {
+        Box::<[S]>::default().into()
+    }
+}
+
+impl<S> Deref for Storage<S> {
+    type Target = [S];
+    fn deref(&self) -> &[S] {
+        unsafe { self.slots.as_ref() }
+    }
+}
+
+impl<S> DerefMut for Storage<S> {
+    fn deref_mut(&mut self) -> &mut [S] {
+        unsafe { self.slots.as_mut() }
+    }
+}
+
+impl<S> Drop for Storage<S> {
+    fn drop(&mut self) {
+        match self.owner {
+            Owner::Boxed => drop(unsafe { Box::from_raw(self.slots.as_ptr()) }),
+            Owner::Allocated(layout) => unsafe {
+                std::ptr::drop_in_place(self.slots.as_ptr());
+                alloc::dealloc(self.slots.as_ptr().cast(), layout);
+            },
+            #[cfg(not(feature = "loom"))]
+            Owner::User => {}
+        }
+    }
+}
+
+/// Slot of a queue, initialized for the elements between the tail and the head.
+pub(crate) type Slot<T> = mem::MaybeUninit<super::qstd::cell::UnsafeCell<T>>;
+
+/// Slots of a queue with the positions wrapping around explicitly.
+///
+/// With `N == 0`, the slots are on the heap, and one of them is always left empty
+/// to tell between the empty and full states, so the positions index the slots directly.
+/// Otherwise, the `N` slots are inline, and the positions wrap around at `2 * N`,
+/// selecting the slot modulo `N`, so no slot has to be left empty.
+pub(crate) struct Slots<S, H, const N: usize> {
+    heap: H,
+    inline: [S; N],
+}
+
+impl<S, H: Deref<Target = [S]>, const N: usize> Slots<S, H, N> {
+    /// Number of distinct positions, at which they wrap around.
+    #[inline(always)]
+    pub(crate) fn wrap(&self) -> usize {
+        if N == 0 {
+            self.heap.len()
+        } else {
+            2 * N
+        }
+    }
+
+    /// Largest number of elements.
+    #[inline(always)]
+    pub(crate) fn capacity(&self) -> usize {
+        if N == 0 {
+            self.heap.len() - 1
+        } else {
+            N
+        }
+    }
+
+    /// Index of the slot at the position.
+    #[inline(always)]
+    pub(crate) fn index(&self, position: usize) -> usize {
+        if N == 0 {
+            position
+        } else {
+            position % N
+        }
+    }
+
+    /// Number of positions from `tail` to `head`.
+    #[inline(always)]
+    pub(crate) fn distance(&self, tail: usize, head: usize) -> usize {
+        (head + self.wrap() - tail) % self.wrap()
+    }
+
+    /// Slot at the position, which has to be below `wrap`.
+    #[inline(always)]
+    pub(crate) unsafe fn get_unchecked(&self, position: usize) -> &S {
+        if N == 0 {
+            self.heap.get_unchecked(position)
+        } else {
+            self.inline.get_unchecked(position % N)
+        }
+    }
+
+    /// Heap slots, which are empty if the slots are inline.
+    #[cfg(any(test, not(feature = "loom")))]
+    pub(crate) fn heap(&self) -> &H {
+        &self.heap
+    }
+}
+
+impl<S, H> Slots<S, H, 0> {
+    #[cfg(not(feature = "loom"))]
+    pub(crate) fn into_heap(self) -> H {
+        self.heap
+    }
+}
+
+impl<S, H, const N: usize> Slots<mem::MaybeUninit<S>, H, N> {
+    /// Wrap the uninitialized slots, where `heap` is only used if `N` is zero.
+    pub(crate) fn new(heap: H) -> Self {
+        Self {
+            heap,
+            inline: [const { mem::MaybeUninit::uninit() }; N],
+        }
+    }
+}
+
+impl<T, H: DerefMut<Target = [mem::MaybeUninit<T>]>, const N: usize>
+    Slots<mem::MaybeUninit<T>, H, N>
+{
+    /// Drop the initialized slots in `tail..head` positions.
+    pub(crate) unsafe fn drop_range(&mut self, tail: usize, head: usize, order: super::DropOrder) {
+        let count = self.distance(tail, head);
+        for i in 0..count {
+            let offset = match order {
+                super::DropOrder::Fifo => i,
+                super::DropOrder::Lifo => count - 1 - i,
+            };
+            let index = self.index((tail + offset) % self.wrap());
+            let slot = if N == 0 {
+                &mut self.heap[index]
+            } else {
+                &mut self.inline[index]
+            };
+            slot.assume_init_drop();
+        }
+    }
+}
+
+#[test]
+fn allocate_panic() {
+    use std::sync::Arc;
+    let value = Arc::new(());
+    let mut count = 0;
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        Storage::allocate(4, AllocOptions::default().alignment(64), || {
+            count += 1;
+            assert!(count <= 2, "out of values");
+            Arc::clone(&value)
+        })
+    }));
+    assert!(result.is_err());
+    // the slots written before the panic are dropped
+    assert_eq!(Arc::strong_count(&value), 1);
+}
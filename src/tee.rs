@@ -0,0 +1,98 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+
+/// What to do when the secondary queue of a `Tee` is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeePolicy {
+    /// Drop the copy, accounting for it in `Tee::missed`.
+    Drop,
+    /// Retry the given number of times, waiting for the consumers
+    /// with the default `WaitStrategy`, before dropping the copy.
+    Retry(u32),
+}
+
+/// Producer-side wrapper duplicating every value into two queues.
+///
+/// The primary queue gets the value itself, and the secondary gets a clone,
+/// for example to mirror the traffic to an audit or debug consumer.
+/// A push only succeeds if the primary accepts the value, and the secondary is
+/// only tried afterwards, so it never sees values that the primary rejected.
+/// Copies the secondary couldn't accept are handled by the `TeePolicy`
+/// and are always accounted for, never lost silently.
+pub struct Tee<Q1, Q2> {
+    primary: Q1,
+    secondary: Q2,
+    policy: TeePolicy,
+    missed: AtomicUsize,
+}
+
+impl<Q1, Q2> Tee<Q1, Q2> {
+    pub fn new(primary: Q1, secondary: Q2, policy: TeePolicy) -> Self {
+        Self {
+            primary,
+            secondary,
+            policy,
+            missed: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn primary(&self) -> &Q1 {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &Q2 {
+        &self.secondary
+    }
+
+    /// Number of copies the secondary queue missed.
+    pub fn missed(&self) -> usize {
+        self.missed.load(Ordering::Relaxed)
+    }
+
+    pub fn push<T: Clone>(&self, value: T) -> Result<(), T>
+    where
+        Q1: super::SynQueue<T>,
+        Q2: super::SynQueue<T>,
+    {
+        let copy = value.clone();
+        self.primary.push(value)?;
+        let retries = match self.policy {
+            TeePolicy::Drop => 0,
+            TeePolicy::Retry(count) => count,
+        };
+        let mut copy = match self.secondary.push(copy) {
+            Ok(()) => return Ok(()),
+            Err(copy) => copy,
+        };
+        let wait = super::WaitStrategy::default();
+        for step in 0..retries {
+            wait.wait(step);
+            copy = match self.secondary.push(copy) {
+                Ok(()) => return Ok(()),
+                Err(copy) => copy,
+            };
+        }
+        trace!("Tee missed a copy");
+        self.missed.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[test]
+fn mirror() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let tee = Tee::new(
+            super::DoubleQueue::new(2),
+            super::AxelQueue::new(1),
+            TeePolicy::Retry(2),
+        );
+        tee.push(1).unwrap();
+        tee.push(2).unwrap();
+        assert_eq!(tee.push(3), Err(3));
+        assert_eq!(tee.missed(), 1);
+        assert_eq!(tee.primary().pop(), Some(1));
+        assert_eq!(tee.primary().pop(), Some(2));
+        assert_eq!(tee.secondary().pop(), Some(1));
+        assert_eq!(tee.secondary().pop(), None);
+    })
+}
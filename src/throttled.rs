@@ -0,0 +1,118 @@
+use super::qstd::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Queue wrapper limiting the rate of dequeuing with a token bucket.
+///
+/// ## Principle
+/// The bucket is tracked as a "theoretical arrival time" of the next token (GCRA),
+/// which is a single atomic advanced by a CAS loop on every `pop`.
+/// If no token is available, `pop` returns `None` even if the queue has elements,
+/// so consumers can't overrun the downstream systems. The queue is reported
+/// empty in the meantime, so that `is_empty` agrees with `pop`.
+pub struct Throttled<Q> {
+    queue: Q,
+    start: Instant,
+    interval: u64,
+    tolerance: u64,
+    arrival: AtomicU64,
+}
+
+impl<Q> Throttled<Q> {
+    /// Create a wrapper allowing `per_second` pops on average, and up to `burst` at once.
+    pub fn new(queue: Q, per_second: u32, burst: u32) -> Self {
+        assert_ne!(per_second, 0);
+        assert_ne!(burst, 0);
+        let interval = Duration::from_secs(1).as_nanos() as u64 / per_second as u64;
+        Self {
+            queue,
+            start: Instant::now(),
+            interval,
+            tolerance: interval * (burst as u64 - 1),
+            arrival: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    /// Check if a token is available, without taking it.
+    fn has_token(&self) -> bool {
+        let now = self.now();
+        self.arrival.load(super::LOAD_ORDER).max(now) <= now + self.tolerance
+    }
+
+    fn acquire_token(&self) -> bool {
+        let now = self.now();
+        let mut arrival = self.arrival.load(super::LOAD_ORDER);
+        loop {
+            let tat = arrival.max(now);
+            if tat > now + self.tolerance {
+                return false;
+            }
+            match self.arrival.compare_exchange_weak(
+                arrival,
+                tat + self.interval,
+                super::CAS_ORDER,
+                super::LOAD_ORDER,
+            ) {
+                Ok(_) => return true,
+                Err(other) => arrival = other,
+            }
+        }
+    }
+}
+
+impl<T, Q> super::SynQueue<T> for Throttled<Q>
+where
+    Q: super::SynQueue<T>,
+{
+    fn push(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        if !self.acquire_token() {
+            return None;
+        }
+        let value = self.queue.pop();
+        if value.is_none() {
+            // give the token back
+            self.arrival.fetch_sub(self.interval, Ordering::Relaxed);
+        }
+        value
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty() || !self.has_token()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn burst() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let tq = Throttled::new(super::DoubleQueue::new(4), 1, 2);
+        assert_eq!(tq.pop(), None::<i32>);
+        for i in 0..4 {
+            tq.push(i).unwrap();
+        }
+        assert!(!tq.is_empty());
+        assert_eq!(tq.pop(), Some(0));
+        assert_eq!(tq.pop(), Some(1));
+        assert!(tq.is_empty());
+        assert_eq!(tq.pop(), None);
+    })
+}
@@ -0,0 +1,18 @@
+/// Producer-side cache of the queue state, created by `producer_token()`.
+///
+/// Passing the token to `push_with_token` lets the queue start from
+/// the state observed by the previous push of the same producer,
+/// instead of loading it again. What exactly is cached depends on the queue,
+/// and a token of another queue only makes the first attempt miss.
+#[derive(Debug)]
+pub struct ProducerToken {
+    pub(crate) hint: usize,
+}
+
+/// Consumer-side cache of the queue state, created by `consumer_token()`.
+///
+/// See `ProducerToken` for the details.
+#[derive(Debug)]
+pub struct ConsumerToken {
+    pub(crate) hint: usize,
+}
@@ -0,0 +1,190 @@
+use super::qstd::{cell::UnsafeCell, hint, sync::atomic::AtomicUsize};
+use std::mem;
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    value: mem::MaybeUninit<UnsafeCell<T>>,
+}
+
+/// Yet another internally synchronized (MPMC) queue, following Dmitry Vyukov's design.
+///
+/// ## Principle
+/// Every slot has a sequence counter, telling which lap of the ring it's ready for.
+/// A producer at position `pos` waits for the sequence to be `pos`, claims the position
+/// with a CAS on the head, writes the data, and sets the sequence to `pos + 1`.
+/// A consumer waits for `pos + 1`, claims the position with a CAS on the tail,
+/// reads the data, and sets the sequence to `pos + capacity` for the next lap.
+/// Head and tail are never wrapped, and only their lower bits select the slot,
+/// so the capacity has to be a power of two.
+///
+/// This is the classic approach with an extra word per element, which
+/// the other queues in this crate are trying to avoid.
+pub struct VyukovQueue<T> {
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    slots: Box<[Slot<T>]>,
+}
+
+unsafe impl<T> Sync for VyukovQueue<T> {}
+
+impl<T> VyukovQueue<T> {
+    #[inline(always)]
+    fn slot(&self, pos: usize) -> &Slot<T> {
+        unsafe { self.slots.get_unchecked(pos & (self.slots.len() - 1)) }
+    }
+
+    fn pop_impl(&self) -> Result<T, super::TryPopError> {
+        let mut pos = self.tail.load(super::LOAD_ORDER);
+        loop {
+            let slot = self.slot(pos);
+            let sequence = slot.sequence.load(super::LOAD_ORDER);
+            let diff = sequence.wrapping_sub(pos.wrapping_add(1)) as isize;
+            if diff == 0 {
+                match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    super::CAS_ORDER,
+                    super::LOAD_ORDER,
+                ) {
+                    Ok(_) => {
+                        trace!("Pop success, pos = {:x}", pos);
+                        let value = unsafe { slot.value.assume_init_read().into_inner() };
+                        slot.sequence
+                            .store(pos.wrapping_add(self.slots.len()), super::STORE_ORDER);
+                        return Ok(value);
+                    }
+                    Err(other) => pos = other,
+                }
+            } else if diff < 0 {
+                // the slot isn't written yet
+                return Err(if self.head.load(super::LOAD_ORDER) != pos {
+                    super::TryPopError::Busy
+                } else {
+                    super::TryPopError::Empty
+                });
+            } else {
+                pos = self.tail.load(super::LOAD_ORDER);
+            }
+            hint::spin_loop();
+        }
+    }
+}
+
+impl<T: Send> super::NewQueue<T> for VyukovQueue<T> {
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        if !capacity.is_power_of_two() {
+            return Err(super::NewError::InvalidCapacity(capacity));
+        }
+        let mut index = 0;
+        Ok(Self {
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            slots: super::try_alloc(capacity, || {
+                index += 1;
+                Slot {
+                    sequence: AtomicUsize::new(index - 1),
+                    value: mem::MaybeUninit::uninit(),
+                }
+            })?,
+        })
+    }
+}
+
+impl<T: Send> super::SynQueue<T> for VyukovQueue<T> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.head.load(super::LOAD_ORDER);
+        loop {
+            let slot = self.slot(pos);
+            let sequence = slot.sequence.load(super::LOAD_ORDER);
+            let diff = sequence.wrapping_sub(pos) as isize;
+            if diff == 0 {
+                match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    super::CAS_ORDER,
+                    super::LOAD_ORDER,
+                ) {
+                    Ok(_) => {
+                        trace!("Push success, pos = {:x}", pos);
+                        unsafe { super::UnsafeCellHelper::write(slot.value.as_ptr(), value) };
+                        slot.sequence.store(pos.wrapping_add(1), super::STORE_ORDER);
+                        return Ok(());
+                    }
+                    Err(other) => pos = other,
+                }
+            } else if diff < 0 {
+                // the slot isn't consumed on the previous lap
+                return Err(value);
+            } else {
+                pos = self.head.load(super::LOAD_ORDER);
+            }
+            hint::spin_loop();
+        }
+    }
+
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn pop(&self) -> Option<T> {
+        self.pop_impl().ok()
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        self.pop_impl()
+    }
+
+    fn is_empty(&self) -> bool {
+        let tail = self.tail.load(super::LOAD_ORDER);
+        self.head.load(super::LOAD_ORDER) == tail
+    }
+
+    fn is_full(&self) -> bool {
+        let tail = self.tail.load(super::LOAD_ORDER);
+        self.head.load(super::LOAD_ORDER).wrapping_sub(tail) >= self.slots.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+impl<T> Drop for VyukovQueue<T> {
+    fn drop(&mut self) {
+        let head = self.head.load(super::LOAD_ORDER);
+        let mut pos = self.tail.load(super::LOAD_ORDER);
+        while pos != head {
+            let index = pos & (self.slots.len() - 1);
+            unsafe { self.slots[index].value.assume_init_drop() };
+            pos = pos.wrapping_add(1);
+        }
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<VyukovQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<VyukovQueue<i32>>();
+}
+
+#[test]
+fn barrage() {
+    super::test_barrage::<VyukovQueue<usize>>();
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    super::lincheck::check_mpmc::<VyukovQueue<u32>>();
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<VyukovQueue<u32>>();
+}
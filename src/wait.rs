@@ -0,0 +1,270 @@
+use super::qstd::{
+    hint,
+    sync::{atomic::AtomicUsize, Condvar, Mutex},
+    thread,
+};
+#[cfg(feature = "portable-atomic")]
+use portable_atomic::{AtomicU64, Ordering};
+#[cfg(not(feature = "portable-atomic"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{fmt, ptr, sync::RwLock, time::Duration};
+
+/// User-defined way of waiting, plugged in with `WaitStrategy::Custom`.
+pub trait Backoff: fmt::Debug + Send + Sync {
+    /// Wait for the `step`-th time within an operation, where `step` starts at 0.
+    fn wait(&self, step: u32);
+}
+
+/// Spin for an exponentially growing number of iterations, up to `2^limit`.
+///
+/// Limits above 63 are treated as 63.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExponentialSpin(pub u32);
+
+impl ExponentialSpin {
+    fn iterations(&self, step: u32) -> u64 {
+        1 << step.min(self.0).min(63)
+    }
+}
+
+impl Backoff for ExponentialSpin {
+    fn wait(&self, step: u32) {
+        for _ in 0..self.iterations(step) {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Park the thread for the given duration.
+///
+/// Nothing unparks it early, so this is a sleep that wakes up on spurious unparks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Park(pub Duration);
+
+impl Backoff for Park {
+    fn wait(&self, _step: u32) {
+        std::thread::park_timeout(self.0);
+    }
+}
+
+/// Retry right away, without any hint to the CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoBackoff;
+
+impl Backoff for NoBackoff {
+    fn wait(&self, _step: u32) {}
+}
+
+/// Strategy for waiting on other threads to finish their operations.
+///
+/// This is used when an operation can't proceed until a peer completes,
+/// such as the catch-up phase of `DoubleQueue`. CAS contention is always
+/// handled by spinning.
+#[derive(Clone, Copy, Debug)]
+pub enum WaitStrategy {
+    /// Busy-wait with a spin loop hint.
+    Spin,
+    /// Yield the time slice to the OS scheduler.
+    Yield,
+    /// Spin for the given number of iterations, then start yielding.
+    SpinThenYield(u32),
+    /// Spin for the given number of iterations, then park the thread
+    /// until the operation it waits for is completed.
+    ///
+    /// Only `DoubleQueue` can wake up the parked threads, other queues yield instead.
+    SpinThenPark(u32),
+    /// Delegate to a user-defined backoff.
+    Custom(&'static dyn Backoff),
+}
+
+impl PartialEq for WaitStrategy {
+    fn eq(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (Self::Spin, Self::Spin) | (Self::Yield, Self::Yield) => true,
+            (Self::SpinThenYield(a), Self::SpinThenYield(b))
+            | (Self::SpinThenPark(a), Self::SpinThenPark(b)) => a == b,
+            (Self::Custom(a), Self::Custom(b)) => ptr::addr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for WaitStrategy {}
+
+const KIND_SHIFT: u32 = 32;
+const KIND_CUSTOM: u64 = 3;
+
+impl WaitStrategy {
+    const fn encode(self) -> u64 {
+        match self {
+            Self::Spin => 0,
+            Self::Yield => 1 << KIND_SHIFT,
+            Self::SpinThenYield(budget) => (2 << KIND_SHIFT) | budget as u64,
+            // the backoff itself is stored in `DEFAULT_CUSTOM`
+            Self::Custom(_) => KIND_CUSTOM << KIND_SHIFT,
+            Self::SpinThenPark(budget) => (4 << KIND_SHIFT) | budget as u64,
+        }
+    }
+
+    fn decode(raw: u64) -> Self {
+        match raw >> KIND_SHIFT {
+            0 => Self::Spin,
+            1 => Self::Yield,
+            2 => Self::SpinThenYield(raw as u32),
+            KIND_CUSTOM => match *DEFAULT_CUSTOM.read().unwrap() {
+                Some(backoff) => Self::Custom(backoff),
+                None => Self::Yield,
+            },
+            _ => Self::SpinThenPark(raw as u32),
+        }
+    }
+
+    /// Wait for the `step`-th time within an operation.
+    #[inline]
+    pub(crate) fn wait(&self, step: u32) {
+        match *self {
+            Self::Spin => hint::spin_loop(),
+            Self::SpinThenYield(budget) | Self::SpinThenPark(budget) if step < budget => {
+                hint::spin_loop()
+            }
+            Self::Yield | Self::SpinThenYield(_) | Self::SpinThenPark(_) => thread::yield_now(),
+            Self::Custom(backoff) => backoff.wait(step),
+        }
+    }
+}
+
+impl WaitStrategy {
+    /// Check if the `step`-th wait should park the thread instead of calling `wait`.
+    #[inline]
+    pub(crate) fn parks(&self, step: u32) -> bool {
+        matches!(*self, Self::SpinThenPark(budget) if step >= budget)
+    }
+
+    /// Check if the `step`-th wait gives up the time slice.
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub(crate) fn yields(&self, step: u32) -> bool {
+        match *self {
+            Self::Spin | Self::Custom(_) => false,
+            Self::Yield => true,
+            Self::SpinThenYield(budget) | Self::SpinThenPark(budget) => step >= budget,
+        }
+    }
+}
+
+impl Backoff for WaitStrategy {
+    fn wait(&self, step: u32) {
+        WaitStrategy::wait(self, step)
+    }
+}
+
+/// Place for the threads parked by `WaitStrategy::SpinThenPark`.
+///
+/// Like in `BlockingQueue`, the completing operations only take the lock
+/// if there are parked threads.
+pub(crate) struct Parking {
+    lock: Mutex<()>,
+    condvar: Condvar,
+    parked: AtomicUsize,
+}
+
+impl Parking {
+    pub(crate) fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+            condvar: Condvar::new(),
+            parked: AtomicUsize::new(0),
+        }
+    }
+
+    /// Park until `done` returns true.
+    pub(crate) fn park_until(&self, done: impl Fn() -> bool) {
+        let mut guard = self.lock.lock().unwrap();
+        self.parked.fetch_add(1, Ordering::SeqCst);
+        // pairs with `parked_after_fence` in `unpark_all`
+        #[cfg(not(feature = "tsan"))]
+        super::qstd::sync::atomic::fence(Ordering::SeqCst);
+        while !done() {
+            guard = self.condvar.wait(guard).unwrap();
+        }
+        self.parked.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Wake up all the parked threads, so that they check their conditions.
+    pub(crate) fn unpark_all(&self) {
+        if self.parked_after_fence() != 0 {
+            let _guard = self.lock.lock().unwrap();
+            self.condvar.notify_all();
+        }
+    }
+
+    #[cfg(not(feature = "tsan"))]
+    fn parked_after_fence(&self) -> usize {
+        super::qstd::sync::atomic::fence(Ordering::SeqCst);
+        self.parked.load(Ordering::SeqCst)
+    }
+
+    /// ThreadSanitizer doesn't model standalone fences, so synchronize
+    /// on `parked` itself: the RMW reads the latest value in its order.
+    #[cfg(feature = "tsan")]
+    fn parked_after_fence(&self) -> usize {
+        self.parked.fetch_add(0, Ordering::SeqCst)
+    }
+}
+
+static DEFAULT_STRATEGY: AtomicU64 = AtomicU64::new(WaitStrategy::Yield.encode());
+static DEFAULT_CUSTOM: RwLock<Option<&'static dyn Backoff>> = RwLock::new(None);
+
+/// Set the process-wide wait strategy for the queues created afterwards
+/// without an explicit strategy.
+pub fn set_default_wait_strategy(strategy: WaitStrategy) {
+    if let WaitStrategy::Custom(backoff) = strategy {
+        *DEFAULT_CUSTOM.write().unwrap() = Some(backoff);
+    }
+    DEFAULT_STRATEGY.store(strategy.encode(), Ordering::Relaxed);
+}
+
+impl Default for WaitStrategy {
+    /// Returns the process-wide default strategy.
+    fn default() -> Self {
+        Self::decode(DEFAULT_STRATEGY.load(Ordering::Relaxed))
+    }
+}
+
+#[test]
+fn encoding() {
+    for strategy in [
+        WaitStrategy::Spin,
+        WaitStrategy::Yield,
+        WaitStrategy::SpinThenYield(0),
+        WaitStrategy::SpinThenYield(!0),
+        WaitStrategy::SpinThenPark(5),
+    ] {
+        assert_eq!(WaitStrategy::decode(strategy.encode()), strategy);
+    }
+}
+
+#[test]
+fn custom() {
+    static PARK: Park = Park(Duration::from_micros(1));
+    let strategy = WaitStrategy::Custom(&PARK);
+    assert_eq!(strategy, WaitStrategy::Custom(&PARK));
+    assert_ne!(strategy, WaitStrategy::Custom(&NoBackoff));
+    assert_eq!(WaitStrategy::decode(strategy.encode()), WaitStrategy::Yield);
+    *DEFAULT_CUSTOM.write().unwrap() = Some(&PARK);
+    assert_eq!(WaitStrategy::decode(strategy.encode()), strategy);
+    super::loom::model(move || {
+        for step in 0..4 {
+            strategy.wait(step);
+            ExponentialSpin(2).wait(step);
+        }
+    })
+}
+
+#[test]
+fn exponential_limit() {
+    assert_eq!(ExponentialSpin(3).iterations(2), 4);
+    assert_eq!(ExponentialSpin(3).iterations(10), 8);
+    assert_eq!(ExponentialSpin(64).iterations(100), 1 << 63);
+    assert_eq!(ExponentialSpin(u32::MAX).iterations(u32::MAX), 1 << 63);
+}
@@ -0,0 +1,128 @@
+use super::qstd::sync::atomic::{AtomicUsize, Ordering};
+
+/// Queue wrapper recording the maximum observed occupancy.
+///
+/// Helps tuning the capacity with the data from production: a mark that stays
+/// well below the capacity means the memory is wasted, and a mark reaching it
+/// means the producers are getting rejected.
+///
+/// The occupancy is counted by the wrapper. A push takes its place in the count
+/// before it's attempted, and gives it back if rejected, while only the successful
+/// pushes raise the mark. The rejected pushes in flight can still inflate the count
+/// seen by the others, so the mark is clamped to the capacity.
+/// Elements that were in the queue before wrapping it are not counted,
+/// and popping them doesn't take the count below zero.
+pub struct HighWater<Q> {
+    queue: Q,
+    depth: AtomicUsize,
+    mark: AtomicUsize,
+}
+
+impl<Q> HighWater<Q> {
+    pub fn new(queue: Q) -> Self {
+        Self {
+            queue,
+            depth: AtomicUsize::new(0),
+            mark: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &Q {
+        &self.queue
+    }
+
+    /// The maximum occupancy observed since the creation or the last reset.
+    pub fn high_water_mark(&self) -> usize {
+        self.mark.load(Ordering::Relaxed)
+    }
+
+    /// Forget the maximum observed so far, starting over from the current occupancy.
+    pub fn reset_high_water_mark(&self) {
+        self.mark
+            .store(self.depth.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    fn decrement(&self) {
+        // popping the elements from before the wrapping mustn't wrap around
+        let _ = self
+            .depth
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |depth| {
+                depth.checked_sub(1)
+            });
+    }
+}
+
+impl<T, Q: super::SynQueue<T>> super::SynQueue<T> for HighWater<Q> {
+    fn push(&self, value: T) -> Result<(), T> {
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.queue.push(value) {
+            Ok(()) => {
+                self.mark
+                    .fetch_max(depth.min(self.queue.capacity()), Ordering::Relaxed);
+                Ok(())
+            }
+            Err(value) => {
+                self.decrement();
+                Err(value)
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let value = self.queue.pop()?;
+        self.decrement();
+        Some(value)
+    }
+
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let value = self.queue.try_pop()?;
+        self.decrement();
+        Ok(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+#[test]
+fn mark() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let hw = HighWater::new(super::DoubleQueue::new(3));
+        for i in 0..4 {
+            let _ = hw.push(i);
+        }
+        assert_eq!(hw.high_water_mark(), 3);
+        assert_eq!(hw.pop(), Some(0));
+        assert_eq!(hw.pop(), Some(1));
+        assert_eq!(hw.high_water_mark(), 3);
+        hw.reset_high_water_mark();
+        assert_eq!(hw.high_water_mark(), 1);
+        hw.push(4).unwrap();
+        assert_eq!(hw.high_water_mark(), 2);
+    })
+}
+
+#[test]
+fn prefilled() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let queue = super::DoubleQueue::new(3);
+        queue.push(0).unwrap();
+        queue.push(1).unwrap();
+        let hw = HighWater::new(queue);
+        assert_eq!(hw.pop(), Some(0));
+        assert_eq!(hw.pop(), Some(1));
+        hw.push(2).unwrap();
+        assert_eq!(hw.high_water_mark(), 1);
+    })
+}
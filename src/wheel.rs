@@ -0,0 +1,172 @@
+use super::qstd::sync::Mutex;
+use std::{collections::VecDeque, mem};
+
+const SLOT_BITS: usize = 6;
+const NUM_SLOTS: usize = 1 << SLOT_BITS;
+const NUM_LEVELS: usize = 4;
+
+struct Timer<T> {
+    tick: u64,
+    value: T,
+}
+
+struct Wheel<T> {
+    now: u64,
+    levels: [Vec<Vec<Timer<T>>>; NUM_LEVELS],
+    // expired timers that didn't fit into the output queue
+    pending: VecDeque<T>,
+}
+
+impl<T> Wheel<T> {
+    fn insert(&mut self, timer: Timer<T>) {
+        if timer.tick <= self.now {
+            self.pending.push_back(timer.value);
+            return;
+        }
+        let max_tick = self.now.saturating_add((1 << (SLOT_BITS * NUM_LEVELS)) - 1);
+        let tick = timer.tick.min(max_tick);
+        let delta = tick - self.now;
+        let level = (0..NUM_LEVELS)
+            .find(|&level| delta < 1 << (SLOT_BITS * (level + 1)))
+            .unwrap();
+        let slot = (tick >> (SLOT_BITS * level)) as usize & (NUM_SLOTS - 1);
+        self.levels[level][slot].push(timer);
+    }
+}
+
+/// Hierarchical timer wheel feeding expired elements into a queue.
+///
+/// ## Principle
+/// Level `L` of the wheel has 64 slots, each covering `64^L` ticks.
+/// Timers are placed at the lowest level that can fit their deadline,
+/// and get cascaded into lower levels as the time advances. When a timer expires,
+/// its value gets pushed into the output queue for the consumers to pick up.
+/// Scheduling and ticking are serialized by a lock.
+///
+/// The wheel slots are plain vectors under that lock rather than the queues of this crate:
+/// any number of timers can share a slot, while the queues have a fixed capacity,
+/// and cascading takes a whole slot at once, which the lock makes trivial.
+/// The queues are used where the concurrency is, for handing the expired values
+/// over to the consumers.
+pub struct TimerWheel<T, Q> {
+    wheel: Mutex<Wheel<T>>,
+    output: Q,
+}
+
+impl<T, Q: super::SynQueue<T>> TimerWheel<T, Q> {
+    pub fn new(output: Q) -> Self {
+        let make_level = || (0..NUM_SLOTS).map(|_| Vec::new()).collect();
+        Self {
+            wheel: Mutex::new(Wheel {
+                now: 0,
+                levels: [make_level(), make_level(), make_level(), make_level()],
+                pending: VecDeque::new(),
+            }),
+            output,
+        }
+    }
+
+    pub fn output(&self) -> &Q {
+        &self.output
+    }
+
+    /// Current tick of the wheel.
+    pub fn now(&self) -> u64 {
+        self.wheel.lock().unwrap().now
+    }
+
+    /// Schedule a value to be pushed into the output queue after `delay` ticks.
+    pub fn schedule(&self, delay: u64, value: T) {
+        let mut wheel = self.wheel.lock().unwrap();
+        // a delay too far into the future never expires
+        let tick = wheel.now.saturating_add(delay);
+        wheel.insert(Timer { tick, value });
+    }
+
+    /// Advance the wheel by one tick, and push the expired values into the output queue.
+    ///
+    /// Returns the number of values pushed. Expired values that don't fit into
+    /// the output queue are retried on the following ticks.
+    pub fn tick(&self) -> usize {
+        let mut wheel = self.wheel.lock().unwrap();
+        wheel.now += 1;
+        let now = wheel.now;
+
+        for level in 1..NUM_LEVELS {
+            if now & ((1 << (SLOT_BITS * level)) - 1) != 0 {
+                break;
+            }
+            let slot = (now >> (SLOT_BITS * level)) as usize & (NUM_SLOTS - 1);
+            for timer in mem::take(&mut wheel.levels[level][slot]) {
+                wheel.insert(timer);
+            }
+        }
+
+        let slot = now as usize & (NUM_SLOTS - 1);
+        for timer in mem::take(&mut wheel.levels[0][slot]) {
+            wheel.pending.push_back(timer.value);
+        }
+
+        let mut count = 0;
+        while let Some(value) = wheel.pending.pop_front() {
+            if let Err(value) = self.output.push(value) {
+                wheel.pending.push_front(value);
+                break;
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+#[test]
+fn smoke() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let tw = TimerWheel::new(super::DoubleQueue::new(4));
+        tw.schedule(3, 'a');
+        tw.schedule(1, 'b');
+        tw.schedule(3, 'c');
+        assert_eq!(tw.tick(), 1);
+        assert_eq!(tw.output().pop(), Some('b'));
+        assert_eq!(tw.tick(), 0);
+        assert_eq!(tw.tick(), 2);
+        assert_eq!(tw.output().pop(), Some('a'));
+        assert_eq!(tw.output().pop(), Some('c'));
+    })
+}
+
+#[test]
+fn cascade() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let tw = TimerWheel::new(super::DoubleQueue::new(2));
+        let delays = [5000, 64, 100, 4096, 63];
+        for &delay in delays.iter() {
+            tw.schedule(delay, delay);
+        }
+        let mut fired = Vec::new();
+        while fired.len() < delays.len() {
+            tw.tick();
+            while let Some(delay) = tw.output().pop() {
+                assert_eq!(delay, tw.now());
+                fired.push(delay);
+            }
+        }
+        assert_eq!(fired, [63, 64, 100, 4096, 5000]);
+    })
+}
+
+#[test]
+fn far_future() {
+    use super::{NewQueue as _, SynQueue as _};
+    super::loom::model(|| {
+        let tw = TimerWheel::new(super::DoubleQueue::new(2));
+        tw.tick();
+        tw.schedule(u64::MAX, 0);
+        for _ in 0..NUM_SLOTS {
+            assert_eq!(tw.tick(), 0);
+        }
+        assert!(tw.output().is_empty());
+    })
+}
@@ -0,0 +1,288 @@
+use super::pad::CachePadded;
+use super::qstd::{cell::UnsafeCell, hint};
+use portable_atomic::AtomicU128;
+use std::mem;
+
+/// The whole queue state, packed into a single double-width word.
+#[derive(Clone, Copy, Debug)]
+struct State {
+    head: u32,
+    tail: u32,
+    /// Slots holding a written element.
+    full: u32,
+    /// Slots being read by a consumer.
+    taken: u32,
+}
+
+const FULL_SHIFT: u32 = 64;
+const TAKEN_SHIFT: u32 = 96;
+
+impl State {
+    #[inline(always)]
+    fn unpack(raw: u128) -> Self {
+        Self {
+            head: raw as u32,
+            tail: (raw >> 32) as u32,
+            full: (raw >> FULL_SHIFT) as u32,
+            taken: (raw >> TAKEN_SHIFT) as u32,
+        }
+    }
+    #[inline(always)]
+    fn pack(self) -> u128 {
+        (self.head as u128)
+            | ((self.tail as u128) << 32)
+            | ((self.full as u128) << FULL_SHIFT)
+            | ((self.taken as u128) << TAKEN_SHIFT)
+    }
+}
+
+/// An internally synchronized (MPMC) queue on a double-width CAS.
+///
+/// ## Principle
+/// The head, the tail, and the occupancy masks of all the slots share one 128-bit atomic,
+/// which is lock-free on x86-64 (`cmpxchg16b`) and AArch64 (`casp`).
+/// Claiming a position is a single CAS that sees the state of every slot at once,
+/// and finishing the data operation is a single `fetch_or`/`fetch_and` on the mask.
+/// Unlike `DoubleQueue`, there is no second CAS loop waiting for the other
+/// operations to catch up, so they complete out of order.
+///
+/// The masks limit the capacity to `MAX_CAPACITY` elements. The positions wrap around
+/// at twice the capacity, so it doesn't have to be a power of two.
+///
+/// `pop` returns `None` if the oldest element is still being written,
+/// and `try_pop` reports that as `TryPopError::Busy`.
+pub struct WideQueue<T> {
+    state: CachePadded<AtomicU128>,
+    data: Box<[mem::MaybeUninit<UnsafeCell<T>>]>,
+    wait: super::WaitStrategy,
+}
+
+unsafe impl<T: Send> Sync for WideQueue<T> {}
+
+impl<T> WideQueue<T> {
+    pub const MAX_CAPACITY: usize = 32;
+
+    /// Override the process-wide default wait strategy.
+    pub fn with_wait_strategy(mut self, strategy: super::WaitStrategy) -> Self {
+        self.wait = strategy;
+        self
+    }
+
+    fn advance(&self, position: u32) -> u32 {
+        if position as usize + 1 == 2 * self.data.len() {
+            0
+        } else {
+            position + 1
+        }
+    }
+
+    fn len(&self, s: State) -> usize {
+        let wrap = 2 * self.data.len();
+        (s.head as usize + wrap - s.tail as usize) % wrap
+    }
+
+    /// Index of the slot and its bit in the masks.
+    #[inline(always)]
+    fn slot(&self, position: u32) -> (usize, u32) {
+        let index = position as usize % self.data.len();
+        (index, 1 << index)
+    }
+}
+
+impl<T: Send> super::NewQueue<T> for WideQueue<T> {
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, super::NewError> {
+        if capacity == 0 || capacity > Self::MAX_CAPACITY {
+            return Err(super::NewError::InvalidCapacity(capacity));
+        }
+        Ok(Self {
+            state: CachePadded::new(AtomicU128::new(0)),
+            data: super::try_alloc(capacity, mem::MaybeUninit::uninit)?,
+            wait: super::WaitStrategy::default(),
+        })
+    }
+}
+
+impl<T: Send> super::SynQueue<T> for WideQueue<T> {
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn push(&self, value: T) -> Result<(), T> {
+        let mut raw = self.state.load(super::LOAD_ORDER);
+        let mut step = 0;
+        let (index, bit) = loop {
+            trace!("Push pre-CAS: {:x}", raw);
+            let s = State::unpack(raw);
+            if self.len(s) == self.data.len() {
+                return Err(value);
+            }
+            let (index, bit) = self.slot(s.head);
+            if s.taken & bit != 0 {
+                // a consumer of the previous lap is still reading
+                self.wait.wait(step);
+                step = step.saturating_add(1);
+                raw = self.state.load(super::LOAD_ORDER);
+                continue;
+            }
+            match self.state.compare_exchange_weak(
+                raw,
+                State {
+                    head: self.advance(s.head),
+                    ..s
+                }
+                .pack(),
+                super::CAS_ORDER,
+                super::LOAD_ORDER,
+            ) {
+                Ok(_) => break (index, bit),
+                Err(other) => raw = other,
+            }
+            hint::spin_loop();
+        };
+
+        unsafe { super::UnsafeCellHelper::write(self.data.get_unchecked(index).as_ptr(), value) };
+        self.state
+            .fetch_or((bit as u128) << FULL_SHIFT, super::STORE_ORDER);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.try_pop().ok()
+    }
+
+    #[cfg_attr(feature = "profiling", profiling::function)]
+    fn try_pop(&self) -> Result<T, super::TryPopError> {
+        let mut raw = self.state.load(super::LOAD_ORDER);
+        let (index, bit) = loop {
+            trace!("Pop pre-CAS: {:x}", raw);
+            let s = State::unpack(raw);
+            if s.head == s.tail {
+                return Err(super::TryPopError::Empty);
+            }
+            let (index, bit) = self.slot(s.tail);
+            if s.full & bit == 0 {
+                return Err(super::TryPopError::Busy);
+            }
+            match self.state.compare_exchange_weak(
+                raw,
+                State {
+                    head: s.head,
+                    tail: self.advance(s.tail),
+                    full: s.full & !bit,
+                    taken: s.taken | bit,
+                }
+                .pack(),
+                super::CAS_ORDER,
+                super::LOAD_ORDER,
+            ) {
+                Ok(_) => break (index, bit),
+                Err(other) => raw = other,
+            }
+            hint::spin_loop();
+        };
+
+        let value = unsafe {
+            self.data
+                .get_unchecked(index)
+                .assume_init_read()
+                .into_inner()
+        };
+        self.state
+            .fetch_and(!((bit as u128) << TAKEN_SHIFT), super::STORE_ORDER);
+        Ok(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        let s = State::unpack(self.state.load(super::LOAD_ORDER));
+        s.head == s.tail
+    }
+
+    fn is_full(&self) -> bool {
+        self.len(State::unpack(self.state.load(super::LOAD_ORDER))) == self.data.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl<T> Drop for WideQueue<T> {
+    fn drop(&mut self) {
+        let s = State::unpack(self.state.load(super::LOAD_ORDER));
+        assert_eq!(s.taken, 0);
+        let mut position = s.tail;
+        while position != s.head {
+            let (index, _) = self.slot(position);
+            unsafe { self.data[index].assume_init_drop() };
+            position = self.advance(position);
+        }
+    }
+}
+
+#[test]
+fn overflow() {
+    super::test_overflow::<WideQueue<i32>>();
+}
+
+#[test]
+fn smoke() {
+    super::test_smoke::<WideQueue<i32>>();
+}
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn transfer() {
+    use super::{NewQueue as _, SynQueue as _};
+    use std::{sync::Arc, thread};
+
+    const NUM_THREADS: usize = 4;
+    const NUM_ELEMENTS: usize = if cfg!(miri) { 1 << 5 } else { 1 << 12 };
+    let sq = Arc::new(WideQueue::<usize>::new(5));
+    let mut producers = Vec::new();
+    for _ in 0..NUM_THREADS {
+        let sq = Arc::clone(&sq);
+        producers.push(thread::spawn(move || {
+            for i in 0..NUM_ELEMENTS {
+                let mut value = i;
+                while let Err(v) = sq.push(value) {
+                    value = v;
+                    thread::yield_now();
+                }
+            }
+        }));
+    }
+    let mut consumers = Vec::new();
+    for _ in 0..NUM_THREADS {
+        let sq = Arc::clone(&sq);
+        consumers.push(thread::spawn(move || {
+            let mut sum = 0;
+            for _ in 0..NUM_ELEMENTS {
+                sum += loop {
+                    match sq.pop() {
+                        Some(value) => break value,
+                        None => thread::yield_now(),
+                    }
+                };
+            }
+            sum
+        }));
+    }
+    for handle in producers {
+        handle.join().unwrap();
+    }
+    let total: usize = consumers.into_iter().map(|h| h.join().unwrap()).sum();
+    assert_eq!(total, NUM_THREADS * NUM_ELEMENTS * (NUM_ELEMENTS - 1) / 2);
+    assert!(sq.is_empty());
+}
+
+#[cfg(not(any(feature = "loom", feature = "shuttle")))]
+#[test]
+fn linearizable() {
+    super::lincheck::check_mpmc::<WideQueue<u32>>();
+}
+
+#[test]
+fn differential() {
+    super::test_differential::<WideQueue<u32>>();
+}